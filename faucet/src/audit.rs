@@ -0,0 +1,333 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Append-only audit log of faucet requests.
+//!
+//! Every time a faucet request is received or changes state, an [`AuditEntry`] is appended to a
+//! JSON lines file. Unlike the state file, the log is never rewritten, so it records who got what
+//! and when for as long as the file is kept.
+use crate::{Asset, RequestId, RequestState, RequestStatus};
+use anyhow::Result;
+use async_std::{
+    channel::{Receiver, Sender, TrySendError},
+    fs::{File, OpenOptions},
+    io::{self, prelude::*, BufReader},
+    path::Path,
+    task::sleep,
+};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The maximum number of entries waiting to be written to the audit log.
+///
+/// If the log can't be written for a long time, further entries are dropped instead of using up
+/// all memory.
+pub(crate) const AUDIT_QUEUE_LEN: usize = 10_000;
+
+/// How long to wait before retrying after the audit log could not be written.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The frontend which received a faucet request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Source {
+    Web,
+    Discord,
+    /// Requests made directly through the faucet, e.g. in tests.
+    #[default]
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    pub chain: String,
+    pub request_id: RequestId,
+    pub source: Source,
+    pub recipient: Address,
+    pub asset: Asset,
+    /// The amount granted, once the transfer was sent.
+    pub amount: Option<U256>,
+    pub sender: Option<Address>,
+    pub tx_hash: Option<H256>,
+    pub state: RequestState,
+}
+
+/// Which audit log entries to return.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditFilter {
+    /// Only entries with this recipient or sender.
+    pub address: Option<Address>,
+    /// Only entries at or after this unix timestamp.
+    pub from: Option<u64>,
+    /// Only entries at or before this unix timestamp.
+    pub to: Option<u64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.address.map_or(true, |address| {
+            entry.recipient == address || entry.sender == Some(address)
+        }) && self.from.map_or(true, |from| entry.timestamp >= from)
+            && self.to.map_or(true, |to| entry.timestamp <= to)
+    }
+}
+
+/// Records the state changes of the requests of one chain.
+#[derive(Clone, Debug)]
+pub(crate) struct AuditSink {
+    chain: String,
+    entries: Sender<AuditEntry>,
+}
+
+impl AuditSink {
+    pub fn new(chain: String, entries: Sender<AuditEntry>) -> Self {
+        Self { chain, entries }
+    }
+
+    pub fn record(&self, status: &RequestStatus) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            chain: self.chain.clone(),
+            request_id: status.id,
+            source: status.source,
+            recipient: status.to,
+            asset: status.asset.clone(),
+            amount: status.amount,
+            sender: status.sender,
+            tx_hash: status.tx_hash,
+            state: status.state,
+        };
+        match self.entries.try_send(entry) {
+            Ok(()) => {}
+            Err(TrySendError::Full(entry)) => tracing::error!(
+                "Dropping audit log entry {entry:?}, {} entries are waiting to be written",
+                self.entries.len()
+            ),
+            Err(TrySendError::Closed(entry)) => {
+                tracing::error!("Dropping audit log entry {entry:?}, the writer is gone")
+            }
+        }
+    }
+}
+
+/// Append the entries received on `entries` to the audit log at `path`.
+///
+/// If the log can't be written, e.g. because the disk is full or the file was removed, the file is
+/// reopened and the entry retried until it succeeds, so no entries are lost while the problem is
+/// fixed.
+pub async fn write_audit_log(path: impl AsRef<Path>, entries: Receiver<AuditEntry>) -> Result<()> {
+    let path = path.as_ref();
+    let mut file = None;
+    // Whether a failed write may have left a partial line behind.
+    let mut partial = false;
+    while let Ok(entry) = entries.recv().await {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        loop {
+            // Start a new line after a failed write, so the partial line does not swallow the
+            // retried entry.
+            let result = if partial {
+                append(&mut file, path, &[b"\n", line.as_slice()].concat()).await
+            } else {
+                append(&mut file, path, &line).await
+            };
+            match result {
+                Ok(()) => {
+                    partial = false;
+                    break;
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Failed to write audit log {path:?}, retrying in {RETRY_INTERVAL:?}: {err}"
+                    );
+                    file = None;
+                    partial = true;
+                    sleep(RETRY_INTERVAL).await;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn append(file: &mut Option<File>, path: &Path, line: &[u8]) -> io::Result<()> {
+    if file.is_none() {
+        *file = Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        );
+    }
+    if let Some(file) = file {
+        file.write_all(line).await?;
+        file.flush().await?;
+    }
+    Ok(())
+}
+
+/// Read the entries of the audit log at `path` which match `filter`, oldest first.
+pub async fn read_audit_log(
+    path: impl AsRef<Path>,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEntry>> {
+    let path = path.as_ref();
+    if !path.exists().await {
+        return Ok(vec![]);
+    }
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut entries = vec![];
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(entry) if filter.matches(&entry) => entries.push(entry),
+            Ok(_) => {}
+            // A crash while writing can leave a partial last line behind.
+            Err(err) => tracing::warn!("Skipping invalid audit log entry {line:?}: {err}"),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::{channel::bounded, fs::create_dir, task::spawn};
+
+    fn status(id: RequestId, to: Address, state: RequestState) -> RequestStatus {
+        RequestStatus {
+            state,
+            source: Source::Web,
+            ..RequestStatus::new(id, to, Asset::Native)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_audit_log() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("audit.jsonl");
+        let alice = Address::random();
+        let bob = Address::random();
+
+        let (sender, receiver) = bounded(AUDIT_QUEUE_LEN);
+        let sink = AuditSink::new("l2".to_string(), sender);
+        sink.record(&status(0, alice, RequestState::Queued));
+        sink.record(&status(1, bob, RequestState::Queued));
+        sink.record(&RequestStatus {
+            amount: Some(1.into()),
+            sender: Some(bob),
+            ..status(0, alice, RequestState::Confirmed)
+        });
+        drop(sink);
+        write_audit_log(&path, receiver).await?;
+
+        let all = read_audit_log(&path, &AuditFilter::default()).await?;
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].chain, "l2");
+        assert_eq!(all[0].source, Source::Web);
+        assert_eq!(all[2].state, RequestState::Confirmed);
+        assert_eq!(all[2].amount, Some(1.into()));
+
+        // Entries match their recipient or sender.
+        let filter = AuditFilter {
+            address: Some(alice),
+            ..Default::default()
+        };
+        let ids: Vec<_> = read_audit_log(&path, &filter)
+            .await?
+            .iter()
+            .map(|entry| entry.request_id)
+            .collect();
+        assert_eq!(ids, [0, 0]);
+        let filter = AuditFilter {
+            address: Some(bob),
+            ..Default::default()
+        };
+        assert_eq!(read_audit_log(&path, &filter).await?.len(), 2);
+
+        // Entries are filtered by time.
+        let filter = AuditFilter {
+            to: Some(all[0].timestamp - 1),
+            ..Default::default()
+        };
+        assert!(read_audit_log(&path, &filter).await?.is_empty());
+        let filter = AuditFilter {
+            from: Some(all[0].timestamp),
+            to: Some(all[2].timestamp),
+            ..Default::default()
+        };
+        assert_eq!(read_audit_log(&path, &filter).await?, all);
+
+        // The log is appended to, and partial lines are skipped.
+        let mut file = OpenOptions::new().append(true).open(&path).await?;
+        file.write_all(b"{\"timestamp\":").await?;
+        file.flush().await?;
+        assert_eq!(read_audit_log(&path, &AuditFilter::default()).await?, all);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_queue_bound() {
+        let alice = Address::random();
+
+        // Nothing is written, so entries beyond the capacity of the queue are
+        // dropped instead of queued without bound.
+        let (sender, receiver) = bounded(2);
+        let sink = AuditSink::new("l2".to_string(), sender);
+        for id in 0..3 {
+            sink.record(&status(id, alice, RequestState::Queued));
+        }
+        drop(sink);
+        let mut ids = vec![];
+        while let Ok(entry) = receiver.try_recv() {
+            ids.push(entry.request_id);
+        }
+        assert_eq!(ids, [0, 1]);
+    }
+
+    #[async_std::test]
+    async fn test_audit_log_retry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let logs = dir.path().join("logs");
+        let path = logs.join("audit.jsonl");
+        let alice = Address::random();
+
+        // The log can't be written until its directory exists.
+        let (sender, receiver) = bounded(AUDIT_QUEUE_LEN);
+        let sink = AuditSink::new("l2".to_string(), sender);
+        sink.record(&status(0, alice, RequestState::Queued));
+        let writer = spawn(write_audit_log(path.clone(), receiver.clone()));
+        while !receiver.is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        // The entry the writer is stuck on is written once the problem is
+        // fixed, followed by the entries queued in the meantime.
+        sink.record(&status(1, alice, RequestState::Queued));
+        create_dir(&logs).await?;
+        drop(sink);
+        writer.await?;
+        let ids: Vec<_> = read_audit_log(&path, &AuditFilter::default())
+            .await?
+            .iter()
+            .map(|entry| entry.request_id)
+            .collect();
+        assert_eq!(ids, [0, 1]);
+
+        Ok(())
+    }
+}
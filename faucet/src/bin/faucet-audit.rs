@@ -0,0 +1,53 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use anyhow::Result;
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::Parser;
+use ethers::types::Address;
+use faucet::{read_audit_log, AuditFilter};
+use std::path::PathBuf;
+
+/// Query the audit log of the faucet.
+///
+/// Prints the matching entries as one JSON object per line, oldest first.
+#[derive(Parser)]
+pub struct Options {
+    /// The audit log to query.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_AUDIT_LOG")]
+    pub audit_log: PathBuf,
+
+    /// Only show entries whose recipient or sender is this address.
+    #[arg(long)]
+    pub address: Option<Address>,
+
+    /// Only show entries at or after this unix timestamp.
+    #[arg(long)]
+    pub from: Option<u64>,
+
+    /// Only show entries at or before this unix timestamp.
+    #[arg(long)]
+    pub to: Option<u64>,
+}
+
+#[async_std::main]
+async fn main() -> Result<()> {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    let filter = AuditFilter {
+        address: opt.address,
+        from: opt.from,
+        to: opt.to,
+    };
+    let entries = read_audit_log(&opt.audit_log, &filter).await?;
+    for entry in entries {
+        println!("{}", serde_json::to_string(&entry)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Granting L2 funds through the bridge.
+//!
+//! In bridge mode the faucet runs against the L1. Instead of transferring the native asset to the
+//! recipient, it deposits it into the `PolygonZkEVMBridge` with `bridgeAsset`, addressed to the
+//! recipient on the L2. This takes the same path as users moving funds to the L2, so the faucet
+//! doubles as a smoke test of the bridge.
+//!
+//! The deposit count of the `BridgeEvent` identifies the deposit. If an L2 node is configured, the
+//! faucet keeps checking each deposit until it is claimed on the L2:
+//!
+//! * A deposit is claimable once the L2 knows a global exit root which includes it. Every L1 global
+//!   exit root from the deposit on includes it, so the faucet checks the global exit root right after
+//!   the deposit and the latest one.
+//! * A deposit is claimed once the L2 bridge marks its deposit count as claimed, usually by the
+//!   auto-claim service.
+use crate::Options;
+use anyhow::{Context, Result};
+use ethers::{
+    contract::parse_log,
+    providers::{Http, Provider},
+    types::{Address, Bytes, TransactionReceipt, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use zkevm_contract_bindings::{
+    polygon_zk_evm_bridge::{BridgeEventFilter, PolygonZkEVMBridge},
+    polygon_zk_evm_global_exit_root::PolygonZkEVMGlobalExitRoot,
+    polygon_zk_evm_global_exit_root_l2::PolygonZkEVMGlobalExitRootL2,
+};
+
+/// How often the state of unclaimed deposits is checked.
+pub const DEPOSIT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepositState {
+    /// The deposit was made on the L1, but the L2 can't verify it yet.
+    Pending,
+    /// The L2 knows a global exit root including the deposit, so it can be claimed.
+    Claimable,
+    /// The deposit was claimed on the L2.
+    Claimed,
+}
+
+/// A bridge deposit made to grant a faucet request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    /// The deposit count of the `BridgeEvent`, which is the index of the claim on the L2.
+    pub deposit_count: u32,
+    /// The L1 global exit root right after the deposit.
+    pub global_exit_root: H256,
+    pub state: DepositState,
+}
+
+#[derive(Clone, Debug)]
+struct L2Bridge {
+    bridge: PolygonZkEVMBridge<Provider<Http>>,
+    exit_roots: PolygonZkEVMGlobalExitRootL2<Provider<Http>>,
+}
+
+/// The bridge used to grant funds in bridge mode.
+#[derive(Clone, Debug)]
+pub struct Bridge {
+    l1: PolygonZkEVMBridge<Provider<Http>>,
+    l1_exit_roots: PolygonZkEVMGlobalExitRoot<Provider<Http>>,
+    network_id: u32,
+    /// Used to track deposits, if configured.
+    l2: Option<L2Bridge>,
+}
+
+impl Bridge {
+    /// The bridge of a faucet, if bridge mode is enabled.
+    pub async fn new(options: &Options, provider: &Provider<Http>) -> Result<Option<Self>> {
+        let Some(address) = options.bridge_address else {
+            return Ok(None);
+        };
+        let provider = Arc::new(provider.clone());
+        let l1 = PolygonZkEVMBridge::new(address, provider.clone());
+        let l1_exit_roots = PolygonZkEVMGlobalExitRoot::new(
+            l1.global_exit_root_manager()
+                .call()
+                .await
+                .context("failed to query the L1 bridge")?,
+            provider,
+        );
+
+        let l2 = match &options.bridge_l2_provider_url {
+            Some(url) => {
+                let provider = Arc::new(Provider::<Http>::try_from(url.to_string())?);
+                let bridge = PolygonZkEVMBridge::new(
+                    options.bridge_l2_address.unwrap_or(address),
+                    provider.clone(),
+                );
+                let exit_roots = PolygonZkEVMGlobalExitRootL2::new(
+                    bridge
+                        .global_exit_root_manager()
+                        .call()
+                        .await
+                        .context("failed to query the L2 bridge")?,
+                    provider,
+                );
+                Some(L2Bridge { bridge, exit_roots })
+            }
+            None => None,
+        };
+
+        tracing::info!(
+            "Granting funds through the bridge {address:?} to network {}",
+            options.bridge_network_id
+        );
+        Ok(Some(Self {
+            l1,
+            l1_exit_roots,
+            network_id: options.bridge_network_id,
+            l2,
+        }))
+    }
+
+    /// The address of the bridge on the L1.
+    pub fn address(&self) -> Address {
+        self.l1.address()
+    }
+
+    /// Whether deposits are tracked on the L2.
+    pub fn tracks_claims(&self) -> bool {
+        self.l2.is_some()
+    }
+
+    /// The calldata of a deposit of `amount` of the native asset for `to` on the L2.
+    ///
+    /// The deposit is sent with `amount` as value.
+    pub fn deposit_calldata(&self, to: Address, amount: U256) -> Option<Bytes> {
+        self.l1
+            .bridge_asset(Address::zero(), self.network_id, to, amount, Bytes::new())
+            .calldata()
+    }
+
+    /// The deposit made by the transaction with `receipt`, if any.
+    pub async fn deposit(&self, receipt: &TransactionReceipt) -> Result<Option<Deposit>> {
+        let Some(event) = receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == self.address())
+            .find_map(|log| parse_log::<BridgeEventFilter>(log.clone()).ok())
+        else {
+            return Ok(None);
+        };
+        let global_exit_root = self
+            .l1_exit_roots
+            .get_last_global_exit_root()
+            .call()
+            .await?;
+        Ok(Some(Deposit {
+            deposit_count: event.deposit_count,
+            global_exit_root: global_exit_root.into(),
+            state: DepositState::Pending,
+        }))
+    }
+
+    /// The current state of `deposit`.
+    pub async fn update(&self, deposit: Deposit) -> Result<Deposit> {
+        let Some(l2) = &self.l2 else {
+            return Ok(deposit);
+        };
+        let state = if l2
+            .bridge
+            .is_claimed(deposit.deposit_count.into())
+            .call()
+            .await?
+        {
+            DepositState::Claimed
+        } else if deposit.state == DepositState::Claimable
+            || self.is_known(l2, deposit.global_exit_root).await?
+            || self
+                .is_known(
+                    l2,
+                    self.l1_exit_roots
+                        .get_last_global_exit_root()
+                        .call()
+                        .await?
+                        .into(),
+                )
+                .await?
+        {
+            DepositState::Claimable
+        } else {
+            DepositState::Pending
+        };
+        Ok(Deposit { state, ..deposit })
+    }
+
+    /// Whether the L2 knows the global exit root `root`.
+    async fn is_known(&self, l2: &L2Bridge, root: H256) -> Result<bool> {
+        let timestamp = l2
+            .exit_roots
+            .global_exit_root_map(root.into())
+            .call()
+            .await?;
+        Ok(!timestamp.is_zero())
+    }
+}
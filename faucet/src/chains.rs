@@ -0,0 +1,305 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Serving several chains from one faucet.
+//!
+//! The chain configured by the command line options is the primary chain. Additional chains are
+//! read from a TOML file with one `[[chain]]` table per chain:
+//!
+//! ```toml
+//! [[chain]]
+//! name = "zkevm-1"
+//! provider_url_http = "http://localhost:8126"
+//! provider_url_ws = "ws://localhost:8133"
+//! # Optional, the values of the primary chain are used by default.
+//! grant_amount_ethers = "10"
+//! num_clients = 5
+//! tokens = ["MATIC:0x5FbDB2315678afecb367f032d93F642f64180aa3:100"]
+//! # Optional, grant through a bridge as with the `--bridge-*` options. The bridge options of the
+//! # primary chain are not inherited.
+//! bridge_address = "0x2279B7A0a67DB372996a5FaB50D91eAA73d2eBe6"
+//! bridge_network_id = 1
+//! bridge_l2_provider_url = "http://localhost:8127"
+//! bridge_l2_address = "0x2279B7A0a67DB372996a5FaB50D91eAA73d2eBe6"
+//! ```
+//!
+//! All chains use the same mnemonic or keystore directory. Each chain gets its own range of wallet
+//! indices, so that no wallet is shared between chains. By default the range of a chain starts
+//! after the active and retired wallets of the previous chain, or after its `max_clients` wallets
+//! if that is more.
+use crate::{Options, TokenConfig};
+use anyhow::{ensure, Context, Result};
+use ethers::{types::Address, utils::parse_ether};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Names which would be ambiguous in the routes of the web API and the admin API.
+const RESERVED_NAMES: [&str; 16] = [
+    "request",
+    "status",
+    "info",
+    "chains",
+    "challenge",
+    "pause",
+    "resume",
+    "grant",
+    "block",
+    "unblock",
+    "allow",
+    "disallow",
+    "drain",
+    "resize",
+    "settings",
+    "audit",
+];
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainsConfig {
+    #[serde(default)]
+    chain: Vec<ChainConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainConfig {
+    name: String,
+    provider_url_http: String,
+    provider_url_ws: String,
+    grant_amount_ethers: Option<String>,
+    num_clients: Option<usize>,
+    #[serde(default)]
+    tokens: Vec<String>,
+    /// The first HD wallet index of the chain's wallets.
+    client_index_offset: Option<usize>,
+    bridge_address: Option<Address>,
+    bridge_network_id: Option<u32>,
+    bridge_l2_provider_url: Option<String>,
+    bridge_l2_address: Option<Address>,
+}
+
+impl Options {
+    /// The options of every chain served by the faucet, primary chain first.
+    pub fn chains(&self) -> Result<Vec<Options>> {
+        let config = match &self.chains_config {
+            Some(path) => {
+                let toml = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                toml::from_str(&toml)
+                    .with_context(|| format!("failed to parse {}", path.display()))?
+            }
+            None => ChainsConfig::default(),
+        };
+        self.with_chains(config)
+    }
+
+    fn with_chains(&self, config: ChainsConfig) -> Result<Vec<Options>> {
+        let mut chains = vec![self.clone()];
+        for chain in config.chain {
+            let previous = chains.last().unwrap();
+            let mut options = Options {
+                chain_name: chain.name.clone(),
+                client_index_offset: chain.client_index_offset.unwrap_or(
+                    previous.client_index_offset
+                        + previous
+                            .num_clients
+                            .max(previous.max_clients.unwrap_or_default())
+                        + previous.retired_clients,
+                ),
+                provider_url_http: chain.provider_url_http.parse().with_context(|| {
+                    format!("invalid provider_url_http for chain {}", chain.name)
+                })?,
+                provider_url_ws: chain
+                    .provider_url_ws
+                    .parse()
+                    .with_context(|| format!("invalid provider_url_ws for chain {}", chain.name))?,
+                num_clients: chain.num_clients.unwrap_or(self.num_clients),
+                tokens: chain
+                    .tokens
+                    .iter()
+                    .map(|token| token.parse())
+                    .collect::<Result<Vec<TokenConfig>>>()?,
+                state_file: self
+                    .state_file
+                    .as_deref()
+                    .map(|path| chain_state_file(path, &chain.name)),
+                audit_log: self
+                    .audit_log
+                    .as_deref()
+                    .map(|path| chain_state_file(path, &chain.name)),
+                // Every chain has its own bridge, if any.
+                bridge_address: chain.bridge_address,
+                bridge_network_id: chain.bridge_network_id.unwrap_or(1),
+                bridge_l2_provider_url: chain
+                    .bridge_l2_provider_url
+                    .as_deref()
+                    .map(|url| url.parse())
+                    .transpose()
+                    .with_context(|| {
+                        format!("invalid bridge_l2_provider_url for chain {}", chain.name)
+                    })?,
+                bridge_l2_address: chain.bridge_l2_address,
+                ..self.clone()
+            };
+            ensure!(
+                options.bridge_address.is_some() || options.bridge_l2_provider_url.is_none(),
+                "bridge_l2_provider_url requires bridge_address for chain {}",
+                chain.name
+            );
+            if let Some(amount) = &chain.grant_amount_ethers {
+                options.faucet_grant_amount = parse_ether(amount)
+                    .with_context(|| format!("invalid grant amount for chain {}", chain.name))?;
+            }
+            chains.push(options);
+        }
+
+        let mut names = HashSet::new();
+        for chain in &chains {
+            ensure!(
+                !chain.chain_name.is_empty() && !chain.chain_name.contains('/'),
+                "invalid chain name {:?}",
+                chain.chain_name
+            );
+            ensure!(
+                !RESERVED_NAMES.contains(&chain.chain_name.as_str()),
+                "chain name {} is reserved",
+                chain.chain_name
+            );
+            ensure!(
+                names.insert(&chain.chain_name),
+                "duplicate chain name {}",
+                chain.chain_name
+            );
+        }
+        Ok(chains)
+    }
+}
+
+/// The state file or audit log of an additional chain, next to the one of the primary chain.
+fn chain_state_file(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{stem}-{name}.{}", ext.to_string_lossy())),
+        None => path.with_file_name(format!("{stem}-{name}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use url::Url;
+
+    #[test]
+    fn test_chains_config() {
+        let options = Options {
+            num_clients: 3,
+            retired_clients: 2,
+            state_file: Some(PathBuf::from("/data/faucet.json")),
+            bridge_address: Some(Address::random()),
+            bridge_network_id: 2,
+            bridge_l2_provider_url: Some("http://localhost:8200".parse().unwrap()),
+            bridge_l2_address: Some(Address::random()),
+            ..Default::default()
+        };
+        let config = toml::from_str(
+            r#"
+            [[chain]]
+            name = "zkevm-1"
+            provider_url_http = "http://localhost:8126"
+            provider_url_ws = "ws://localhost:8133"
+            grant_amount_ethers = "10"
+
+            [[chain]]
+            name = "zkevm-2"
+            provider_url_http = "http://localhost:8127"
+            provider_url_ws = "ws://localhost:8134"
+            num_clients = 1
+            client_index_offset = 100
+            bridge_address = "0x2279b7a0a67db372996a5fab50d91eaa73d2ebe6"
+            bridge_l2_provider_url = "http://localhost:8201"
+            "#,
+        )
+        .unwrap();
+        let chains = options.with_chains(config).unwrap();
+        assert_eq!(chains.len(), 3);
+
+        assert_eq!(chains[0].chain_name, "default");
+        assert_eq!(chains[0].client_index_offset, 0);
+
+        assert_eq!(chains[1].chain_name, "zkevm-1");
+        assert_eq!(chains[1].client_index_offset, 5);
+        assert_eq!(chains[1].num_clients, 3);
+        assert_eq!(chains[1].faucet_grant_amount, parse_ether("10").unwrap());
+        assert_eq!(
+            chains[1].provider_url_http.as_str(),
+            "http://localhost:8126/"
+        );
+        assert_eq!(
+            chains[1].state_file,
+            Some(PathBuf::from("/data/faucet-zkevm-1.json"))
+        );
+
+        assert_eq!(chains[2].client_index_offset, 100);
+        assert_eq!(chains[2].num_clients, 1);
+        assert_eq!(chains[2].faucet_grant_amount, options.faucet_grant_amount);
+
+        // The bridge of the primary chain is not inherited.
+        assert_eq!(chains[1].bridge_address, None);
+        assert_eq!(chains[1].bridge_network_id, 1);
+        assert_eq!(chains[1].bridge_l2_provider_url, None);
+        assert_eq!(chains[1].bridge_l2_address, None);
+        assert_eq!(
+            chains[2].bridge_address,
+            Some(
+                "0x2279b7a0a67db372996a5fab50d91eaa73d2ebe6"
+                    .parse()
+                    .unwrap()
+            )
+        );
+        assert_eq!(chains[2].bridge_network_id, 1);
+        assert_eq!(
+            chains[2].bridge_l2_provider_url.as_ref().map(Url::as_str),
+            Some("http://localhost:8201/")
+        );
+        assert_eq!(chains[2].bridge_l2_address, None);
+    }
+
+    #[test]
+    fn test_chain_names() {
+        let options = Options::default();
+        let chain = |name: &str| ChainConfig {
+            name: name.to_string(),
+            provider_url_http: "http://localhost:8545".to_string(),
+            provider_url_ws: "ws://localhost:8545".to_string(),
+            grant_amount_ethers: None,
+            num_clients: None,
+            tokens: vec![],
+            client_index_offset: None,
+            bridge_address: None,
+            bridge_network_id: None,
+            bridge_l2_provider_url: None,
+            bridge_l2_address: None,
+        };
+        for chains in [
+            vec![chain("default")],
+            vec![chain("l2"), chain("l2")],
+            vec![chain("status")],
+            vec![chain("a/b")],
+        ] {
+            assert!(options.with_chains(ChainsConfig { chain: chains }).is_err());
+        }
+        assert!(options
+            .with_chains(ChainsConfig {
+                chain: vec![chain("l1"), chain("l2")]
+            })
+            .is_ok());
+    }
+}
@@ -0,0 +1,660 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The chat-bot commands of the faucet.
+//!
+//! The commands are independent of the chat service. A [ChatFrontend] represents one command
+//! received by a chat service: it parses the command into a [ChatCommand] and delivers the reply,
+//! which is edited once a requested transfer is confirmed. The commands are:
+//!   - `/faucet <address> [asset] [chain]` to request funds.
+//!   - `/faucet-status` to show the status of the user's last request.
+//!   - `/balance <address> [chain]` to show the balance of an address.
+//!   - `/faucet-info` to show the state of the faucet wallets, for admins only.
+//!
+//! Discord is the frontend used in production. [LocalChat] runs commands in process, so the
+//! command logic can be exercised without a chat service.
+use crate::{
+    Asset, ChainState, DepositState, FaucetError, RateLimitKey, RequestId, RequestState,
+    RequestStatus, Source, WalletState, WebState,
+};
+use anyhow::{ensure, Result};
+use async_std::{
+    sync::Mutex,
+    task::{sleep, spawn},
+};
+use async_trait::async_trait;
+use ethers::{
+    types::{Address, U256},
+    utils::format_ether,
+};
+use regex::Regex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// How long to wait for a transfer to be confirmed before giving up on editing
+/// the reply. Discord only allows editing replies for 15 minutes.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(14 * 60);
+
+/// How often to check whether a transfer was confirmed.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The parameters of each command, in the order they can be given positionally.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("faucet", &["address", "asset", "chain"]),
+    ("faucet-status", &[]),
+    ("balance", &["address", "chain"]),
+    ("faucet-info", &[]),
+];
+
+/// An error handling a command, shown to the user.
+#[derive(Clone, Debug, Error)]
+pub enum CommandError {
+    #[error("Unknown command {0}.")]
+    UnknownCommand(String),
+    #[error("Unexpected argument {0}.")]
+    UnexpectedArgument(String),
+    #[error("Missing option {0}.")]
+    MissingOption(&'static str),
+    #[error("No address found!")]
+    NoAddress,
+    #[error("You are not allowed to use this command here.")]
+    NotAllowed,
+    #[error("Unknown chain {chain}, the faucet serves {chains}.")]
+    UnknownChain { chain: String, chains: String },
+    #[error("Unknown asset {0}, the faucet does not grant it.")]
+    UnknownAsset(String),
+    #[error("Slow down! Funds were sent recently, please try again in {wait}.")]
+    RateLimited { wait: String },
+    #[error("The faucet is paused, please try again later.")]
+    Paused,
+    #[error("This address is not eligible for funds.")]
+    Blocked,
+    #[error("The faucet is busy, please try again later.")]
+    QueueFull,
+    #[error("You have not requested funds yet.")]
+    NoRequest,
+    #[error("Internal Error: {0}")]
+    Internal(String),
+}
+
+impl From<FaucetError> for CommandError {
+    fn from(err: FaucetError) -> Self {
+        match err {
+            FaucetError::RateLimited { retry_after, .. } => Self::RateLimited {
+                wait: format_retry_after(retry_after),
+            },
+            FaucetError::UnknownAsset { asset, .. } => Self::UnknownAsset(asset),
+            FaucetError::Paused { .. } => Self::Paused,
+            FaucetError::Blocked { .. } => Self::Blocked,
+            FaucetError::QueueFull { .. } => Self::QueueFull,
+            err => {
+                tracing::error!("Failed to handle chat command: {err}");
+                Self::Internal(err.to_string())
+            }
+        }
+    }
+}
+
+/// A command sent to the faucet bot.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatCommand {
+    /// The name of the command, without the leading `/`.
+    pub name: String,
+    /// The options given by name.
+    pub options: Vec<(String, String)>,
+    /// The id of the user who sent the command.
+    pub user: u64,
+    /// The roles of the user.
+    pub roles: Vec<u64>,
+    /// The channel the command was sent in.
+    pub channel: u64,
+}
+
+impl ChatCommand {
+    /// Parse a command written as text, like `/faucet 0x... chain=l2`.
+    ///
+    /// Options are given as `name=value` or positionally, in the order of the parameters of the
+    /// command.
+    pub fn parse(text: &str) -> Result<Self, CommandError> {
+        let mut words = text.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let (name, parameters) = name
+            .strip_prefix('/')
+            .and_then(|name| COMMANDS.iter().find(|(command, _)| *command == name))
+            .ok_or_else(|| CommandError::UnknownCommand(name.to_string()))?;
+
+        let mut options = vec![];
+        let mut positional = parameters.iter();
+        for word in words {
+            let (option, value) = match word.split_once('=') {
+                Some((option, value)) if parameters.contains(&option) => (option, value),
+                _ => (
+                    *positional
+                        .next()
+                        .ok_or_else(|| CommandError::UnexpectedArgument(word.to_string()))?,
+                    word,
+                ),
+            };
+            options.push((option.to_string(), value.to_string()));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            options,
+            ..Default::default()
+        })
+    }
+
+    /// The value of the option `name`, if given.
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The address given in the `address` option.
+    pub fn address(&self) -> Result<Address, CommandError> {
+        find_address(
+            self.option("address")
+                .ok_or(CommandError::MissingOption("address"))?,
+        )
+    }
+
+    /// Whether the command is restricted to admins.
+    pub fn is_admin(&self) -> bool {
+        self.name == "faucet-info"
+    }
+}
+
+/// A command received by a chat service.
+#[async_trait]
+pub trait ChatFrontend: Clone + Send + Sync + 'static {
+    /// The command sent by the user.
+    fn command(&self) -> Result<ChatCommand, CommandError>;
+
+    /// The frontend recorded for faucet requests.
+    fn source(&self) -> Source;
+
+    /// Reply to the command.
+    ///
+    /// An ephemeral reply is only shown to the user who sent the command.
+    async fn reply(&self, content: String, ephemeral: bool) -> Result<()>;
+
+    /// Replace the content of the reply.
+    async fn edit_reply(&self, content: String) -> Result<()>;
+}
+
+/// The reply to a command.
+struct Reply {
+    content: String,
+    /// A faucet request to follow, editing the reply once it is confirmed.
+    follow: Option<(ChainState, RequestId)>,
+}
+
+impl From<String> for Reply {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            follow: None,
+        }
+    }
+}
+
+impl WebState {
+    /// Handle the command received by `frontend` and reply to it.
+    pub(crate) async fn handle_chat<F: ChatFrontend>(&self, frontend: F) {
+        let (reply, ephemeral) = match frontend.command() {
+            Ok(command) => {
+                tracing::info!("Received chat command: {:?}", command);
+                let reply = self
+                    .handle_command(&command, frontend.source())
+                    .await
+                    .unwrap_or_else(|err| err.to_string().into());
+                // Only show admin information to the admin who asked.
+                (reply, command.is_admin())
+            }
+            Err(err) => (err.to_string().into(), false),
+        };
+
+        if let Err(err) = frontend.reply(reply.content, ephemeral).await {
+            tracing::error!("Cannot respond to chat command: {err}");
+            return;
+        }
+
+        if let Some((chain, id)) = reply.follow {
+            spawn(follow_request(frontend, chain, id));
+        }
+    }
+
+    async fn handle_command(
+        &self,
+        command: &ChatCommand,
+        source: Source,
+    ) -> Result<Reply, CommandError> {
+        self.check_allowed(command)?;
+        match command.name.as_str() {
+            "faucet" => self.handle_faucet_request(command, source).await,
+            "faucet-status" => Ok(self.handle_status(command).await?.into()),
+            "balance" => Ok(self.handle_balance(command).await?.into()),
+            "faucet-info" => Ok(self.handle_info().await?.into()),
+            name => Err(CommandError::UnknownCommand(name.to_string())),
+        }
+    }
+
+    /// Check the role and channel restrictions of the faucet.
+    ///
+    /// Admin commands always require one of the admin roles, other commands
+    /// only require one of the configured roles, if any.
+    fn check_allowed(&self, command: &ChatCommand) -> Result<(), CommandError> {
+        let options = self.chain(None)?.options();
+        let channels = &options.discord_channels;
+        if !channels.is_empty() && !channels.contains(&command.channel) {
+            return Err(CommandError::NotAllowed);
+        }
+        let admin = command.is_admin();
+        let roles = if admin {
+            &options.discord_admin_roles
+        } else {
+            &options.discord_roles
+        };
+        if (admin || !roles.is_empty()) && !command.roles.iter().any(|role| roles.contains(role)) {
+            return Err(CommandError::NotAllowed);
+        }
+        Ok(())
+    }
+
+    /// The chain named by the `chain` option, or the primary chain.
+    fn command_chain(&self, command: &ChatCommand) -> Result<&ChainState, CommandError> {
+        let name = command.option("chain");
+        self.chain(name).map_err(|_| CommandError::UnknownChain {
+            chain: name.unwrap_or_default().to_string(),
+            chains: self.chain_names().join(", "),
+        })
+    }
+
+    async fn handle_faucet_request(
+        &self,
+        command: &ChatCommand,
+        source: Source,
+    ) -> Result<Reply, CommandError> {
+        let address = command.address()?;
+        let chain = self.command_chain(command)?;
+        let keys = [
+            RateLimitKey::Address(address),
+            RateLimitKey::DiscordUser(command.user),
+        ];
+        let grant = chain.check_request(&keys).await?;
+
+        let asset = command
+            .option("asset")
+            .map(Asset::parse)
+            .unwrap_or_default();
+        let id = chain.request(grant, address, asset.clone(), source).await?;
+        self.last_requests
+            .lock()
+            .await
+            .insert(command.user, (chain.name().to_string(), id));
+        Ok(Reply {
+            content: format!(
+                "Sending {asset} to {address:?} on {} (request {id})",
+                chain.name()
+            ),
+            follow: Some((chain.clone(), id)),
+        })
+    }
+
+    async fn handle_status(&self, command: &ChatCommand) -> Result<String, CommandError> {
+        let (chain, id) = self
+            .last_requests
+            .lock()
+            .await
+            .get(&command.user)
+            .cloned()
+            .ok_or(CommandError::NoRequest)?;
+        let status = self.chain(Some(&chain))?.status(id).await?;
+        Ok(describe_status(&chain, &status))
+    }
+
+    async fn handle_balance(&self, command: &ChatCommand) -> Result<String, CommandError> {
+        let address = command.address()?;
+        let chain = self.command_chain(command)?;
+        let balance = chain.balance(address).await?;
+        Ok(format!(
+            "{address:?} has {} ETH on {}",
+            format_ether(balance),
+            chain.name()
+        ))
+    }
+
+    async fn handle_info(&self) -> Result<String, CommandError> {
+        let mut lines = vec![];
+        for name in self.chain_names() {
+            let info = self.chain(Some(&name))?.info().await?;
+            let total = info
+                .wallets
+                .iter()
+                .fold(U256::zero(), |total, wallet| total + wallet.balance);
+            let available = info
+                .wallets
+                .iter()
+                .filter(|wallet| wallet.state == WalletState::Available)
+                .count();
+            lines.push(format!(
+                "{name}: {} queued, {} inflight, {available}/{} wallets available with {} ETH in total, granting {} ETH",
+                info.queue_depth,
+                info.inflight,
+                info.wallets.len(),
+                format_ether(total),
+                format_ether(info.grant_amount),
+            ));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Edit the reply to a faucet command once the request is confirmed.
+async fn follow_request<F: ChatFrontend>(frontend: F, chain: ChainState, id: RequestId) {
+    let deadline = Instant::now() + CONFIRMATION_TIMEOUT;
+    while Instant::now() < deadline {
+        sleep(CONFIRMATION_POLL_INTERVAL).await;
+        let status = match chain.status(id).await {
+            Ok(status) => status,
+            Err(err) => {
+                tracing::warn!("Failed to follow faucet request {id}: {err}");
+                return;
+            }
+        };
+        if matches!(status.state, RequestState::Confirmed | RequestState::Failed) {
+            let content = describe_status(chain.name(), &status);
+            if let Err(err) = frontend.edit_reply(content).await {
+                tracing::error!("Cannot edit reply to faucet request {id}: {err}");
+            }
+            return;
+        }
+    }
+}
+
+/// Find an ethereum address in user input.
+fn find_address(input: &str) -> Result<Address, CommandError> {
+    let re =
+        Regex::new("0x[a-fA-F0-9]{40}").map_err(|err| CommandError::Internal(err.to_string()))?;
+    re.find(input)
+        .ok_or(CommandError::NoAddress)?
+        .as_str()
+        .parse()
+        .map_err(|_| CommandError::NoAddress)
+}
+
+/// Describe the status of a faucet request for a human reader.
+fn describe_status(chain: &str, status: &RequestStatus) -> String {
+    let state = match (status.state, status.queue_position) {
+        (RequestState::Queued, Some(position)) => {
+            format!("queued with {position} transfers ahead")
+        }
+        (RequestState::Queued, None) => "queued".to_string(),
+        (RequestState::Submitted, _) => "submitted".to_string(),
+        (RequestState::Confirmed, _) => "confirmed".to_string(),
+        (RequestState::Failed, _) => "failed".to_string(),
+    };
+    let mut description = format!(
+        "Request {} for {} to {:?} on {chain}: {state}",
+        status.id, status.asset, status.to
+    );
+    if let Some(tx_hash) = status.tx_hash {
+        description += &format!(", tx {tx_hash:?}");
+    }
+    if let Some(deposit) = status.deposit {
+        let state = match deposit.state {
+            DepositState::Pending => "pending",
+            DepositState::Claimable => "claimable",
+            DepositState::Claimed => "claimed",
+        };
+        description += &format!(", bridge deposit {} {state}", deposit.deposit_count);
+    }
+    description
+}
+
+/// Format a number of seconds for a human reader.
+fn format_retry_after(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs} seconds"),
+        60..=3599 => format!("{} minutes", (secs + 59) / 60),
+        _ => format!("{} hours", (secs + 3599) / 3600),
+    }
+}
+
+/// A chat frontend which runs a command given as text in process.
+///
+/// The reply and its edits are recorded, so they can be inspected.
+#[derive(Clone, Debug)]
+pub struct LocalChat {
+    text: String,
+    user: u64,
+    roles: Vec<u64>,
+    channel: u64,
+    /// The content of the reply, followed by the content of each edit.
+    replies: Arc<Mutex<Vec<String>>>,
+}
+
+impl LocalChat {
+    /// The command `text` sent by `user`.
+    pub fn new(user: u64, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            user,
+            roles: vec![],
+            channel: 0,
+            replies: Default::default(),
+        }
+    }
+
+    pub fn with_roles(mut self, roles: impl IntoIterator<Item = u64>) -> Self {
+        self.roles = roles.into_iter().collect();
+        self
+    }
+
+    pub fn with_channel(mut self, channel: u64) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// The current content of the reply, if any.
+    pub async fn reply_content(&self) -> Option<String> {
+        self.replies.lock().await.last().cloned()
+    }
+
+    /// How often the reply was edited.
+    pub async fn num_edits(&self) -> usize {
+        self.replies.lock().await.len().saturating_sub(1)
+    }
+}
+
+#[async_trait]
+impl ChatFrontend for LocalChat {
+    fn command(&self) -> Result<ChatCommand, CommandError> {
+        Ok(ChatCommand {
+            user: self.user,
+            roles: self.roles.clone(),
+            channel: self.channel,
+            ..ChatCommand::parse(&self.text)?
+        })
+    }
+
+    fn source(&self) -> Source {
+        Source::Unknown
+    }
+
+    async fn reply(&self, content: String, _ephemeral: bool) -> Result<()> {
+        let mut replies = self.replies.lock().await;
+        ensure!(replies.is_empty(), "cannot reply twice");
+        replies.push(content);
+        Ok(())
+    }
+
+    async fn edit_reply(&self, content: String) -> Result<()> {
+        let mut replies = self.replies.lock().await;
+        ensure!(!replies.is_empty(), "cannot edit a reply before sending it");
+        replies.push(content);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        web::test::{start_test_faucet, TestFaucet},
+        Deposit, Options,
+    };
+    use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+    use ethers::{types::H256, utils::parse_ether};
+
+    #[test]
+    fn test_find_address() {
+        let address = Address::random();
+        assert_eq!(
+            find_address(&format!("please send to {address:?}, thanks")).unwrap(),
+            address
+        );
+        assert!(matches!(
+            find_address("0x1234"),
+            Err(CommandError::NoAddress)
+        ));
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let address = Address::random();
+        let command = ChatCommand::parse(&format!("/faucet {address:?} chain=l2")).unwrap();
+        assert_eq!(command.name, "faucet");
+        assert_eq!(command.address().unwrap(), address);
+        assert_eq!(command.option("chain"), Some("l2"));
+        assert_eq!(command.option("asset"), None);
+
+        // Options are positional in the order of the parameters.
+        let command = ChatCommand::parse(&format!("/faucet {address:?} USDC l2")).unwrap();
+        assert_eq!(command.option("asset"), Some("USDC"));
+        assert_eq!(command.option("chain"), Some("l2"));
+
+        assert!(ChatCommand::parse("/faucet-info").unwrap().is_admin());
+        assert!(matches!(
+            ChatCommand::parse("/balance").unwrap().address(),
+            Err(CommandError::MissingOption("address"))
+        ));
+        assert!(matches!(
+            ChatCommand::parse("faucet"),
+            Err(CommandError::UnknownCommand(_))
+        ));
+        assert!(matches!(
+            ChatCommand::parse("/faucet-status now"),
+            Err(CommandError::UnexpectedArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_describe_status() {
+        let to = Address::random();
+        let mut status = RequestStatus {
+            queue_position: Some(2),
+            ..RequestStatus::new(7, to, Asset::Native)
+        };
+        assert_eq!(
+            describe_status("l2", &status),
+            format!("Request 7 for ETH to {to:?} on l2: queued with 2 transfers ahead")
+        );
+
+        let tx_hash = H256::random();
+        status.state = RequestState::Confirmed;
+        status.tx_hash = Some(tx_hash);
+        assert_eq!(
+            describe_status("l2", &status),
+            format!("Request 7 for ETH to {to:?} on l2: confirmed, tx {tx_hash:?}")
+        );
+
+        status.deposit = Some(Deposit {
+            deposit_count: 3,
+            global_exit_root: H256::random(),
+            state: DepositState::Claimable,
+        });
+        assert_eq!(
+            describe_status("l2", &status),
+            format!(
+                "Request 7 for ETH to {to:?} on l2: confirmed, tx {tx_hash:?}, bridge deposit 3 claimable"
+            )
+        );
+    }
+
+    /// Run the command of `chat` and return the reply.
+    async fn run(state: &WebState, chat: LocalChat) -> String {
+        state.handle_chat(chat.clone()).await;
+        chat.reply_content().await.unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_local_chat() -> anyhow::Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let TestFaucet { state, .. } = &start_test_faucet(Options {
+            num_clients: 2,
+            faucet_grant_amount: parse_ether(1).unwrap(),
+            discord_admin_roles: vec![42],
+            ..Default::default()
+        })
+        .await?;
+
+        let user = 1;
+        let address = Address::random();
+        assert_eq!(
+            run(state, LocalChat::new(user, "/faucet-status")).await,
+            CommandError::NoRequest.to_string()
+        );
+        assert_eq!(
+            run(state, LocalChat::new(user, "/faucet please")).await,
+            CommandError::NoAddress.to_string()
+        );
+        assert_eq!(
+            run(state, LocalChat::new(user, "/faucet-info")).await,
+            CommandError::NotAllowed.to_string()
+        );
+
+        // The reply to a request is edited once the transfer is confirmed.
+        let chat = LocalChat::new(user, format!("/faucet {address:?}"));
+        let reply = run(state, chat.clone()).await;
+        assert!(reply.starts_with(&format!("Sending ETH to {address:?}")));
+        let mut retries = 0;
+        while chat.num_edits().await == 0 {
+            assert!(retries < 30, "reply was not edited");
+            retries += 1;
+            sleep(Duration::from_secs(1)).await;
+        }
+        assert!(chat.reply_content().await.unwrap().contains("confirmed"));
+
+        assert!(run(state, LocalChat::new(user, "/faucet-status"))
+            .await
+            .contains("confirmed"));
+        assert_eq!(
+            run(state, LocalChat::new(user, format!("/balance {address:?}"))).await,
+            format!(
+                "{address:?} has 1.000000000000000000 ETH on {}",
+                state.chain_names()[0]
+            )
+        );
+        let info = run(state, LocalChat::new(user, "/faucet-info").with_roles([42])).await;
+        assert!(info.contains("/2 wallets available"), "{info}");
+
+        // Other users have their own last request.
+        assert_eq!(
+            run(state, LocalChat::new(user + 1, "/faucet-status")).await,
+            CommandError::NoRequest.to_string()
+        );
+        Ok(())
+    }
+}
@@ -7,24 +7,28 @@
 
 //! A discord event handler for the faucet.
 //!
+//! Registers the commands of the faucet bot as slash commands and handles them as a
+//! [ChatFrontend]. The commands can be restricted to users with certain roles and to certain
+//! channels.
+//!
 //! Suggestions for improvements:
 //!   - After starting up, process messages sent since last online.
 use crate::serve;
-use crate::WebState;
-use crate::{Faucet, Options};
+use crate::{ChatCommand, ChatFrontend, CommandError, Faucet, Options, Source, WebState};
+use anyhow::Result;
 use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
 use async_std::task::spawn;
 use clap::Parser;
-use ethers::types::Address;
-use regex::Regex;
 use serenity::{
     async_trait,
+    builder::CreateApplicationCommandOption,
+    http::Http,
     model::{
         gateway::Ready,
         prelude::{
             command::{Command, CommandOptionType},
             interaction::{
-                application_command::{CommandDataOption, CommandDataOptionValue},
+                application_command::{ApplicationCommandInteraction, CommandDataOptionValue},
                 Interaction, InteractionResponseType,
             },
         },
@@ -32,64 +36,92 @@ use serenity::{
     prelude::{Context, EventHandler, GatewayIntents},
     Client,
 };
-use std::io;
-
-impl WebState {
-    async fn handle_faucet_request(&self, options: &[CommandDataOption]) -> String {
-        let option = options
-            .get(0)
-            .expect("Expected address option")
-            .resolved
-            .as_ref()
-            .expect("Expected user object");
-        match option {
-            CommandDataOptionValue::String(input) => {
-                // Try to find an ethereum address in the message body.
-                let re = Regex::new("0x[a-fA-F0-9]{40}").unwrap();
-
-                if let Some(matched) = re.captures(input) {
-                    let address = matched
-                        .get(0)
-                        .expect("At least one match")
-                        .as_str()
-                        .parse::<Address>()
-                        .expect("Address can be parsed after matching regex");
-                    if let Err(err) = self.request(address).await {
-                        tracing::error!("Failed make faucet request for {address:?}: {}", err);
-                        format!("Internal Error: Failed to send funds to {address:?}")
-                    } else {
-                        format!("Sending funds to {address:?}")
+use std::{io, sync::Arc};
+
+/// A slash command received from Discord.
+#[derive(Clone)]
+struct DiscordCommand {
+    http: Arc<Http>,
+    interaction: ApplicationCommandInteraction,
+}
+
+#[async_trait]
+impl ChatFrontend for DiscordCommand {
+    fn command(&self) -> Result<ChatCommand, CommandError> {
+        let interaction = &self.interaction;
+        Ok(ChatCommand {
+            name: interaction.data.name.clone(),
+            options: interaction
+                .data
+                .options
+                .iter()
+                .filter_map(|option| match &option.resolved {
+                    Some(CommandDataOptionValue::String(value)) => {
+                        Some((option.name.clone(), value.clone()))
                     }
-                } else {
-                    "No address found!".to_string()
-                }
-            }
-            _ => unreachable!(),
-        }
+                    _ => None,
+                })
+                .collect(),
+            user: interaction.user.id.0,
+            roles: interaction
+                .member
+                .as_ref()
+                .map(|member| member.roles.iter().map(|role| role.0).collect())
+                .unwrap_or_default(),
+            channel: interaction.channel_id.0,
+        })
+    }
+
+    fn source(&self) -> Source {
+        Source::Discord
     }
+
+    async fn reply(&self, content: String, ephemeral: bool) -> Result<()> {
+        self.interaction
+            .create_interaction_response(&self.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| {
+                        message.content(content).ephemeral(ephemeral)
+                    })
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn edit_reply(&self, content: String) -> Result<()> {
+        self.interaction
+            .edit_original_interaction_response(&self.http, |response| response.content(content))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Add the `chain` option to a command.
+fn chain_option<'a>(
+    option: &'a mut CreateApplicationCommandOption,
+    chains: &[String],
+) -> &'a mut CreateApplicationCommandOption {
+    option
+        .name("chain")
+        .description("The chain, by default the first one")
+        .kind(CommandOptionType::String)
+        .required(false);
+    for name in chains {
+        option.add_string_choice(name, name);
+    }
+    option
 }
 
 #[async_trait]
 impl EventHandler for WebState {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction {
-            tracing::info!("Received command interaction: {:#?}", command);
-
-            let content = match command.data.name.as_str() {
-                "faucet" => self.handle_faucet_request(&command.data.options).await,
-                _ => "not implemented".to_string(),
-            };
-
-            if let Err(why) = command
-                .create_interaction_response(&ctx.http, |response| {
-                    response
-                        .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| message.content(content))
-                })
-                .await
-            {
-                tracing::error!("Cannot respond to slash command: {}", why);
-            }
+        if let Interaction::ApplicationCommand(interaction) = interaction {
+            self.handle_chat(DiscordCommand {
+                http: ctx.http.clone(),
+                interaction,
+            })
+            .await;
         }
     }
 
@@ -100,20 +132,57 @@ impl EventHandler for WebState {
     async fn ready(&self, ctx: Context, ready: Ready) {
         tracing::info!("{} is connected!", ready.user.name);
 
-        Command::create_global_application_command(&ctx.http, |command| {
-            command
-                .name("faucet")
-                .description("Request funds from the faucet")
-                .create_option(|option| {
-                    option
-                        .name("address")
-                        .description("Your ethereum address")
-                        .kind(CommandOptionType::String)
-                        .required(true)
+        let chains = self.chain_names();
+        let result = Command::set_global_application_commands(&ctx.http, |commands| {
+            commands
+                .create_application_command(|command| {
+                    command
+                        .name("faucet")
+                        .description("Request funds from the faucet")
+                        .create_option(|option| {
+                            option
+                                .name("address")
+                                .description("Your ethereum address")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_option(|option| {
+                            option
+                                .name("asset")
+                                .description("The asset to request, ETH or the symbol of a token")
+                                .kind(CommandOptionType::String)
+                                .required(false)
+                        })
+                        .create_option(|option| chain_option(option, &chains))
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("faucet-status")
+                        .description("Show the status of your last faucet request")
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("balance")
+                        .description("Show the balance of an address")
+                        .create_option(|option| {
+                            option
+                                .name("address")
+                                .description("The ethereum address")
+                                .kind(CommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_option(|option| chain_option(option, &chains))
+                })
+                .create_application_command(|command| {
+                    command
+                        .name("faucet-info")
+                        .description("Show the state of the faucet wallets (admins only)")
                 })
         })
-        .await
-        .expect("Command creation succeeds");
+        .await;
+        if let Err(err) = result {
+            tracing::error!("Failed to register slash commands: {err}");
+        }
     }
 }
 
@@ -128,11 +197,20 @@ pub async fn main() -> io::Result<()> {
     // Create a new instance of the Client, logging in as a bot. This will
     // automatically prepend your bot token with "Bot ", which is a requirement
     // by Discord for bot users.
-    let (sender, receiver) = async_std::channel::unbounded();
-    let state = WebState::new(sender);
-    let faucet = Faucet::create(opts.clone(), receiver)
-        .await
-        .expect("Failed to create faucet");
+    let mut state = WebState::default();
+    let mut faucets = vec![];
+    for chain in opts.chains().expect("Invalid chains config") {
+        // Requests are only sent to the faucet while its queue has room, so
+        // sending never blocks.
+        let (sender, receiver) = async_std::channel::bounded(chain.max_queue_len.max(1));
+        let faucet = Faucet::create(chain.clone(), receiver)
+            .await
+            .unwrap_or_else(|err| {
+                panic!("Failed to create faucet for {}: {err}", chain.chain_name)
+            });
+        state = state.with_chain(sender, &faucet);
+        faucets.push(faucet);
+    }
 
     // Do not attempt to start the discord bot if the token is missing or empty.
     let discord_client = if let Some(token) = opts.discord_token.filter(|token| !token.is_empty()) {
@@ -150,7 +228,11 @@ pub async fn main() -> io::Result<()> {
         None
     };
 
-    let faucet_handle = spawn(faucet.start());
+    let mut faucet_handles = vec![];
+    for faucet in faucets {
+        faucet_handles.push(faucet.start().await);
+    }
+    let faucet_handle = futures::future::join_all(faucet_handles);
     let api_handle = spawn(serve(opts.port, state));
 
     if let Some(mut discord) = discord_client {
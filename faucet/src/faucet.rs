@@ -5,20 +5,38 @@
 // This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
 // You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use anyhow::{Error, Result};
-use async_std::{channel::Receiver, sync::RwLock, task::JoinHandle};
-use clap::Parser;
+use crate::{
+    erc20, read_audit_log, write_audit_log, Asset, AuditEntry, AuditFilter, AuditSink, Autoscaler,
+    Bridge, Deposit, DepositState, FeeError, FeePolicy, Fees, PersistedState, PersistedTransfer,
+    ScalingStatus, Source, Token, TokenConfig, TxType, Wallets, AUDIT_QUEUE_LEN,
+    DEPOSIT_POLL_INTERVAL, NATIVE_TRANSFER_GAS, SCALING_INTERVAL,
+};
+use anyhow::{anyhow, ensure, Error, Result};
+use async_std::{
+    channel::Receiver,
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+};
+use clap::{Parser, ValueEnum};
 use ethers::{
     prelude::SignerMiddleware,
-    providers::{Http, Middleware as _, Provider, StreamExt, Ws},
-    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer},
-    types::{Address, TransactionRequest, H256, U256},
-    utils::{parse_ether, ConversionError},
+    providers::{Http, Middleware as _, Provider, ProviderError, StreamExt, Ws},
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, TransactionReceipt,
+        TransactionRequest, H256, U256, U64,
+    },
+    utils::{parse_ether, parse_units, ConversionError},
 };
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BinaryHeap, HashMap, VecDeque},
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    iter,
+    net::IpAddr,
     num::ParseIntError,
     ops::Index,
+    path::PathBuf,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -27,6 +45,27 @@ use url::Url;
 
 pub type Middleware = SignerMiddleware<Provider<Http>, LocalWallet>;
 
+/// How long to poll before trying to subscribe over the websocket again, when
+/// falling back from the websocket to polling.
+const WS_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How often to request the receipt of a mined transaction before giving up.
+const RECEIPT_RETRIES: usize = 10;
+
+/// How the faucet finds out about mined transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MonitorBackend {
+    /// Subscribe to new blocks over the websocket, and poll over HTTP while
+    /// the websocket is not working.
+    #[default]
+    Auto,
+    /// Subscribe to new blocks over the websocket.
+    Ws,
+    /// Poll the block number and the receipts of inflight transactions over
+    /// HTTP.
+    Http,
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct Options {
     /// Number of Ethereum accounts to use for the faucet.
@@ -37,14 +76,65 @@ pub struct Options {
     ///
     /// When initially setting and increasing the number of wallets the faucet
     /// will make sure they are all funded before serving any faucet requests.
-    /// However when reducing the number of wallets the faucet will not collect
-    /// the funds in the wallets that are no longer used.
+    /// When reducing the number of wallets the funds in the wallets that are
+    /// no longer used are swept back into the active wallets, see
+    /// `retired_clients`.
     #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_NUM_CLIENTS", default_value = "10")]
     pub num_clients: usize,
 
-    /// The mnemonic of the faucet wallet.
-    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_MNEMONIC")]
-    pub mnemonic: String,
+    /// The mnemonic of the faucet wallets.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MNEMONIC",
+        required_unless_present = "keystore_dir",
+        conflicts_with = "keystore_dir"
+    )]
+    pub mnemonic: Option<String>,
+
+    /// Directory of encrypted JSON keystores of the faucet wallets, as written
+    /// by `keygen`.
+    ///
+    /// Used instead of a mnemonic. The keystores are used in the order of
+    /// their file names, and there must be one for every wallet, including
+    /// the retired ones.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_KEYSTORE_DIR",
+        requires = "keystore_password_file"
+    )]
+    pub keystore_dir: Option<PathBuf>,
+
+    /// File containing the password of the keystores.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_KEYSTORE_PASSWORD_FILE")]
+    pub keystore_password_file: Option<PathBuf>,
+
+    /// The index of the first faucet wallet.
+    ///
+    /// This is the HD wallet index when using a mnemonic, or the position of
+    /// the keystore when using a keystore directory.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_CLIENT_INDEX_OFFSET",
+        default_value = "0"
+    )]
+    pub client_index_offset: usize,
+
+    /// The name of the chain configured by these options.
+    ///
+    /// Requests for this chain can be made without naming it.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_CHAIN_NAME",
+        default_value = "default"
+    )]
+    pub chain_name: String,
+
+    /// TOML file configuring additional chains to serve.
+    ///
+    /// Each chain has its own providers and wallets, and can override the
+    /// grant amount, number of wallets and tokens of the primary chain.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_CHAINS_CONFIG")]
+    pub chains_config: Option<PathBuf>,
 
     /// Port on which to serve the API.
     #[arg(
@@ -80,52 +170,610 @@ pub struct Options {
     #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_WEB3_PROVIDER_URL_HTTP")]
     pub provider_url_http: Url,
 
+    /// How to monitor transactions.
+    ///
+    /// Some RPC endpoints do not offer websockets, or have unreliable ones.
+    /// With `auto`, the faucet polls over HTTP whenever subscribing over the
+    /// websocket fails.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MONITOR_BACKEND",
+        value_enum,
+        default_value = "auto"
+    )]
+    pub monitor_backend: MonitorBackend,
+
+    /// The time between two polls when monitoring transactions over HTTP.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_POLL_INTERVAL_SECS",
+        default_value = "1",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub poll_interval: Duration,
+
     /// The authentication token for the discord bot.
     #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_DISCORD_TOKEN")]
     pub discord_token: Option<String>,
 
-    /// Enable the funding on startup (currently broken).
+    /// Ids of the Discord roles allowed to use the faucet commands.
+    ///
+    /// If empty, everyone can use them.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_DISCORD_ROLES",
+        value_delimiter = ','
+    )]
+    pub discord_roles: Vec<u64>,
+
+    /// Ids of the Discord channels in which the faucet commands can be used.
+    ///
+    /// If empty, they can be used in any channel the bot can read.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_DISCORD_CHANNELS",
+        value_delimiter = ','
+    )]
+    pub discord_channels: Vec<u64>,
+
+    /// Ids of the Discord roles allowed to use admin commands like
+    /// `/faucet-info`.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_DISCORD_ADMIN_ROLES",
+        value_delimiter = ','
+    )]
+    pub discord_admin_roles: Vec<u64>,
+
+    /// Enable funding and periodic rebalancing of the faucet wallets.
+    ///
+    /// Wallets with significantly less than the average balance are funded by
+    /// the wallets with the most balance, on startup and then every
+    /// `rebalance_interval`.
     #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_ENABLE_FUNDING")]
     pub enable_funding: bool,
+
+    /// The time between two rounds of rebalancing the faucet wallets.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_REBALANCE_INTERVAL_SECS",
+        default_value = "600",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub rebalance_interval: Duration,
+
+    /// The balance in Ethers a wallet must keep after sending a transfer.
+    ///
+    /// Wallets are only used for a grant or funding transfer if they have at
+    /// least this much left afterwards.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MIN_CLIENT_BALANCE_ETHERS",
+        default_value = "0",
+        value_parser = |arg: &str| -> Result<U256, ConversionError> { Ok(parse_ether(arg)?) }
+    )]
+    pub min_client_balance: U256,
+
+    /// Number of wallets after the first `num_clients` to sweep funds from.
+    ///
+    /// When `num_clients` is reduced, the wallets which are no longer used are
+    /// among these, and their funds are transferred back to the active
+    /// wallets.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_RETIRED_CLIENTS",
+        default_value = "20"
+    )]
+    pub retired_clients: usize,
+
+    /// The maximum number of wallets when sizing the wallet pool by demand.
+    ///
+    /// If set, the pool grows when requests wait longer than
+    /// `target_queue_wait`, and shrinks down to `min_clients` when the queue
+    /// stays empty. `num_clients` is the initial size of the pool. Wallets up
+    /// to this maximum are swept when they are removed from the pool. Requires
+    /// `enable_funding`.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_MAX_CLIENTS")]
+    pub max_clients: Option<usize>,
+
+    /// The minimum number of wallets when sizing the wallet pool by demand.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_MIN_CLIENTS", default_value = "1")]
+    pub min_clients: usize,
+
+    /// The longest a request should wait in the queue before the wallet pool
+    /// grows.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_TARGET_QUEUE_WAIT_SECS",
+        default_value = "60",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub target_queue_wait: Duration,
+
+    /// How long the queue must stay empty before the wallet pool shrinks.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_SCALE_DOWN_IDLE_PERIOD_SECS",
+        default_value = "600",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub scale_down_idle_period: Duration,
+
+    /// ERC-20 tokens granted by the faucet, as `SYMBOL:ADDRESS:AMOUNT`.
+    ///
+    /// The amount is in whole tokens. The tokens must be held by the faucet
+    /// wallets, which are not funded with tokens automatically.
+    #[arg(
+        long = "token",
+        env = "ESPRESSO_ZKEVM_FAUCET_TOKENS",
+        value_delimiter = ','
+    )]
+    pub tokens: Vec<TokenConfig>,
+
+    /// The gas limit of a token grant.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_TOKEN_GAS_LIMIT",
+        default_value = "100000"
+    )]
+    pub token_gas_limit: u64,
+
+    /// The type of the transactions sent by the faucet.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_TX_TYPE",
+        value_enum,
+        default_value = "auto"
+    )]
+    pub tx_type: TxType,
+
+    /// Fixed max fee per gas in Gwei, or gas price of legacy transactions.
+    ///
+    /// If not set, the fee is estimated by the node.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MAX_FEE_PER_GAS_GWEI",
+        value_parser = |arg: &str| -> Result<U256, ConversionError> { Ok(parse_units(arg, "gwei")?.into()) }
+    )]
+    pub max_fee_per_gas: Option<U256>,
+
+    /// Fixed max priority fee per gas in Gwei of EIP-1559 transactions.
+    ///
+    /// If not set, the fee is estimated by the node.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MAX_PRIORITY_FEE_PER_GAS_GWEI",
+        value_parser = |arg: &str| -> Result<U256, ConversionError> { Ok(parse_units(arg, "gwei")?.into()) }
+    )]
+    pub max_priority_fee_per_gas: Option<U256>,
+
+    /// The most the faucet pays per unit of gas, in Gwei.
+    ///
+    /// While the fees exceed the cap, no transfers are sent and stuck
+    /// transactions are not replaced.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_FEE_CAP_GWEI",
+        value_parser = |arg: &str| -> Result<U256, ConversionError> { Ok(parse_units(arg, "gwei")?.into()) }
+    )]
+    pub fee_cap: Option<U256>,
+
+    /// Minimum time between two grants to the same recipient address.
+    ///
+    /// A value of 0 disables the limit.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_ADDRESS_COOLDOWN_SECS",
+        default_value = "0",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub address_cooldown: Duration,
+
+    /// Minimum time between two requests from the same HTTP client IP.
+    ///
+    /// A value of 0 disables the limit.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_IP_COOLDOWN_SECS",
+        default_value = "0",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub ip_cooldown: Duration,
+
+    /// IP addresses of the reverse proxies in front of the web server.
+    ///
+    /// The client IP is only read from the `X-Forwarded-For` header of requests
+    /// from these proxies. Otherwise any client could evade the IP cooldown by
+    /// sending the header itself.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_TRUSTED_PROXIES",
+        value_delimiter = ','
+    )]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Minimum time between two requests from the same Discord user.
+    ///
+    /// A value of 0 disables the limit.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_DISCORD_USER_COOLDOWN_SECS",
+        default_value = "0",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub discord_user_cooldown: Duration,
+
+    /// Maximum number of grants in any `grant_window`, across all requesters.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_MAX_GRANTS_PER_WINDOW")]
+    pub max_grants_per_window: Option<usize>,
+
+    /// The window over which `max_grants_per_window` is enforced.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_GRANT_WINDOW_SECS",
+        default_value = "3600",
+        value_parser = |arg: &str| -> Result<Duration, ParseIntError> { Ok(Duration::from_secs(arg.parse::<u64>()?)) }
+    )]
+    pub grant_window: Duration,
+
+    /// File in which to persist queued and inflight transfers across restarts.
+    ///
+    /// If not set, the faucet state is kept in memory only.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+
+    /// File to which every faucet request and its state changes are appended.
+    ///
+    /// If not set, no audit log is kept.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_AUDIT_LOG")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Bearer token for the admin API.
+    ///
+    /// If not set, the admin API is disabled.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Require web requests to solve a proof-of-work challenge of this many
+    /// leading zero bits.
+    ///
+    /// The difficulty grows with the length of the queue. If not set, no
+    /// challenge is required. Requests through Discord never need one.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_POW_DIFFICULTY")]
+    pub pow_difficulty: Option<u32>,
+
+    /// Require web requests to be signed by the recipient.
+    ///
+    /// This proves that the requester controls the recipient address.
+    /// Requests through Discord never need a signature.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_REQUIRE_SIGNATURE")]
+    pub require_signature: bool,
+
+    /// The maximum number of queued faucet requests.
+    ///
+    /// New requests are rejected while the queue is full.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_MAX_QUEUE_LEN",
+        default_value = "1000"
+    )]
+    pub max_queue_len: usize,
+
+    /// Address of the `PolygonZkEVMBridge` on the chain of the faucet.
+    ///
+    /// If set, the faucet runs against the L1 and grants the native asset of
+    /// an L2 by depositing it into the bridge, addressed to the recipient on
+    /// the L2. Tokens can't be granted in this mode.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_BRIDGE_ADDRESS")]
+    pub bridge_address: Option<Address>,
+
+    /// The network ID of the L2 to deposit to.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_BRIDGE_NETWORK_ID",
+        default_value = "1"
+    )]
+    pub bridge_network_id: u32,
+
+    /// The gas limit of a bridge deposit.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_BRIDGE_GAS_LIMIT",
+        default_value = "300000"
+    )]
+    pub bridge_gas_limit: u64,
+
+    /// HTTP URL of a node of the L2 to deposit to.
+    ///
+    /// If set, the faucet reports when deposits become claimable on the L2
+    /// and when they are claimed.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_FAUCET_BRIDGE_L2_PROVIDER_URL",
+        requires = "bridge_address"
+    )]
+    pub bridge_l2_provider_url: Option<Url>,
+
+    /// Address of the bridge on the L2, if it differs from its address on the
+    /// L1.
+    #[arg(long, env = "ESPRESSO_ZKEVM_FAUCET_BRIDGE_L2_ADDRESS")]
+    pub bridge_l2_address: Option<Address>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             num_clients: 10,
-            mnemonic: "test test test test test test test test test test test junk".to_string(),
+            mnemonic: Some(
+                "test test test test test test test test test test test junk".to_string(),
+            ),
+            keystore_dir: None,
+            keystore_password_file: None,
+            client_index_offset: 0,
+            chain_name: "default".to_string(),
+            chains_config: None,
             port: 8111,
             faucet_grant_amount: parse_ether("100").unwrap(),
             transaction_timeout: Duration::from_secs(300),
             provider_url_ws: Url::parse("ws://localhost:8545").unwrap(),
             provider_url_http: Url::parse("http://localhost:8545").unwrap(),
+            monitor_backend: MonitorBackend::Auto,
+            poll_interval: Duration::from_secs(1),
             discord_token: None,
+            discord_roles: vec![],
+            discord_channels: vec![],
+            discord_admin_roles: vec![],
             enable_funding: true,
+            rebalance_interval: Duration::from_secs(600),
+            min_client_balance: U256::zero(),
+            retired_clients: 20,
+            max_clients: None,
+            min_clients: 1,
+            target_queue_wait: Duration::from_secs(60),
+            scale_down_idle_period: Duration::from_secs(600),
+            tokens: vec![],
+            token_gas_limit: 100_000,
+            tx_type: TxType::Auto,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            fee_cap: None,
+            address_cooldown: Duration::ZERO,
+            ip_cooldown: Duration::ZERO,
+            trusted_proxies: vec![],
+            discord_user_cooldown: Duration::ZERO,
+            max_grants_per_window: None,
+            grant_window: Duration::from_secs(3600),
+            state_file: None,
+            audit_log: None,
+            admin_token: None,
+            pow_difficulty: None,
+            require_signature: false,
+            max_queue_len: 1000,
+            bridge_address: None,
+            bridge_network_id: 1,
+            bridge_gas_limit: 300_000,
+            bridge_l2_provider_url: None,
+            bridge_l2_address: None,
+        }
+    }
+}
+
+/// Identifies a faucet request, for querying its status.
+pub type RequestId = u64;
+
+/// The number of completed requests whose status is remembered.
+const MAX_FINISHED_REQUESTS: usize = 10_000;
+
+/// A request received by one of the faucet frontends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaucetRequest {
+    pub id: RequestId,
+    pub to: Address,
+    pub asset: Asset,
+}
+
+impl FaucetRequest {
+    pub fn new(id: RequestId, to: Address, asset: Asset) -> Self {
+        Self { id, to, asset }
+    }
+}
+
+/// The result of submitting a faucet request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Enqueued {
+    /// A new request, which must be sent to the faucet.
+    New(FaucetRequest),
+    /// A request for the same recipient and asset is still queued, the new
+    /// request was merged into it.
+    Merged(RequestId),
+}
+
+impl Enqueued {
+    pub fn id(&self) -> RequestId {
+        match self {
+            Self::New(request) => request.id,
+            Self::Merged(id) => *id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("the faucet queue is full")]
+pub struct QueueFull;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RequestState {
+    /// Waiting for a wallet to send the transfer.
+    Queued,
+    /// The transfer was sent and is waiting to be mined.
+    Submitted,
+    /// The transfer was mined successfully.
+    Confirmed,
+    /// The request cannot be granted.
+    Failed,
+}
+
+impl RequestState {
+    fn is_finished(&self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed)
+    }
+}
+
+/// The status of a faucet request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestStatus {
+    pub id: RequestId,
+    pub to: Address,
+    pub asset: Asset,
+    pub state: RequestState,
+    /// The number of transfers ahead of this one in the queue, if queued.
+    pub queue_position: Option<usize>,
+    /// The faucet wallet sending the transfer, once submitted.
+    pub sender: Option<Address>,
+    /// The hash of the latest transaction for this transfer, once submitted.
+    pub tx_hash: Option<H256>,
+    /// The status of the latest receipt for this transfer: 1 for success and 0
+    /// for failure. Failed transfers are sent again.
+    pub receipt_status: Option<u64>,
+    #[serde(default)]
+    pub source: Source,
+    /// The amount granted, once the transfer was sent.
+    #[serde(default)]
+    pub amount: Option<U256>,
+    /// The bridge deposit granting the request, once mined, in bridge mode.
+    #[serde(default)]
+    pub deposit: Option<Deposit>,
+}
+
+impl RequestStatus {
+    pub(crate) fn new(id: RequestId, to: Address, asset: Asset) -> Self {
+        Self {
+            id,
+            to,
+            asset,
+            state: RequestState::Queued,
+            queue_position: None,
+            sender: None,
+            tx_hash: None,
+            receipt_status: None,
+            source: Source::default(),
+            amount: None,
+            deposit: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalletState {
+    /// The wallet can send a transfer.
+    Available,
+    /// The wallet is waiting for one of its transfers to be mined.
+    Busy,
+    /// The wallet is waiting to be funded.
+    Funding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletInfo {
+    pub address: Address,
+    pub balance: U256,
+    pub state: WalletState,
+}
+
+/// An overview of the faucet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaucetInfo {
+    /// The amount of the native asset granted per request.
+    pub grant_amount: U256,
+    pub tokens: Vec<Token>,
+    /// The number of transfers waiting to be sent.
+    pub queue_depth: usize,
+    /// The number of transfers waiting to be mined.
+    pub inflight: usize,
+    pub wallets: Vec<WalletInfo>,
+    /// The state of sizing the wallet pool by demand, if enabled.
+    #[serde(default)]
+    pub scaling: Option<ScalingStatus>,
+}
+
+/// Settings which can be changed at runtime through the admin API.
+///
+/// Changes are not persisted, on restart the settings are read from the
+/// options again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// New transfers are not sent while paused.
+    pub paused: bool,
+    /// The amount of the native asset granted per request.
+    pub grant_amount: U256,
+    pub tokens: Vec<Token>,
+    /// The number of wallets used to send transfers.
+    pub num_clients: usize,
+    /// Addresses which are not granted funds.
+    pub blocklist: HashSet<Address>,
+    /// Addresses which are not rate limited.
+    pub allowlist: HashSet<Address>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransferRequest {
     Faucet {
         to: Address,
         amount: U256,
+        /// The faucet request granted by this transfer.
+        #[serde(default)]
+        id: Option<RequestId>,
     },
     Funding {
         to: Address,
-        average_wallet_balance: U256,
+        amount: U256,
+    },
+    Token {
+        to: Address,
+        token: Address,
+        amount: U256,
+        /// The faucet request granted by this transfer.
+        #[serde(default)]
+        id: Option<RequestId>,
     },
 }
 
 impl TransferRequest {
     pub fn faucet(to: Address, amount: U256) -> Self {
-        Self::Faucet { to, amount }
+        Self::Faucet {
+            to,
+            amount,
+            id: None,
+        }
+    }
+
+    pub fn funding(to: Address, amount: U256) -> Self {
+        Self::Funding { to, amount }
     }
 
-    pub fn funding(to: Address, average_wallet_balance: U256) -> Self {
-        Self::Funding {
+    pub fn token(to: Address, token: Address, amount: U256) -> Self {
+        Self::Token {
             to,
-            average_wallet_balance,
+            token,
+            amount,
+            id: None,
+        }
+    }
+
+    /// Associate the transfer with the faucet request it grants.
+    pub fn with_id(mut self, request_id: RequestId) -> Self {
+        match &mut self {
+            Self::Faucet { id, .. } | Self::Token { id, .. } => *id = Some(request_id),
+            Self::Funding { .. } => {}
+        }
+        self
+    }
+
+    /// The faucet request granted by this transfer, if any.
+    pub fn id(&self) -> Option<RequestId> {
+        match self {
+            Self::Faucet { id, .. } | Self::Token { id, .. } => *id,
+            Self::Funding { .. } => None,
         }
     }
 
@@ -133,17 +781,42 @@ impl TransferRequest {
         match self {
             Self::Faucet { to, .. } => *to,
             Self::Funding { to, .. } => *to,
+            Self::Token { to, .. } => *to,
+        }
+    }
+
+    /// The amount transferred, in the native asset or in units of the token.
+    pub fn amount(&self) -> U256 {
+        match self {
+            Self::Faucet { amount, .. } => *amount,
+            Self::Funding { amount, .. } => *amount,
+            Self::Token { amount, .. } => *amount,
+        }
+    }
+
+    /// The gas limit of the transaction making this transfer.
+    pub fn gas_limit(&self, token_gas_limit: u64) -> U256 {
+        match self {
+            Self::Token { .. } => token_gas_limit.into(),
+            _ => NATIVE_TRANSFER_GAS.into(),
         }
     }
 
-    pub fn required_funds(&self) -> U256 {
+    /// The native funds the sender needs to make this transfer, paying at most
+    /// `max_fee_per_gas` for `gas_limit`.
+    pub fn required_funds(&self, gas_limit: U256, max_fee_per_gas: U256) -> U256 {
+        let value = match self {
+            Self::Token { .. } => U256::zero(),
+            _ => self.amount(),
+        };
+        value.saturating_add(gas_limit.saturating_mul(max_fee_per_gas))
+    }
+
+    /// The token and amount of tokens the sender needs to make this transfer.
+    pub fn required_tokens(&self) -> Option<(Address, U256)> {
         match self {
-            // Double the faucet amount to be on the safe side regarding gas.
-            Self::Faucet { amount, .. } => *amount * 2,
-            Self::Funding {
-                average_wallet_balance,
-                ..
-            } => *average_wallet_balance,
+            Self::Token { token, amount, .. } => Some((*token, *amount)),
+            _ => None,
         }
     }
 }
@@ -152,17 +825,29 @@ impl TransferRequest {
 struct Transfer {
     sender: Arc<Middleware>,
     request: TransferRequest,
+    /// The submitted transaction, with nonce and fees filled in.
+    tx: TypedTransaction,
+    /// Hashes of earlier transactions for this transfer which were replaced by
+    /// `tx`. Any one of them may still be mined instead of `tx`.
+    replaced: Vec<H256>,
     timestamp: Instant,
 }
 
 impl Transfer {
-    pub fn new(sender: Arc<Middleware>, request: TransferRequest) -> Self {
+    pub fn new(sender: Arc<Middleware>, request: TransferRequest, tx: TypedTransaction) -> Self {
         Self {
             sender,
             request,
+            tx,
+            replaced: vec![],
             timestamp: Instant::now(),
         }
     }
+
+    /// The hashes of all transactions which may complete this transfer.
+    fn hashes(&self, tx_hash: H256) -> impl Iterator<Item = H256> + '_ {
+        iter::once(tx_hash).chain(self.replaced.iter().copied())
+    }
 }
 
 #[derive(Clone, Debug, Error)]
@@ -177,12 +862,19 @@ pub enum TransferError {
     NoClient,
     #[error("No transfers requests available")]
     NoRequests,
+    #[error("The faucet is paused")]
+    Paused,
+    #[error("Not sending transfers: {0}")]
+    Fees(#[from] FeeError),
 }
 
 #[derive(Debug, Clone, Default)]
 struct ClientPool {
     clients: HashMap<Address, Arc<Middleware>>,
     priority: BinaryHeap<(U256, Address)>,
+    /// Token balances of all clients, including those currently not in the
+    /// pool, by client and token address.
+    token_balances: HashMap<(Address, Address), U256>,
 }
 
 impl ClientPool {
@@ -197,10 +889,59 @@ impl ClientPool {
         self.priority.push((balance, client.address()));
     }
 
-    pub fn has_client_for(&self, transfer: TransferRequest) -> bool {
-        self.priority
-            .peek()
-            .map_or(false, |(balance, _)| *balance >= transfer.required_funds())
+    pub fn remove(&mut self, address: Address) -> Option<(U256, Arc<Middleware>)> {
+        let client = self.clients.remove(&address)?;
+        let balance = self
+            .priority
+            .iter()
+            .find(|(_, a)| *a == address)
+            .map(|(balance, _)| *balance)?;
+        self.priority.retain(|(_, a)| *a != address);
+        Some((balance, client))
+    }
+
+    pub fn token_balance(&self, client: Address, token: Address) -> U256 {
+        self.token_balances
+            .get(&(client, token))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_token_balance(&mut self, client: Address, token: Address, balance: U256) {
+        self.token_balances.insert((client, token), balance);
+    }
+
+    /// Take the client with the most balance which can make `transfer` and
+    /// still keep `min_balance` afterwards.
+    pub fn pop_for(
+        &mut self,
+        transfer: TransferRequest,
+        min_balance: U256,
+        gas_limit: U256,
+        max_fee_per_gas: U256,
+    ) -> Option<(U256, Arc<Middleware>)> {
+        let required = transfer
+            .required_funds(gas_limit, max_fee_per_gas)
+            .saturating_add(min_balance);
+        let address = match transfer.required_tokens() {
+            None => {
+                let (balance, address) = self.priority.peek()?;
+                if *balance < required {
+                    return None;
+                }
+                *address
+            }
+            Some((token, amount)) => {
+                self.priority
+                    .iter()
+                    .filter(|(balance, address)| {
+                        *balance >= required && self.token_balance(*address, token) >= amount
+                    })
+                    .max()?
+                    .1
+            }
+        };
+        self.remove(address)
     }
 }
 
@@ -208,46 +949,286 @@ impl ClientPool {
 struct State {
     clients: ClientPool,
     inflight: HashMap<H256, Transfer>,
+    /// The key in `inflight` of every transaction which may complete an
+    /// inflight transfer, including replaced ones.
+    inflight_hashes: HashMap<H256, H256>,
     clients_being_funded: HashMap<Address, Arc<Middleware>>,
     // Funding wallets has priority, these transfer requests must be pushed to
     // the front.
     transfer_queue: VecDeque<TransferRequest>,
     monitoring_started: bool,
+    /// The status of recent faucet requests.
+    requests: HashMap<RequestId, RequestStatus>,
+    /// Finished requests, oldest first, so that their status can be
+    /// forgotten eventually.
+    finished_requests: VecDeque<RequestId>,
+    next_request_id: RequestId,
+    /// Clients removed from the pool while they were busy. They are dropped
+    /// instead of becoming available again.
+    retiring: HashSet<Address>,
+    /// Retired clients which were found empty, so that they are not queried
+    /// again on every sweep.
+    swept: HashSet<Address>,
+    /// The queued request for each recipient and asset, to merge duplicate
+    /// requests. Its size is the length of the request queue.
+    pending: HashMap<(Address, Asset), RequestId>,
+    /// Records request state changes in the audit log, if enabled.
+    audit: Option<AuditSink>,
+    /// When each queued request was queued.
+    queued_since: HashMap<RequestId, Instant>,
 }
 
-#[derive(Debug, Clone)]
-pub struct Faucet {
-    config: Options,
-    state: Arc<RwLock<State>>,
-    /// Used to monitor Ethereum transactions.
-    provider: Provider<Http>,
-    /// Channel to receive faucet requests.
-    faucet_receiver: Arc<RwLock<Receiver<Address>>>,
-}
-
-impl Faucet {
-    /// Create a new faucet.
+impl State {
+    /// Queue funding transfers to all available clients with significantly
+    /// less than the average balance.
     ///
-    /// Creates `num_clients` wallets and transfers funds and queues transfers
-    /// from the ones with most balance to the ones with less than average
+    /// Clients being funded are not available until their funding transfer is
+    /// mined.
+    fn queue_funding(&mut self) {
+        let balances: Vec<_> = self.clients.priority.iter().copied().collect();
+        if balances.is_empty() {
+            return;
+        }
+        let total = balances
+            .iter()
+            .fold(U256::zero(), |total, (balance, _)| total + balance);
+        let desired_balance = total / balances.len() * 8 / 10;
+
+        for (balance, address) in balances {
+            if balance >= desired_balance {
+                continue;
+            }
+            let Some((_, client)) = self.clients.remove(address) else {
+                continue;
+            };
+            tracing::info!("Queuing funding transfer for {:?}", address);
+            // Funding wallets has priority over faucet requests.
+            self.transfer_queue
+                .push_front(TransferRequest::funding(address, desired_balance - balance));
+            self.clients_being_funded.insert(address, client);
+        }
+    }
+
+    /// Make `client` available again after its transfer, unless it is retiring.
+    fn release_client(&mut self, balance: U256, client: Arc<Middleware>) {
+        if self.retiring.remove(&client.address()) {
+            tracing::info!("Retired client {:?}", client.address());
+        } else {
+            self.clients.push(balance, client);
+        }
+    }
+
+    /// Update the status of the faucet request `id`, if any.
+    fn update_request(&mut self, id: Option<RequestId>, f: impl FnOnce(&mut RequestStatus)) {
+        let Some(id) = id else {
+            return;
+        };
+        let Some(status) = self.requests.get_mut(&id) else {
+            return;
+        };
+        let was_finished = status.state.is_finished();
+        let old_state = status.state;
+        f(status);
+        if status.state == RequestState::Queued {
+            self.queued_since.entry(id).or_insert_with(Instant::now);
+        } else {
+            self.queued_since.remove(&id);
+        }
+        if status.state != old_state {
+            if let Some(audit) = &self.audit {
+                audit.record(status);
+            }
+        }
+        let key = (status.to, status.asset.clone());
+        if status.state == RequestState::Queued {
+            self.pending.entry(key).or_insert(id);
+        } else if self.pending.get(&key) == Some(&id) {
+            self.pending.remove(&key);
+        }
+        if !was_finished && status.state.is_finished() {
+            self.finished_requests.push_back(id);
+            while self.finished_requests.len() > MAX_FINISHED_REQUESTS {
+                if let Some(id) = self.finished_requests.pop_front() {
+                    self.requests.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn track_request(&mut self, to: Address, asset: Asset, source: Source) -> FaucetRequest {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        let status = RequestStatus {
+            source,
+            ..RequestStatus::new(id, to, asset.clone())
+        };
+        if let Some(audit) = &self.audit {
+            audit.record(&status);
+        }
+        self.requests.insert(id, status);
+        self.pending.insert((to, asset.clone()), id);
+        self.queued_since.insert(id, Instant::now());
+        FaucetRequest::new(id, to, asset)
+    }
+
+    /// How long the oldest queued request has been waiting.
+    fn queue_wait(&self, now: Instant) -> Option<Duration> {
+        let oldest = self.queued_since.values().min()?;
+        Some(now.saturating_duration_since(*oldest))
+    }
+
+    /// Queue a transfer again after an attempt to make it did not succeed.
+    fn requeue(&mut self, transfer: TransferRequest) {
+        self.update_request(transfer.id(), |status| {
+            status.state = RequestState::Queued;
+            status.sender = None;
+            status.tx_hash = None;
+        });
+        self.transfer_queue.push_back(transfer);
+    }
+
+    fn request_status(&self, id: RequestId) -> Option<RequestStatus> {
+        let mut status = self.requests.get(&id)?.clone();
+        if status.state == RequestState::Queued {
+            status.queue_position = self
+                .transfer_queue
+                .iter()
+                .position(|transfer| transfer.id() == Some(id));
+        }
+        Some(status)
+    }
+
+    /// Find the inflight transfer which `tx_hash` belongs to.
+    ///
+    /// Returns the key of the transfer in `inflight`, which differs from
+    /// `tx_hash` if `tx_hash` is a transaction that was replaced.
+    fn inflight_key(&self, tx_hash: H256) -> Option<H256> {
+        self.inflight_hashes.get(&tx_hash).copied()
+    }
+
+    fn insert_inflight(&mut self, tx_hash: H256, transfer: Transfer) {
+        for hash in transfer.hashes(tx_hash) {
+            self.inflight_hashes.insert(hash, tx_hash);
+        }
+        self.inflight.insert(tx_hash, transfer);
+    }
+
+    fn remove_inflight(&mut self, tx_hash: H256) -> Option<Transfer> {
+        let transfer = self.inflight.remove(&tx_hash)?;
+        for hash in transfer.hashes(tx_hash) {
+            self.inflight_hashes.remove(&hash);
+        }
+        Some(transfer)
+    }
+
+    fn persisted(&self) -> PersistedState {
+        PersistedState {
+            transfer_queue: self.transfer_queue.iter().copied().collect(),
+            inflight: self
+                .inflight
+                .iter()
+                .map(|(tx_hash, transfer)| PersistedTransfer {
+                    tx_hash: *tx_hash,
+                    sender: transfer.sender.address(),
+                    request: transfer.request,
+                    tx: transfer.tx.clone(),
+                    replaced: transfer.replaced.clone(),
+                })
+                .collect(),
+            requests: self.requests.values().cloned().collect(),
+            next_request_id: self.next_request_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Faucet {
+    config: Options,
+    state: Arc<RwLock<State>>,
+    /// Used to monitor Ethereum transactions.
+    provider: Provider<Http>,
+    /// Channel to receive faucet requests.
+    faucet_receiver: Arc<RwLock<Receiver<FaucetRequest>>>,
+    /// Settings which can be changed at runtime.
+    settings: Arc<RwLock<Settings>>,
+    fee_policy: FeePolicy,
+    wallets: Arc<Wallets>,
+    /// Used to grant funds in bridge mode.
+    bridge: Option<Bridge>,
+    /// Sizes the wallet pool by demand, if enabled.
+    autoscaler: Option<Arc<Mutex<Autoscaler>>>,
+    /// Entries to append to the audit log, if enabled.
+    audit_entries: Option<Receiver<AuditEntry>>,
+    /// Serializes writes to the state file.
+    persist_lock: Arc<Mutex<()>>,
+    /// Serializes changes to the wallet pool, that is resizing it and
+    /// sweeping retired wallets.
+    pool_lock: Arc<Mutex<()>>,
+    chain_id: u64,
+}
+
+impl Faucet {
+    /// Create a new faucet.
+    ///
+    /// Creates `num_clients` wallets and transfers funds and queues transfers
+    /// from the ones with most balance to the ones with less than average
     /// balance.
-    pub async fn create(options: Options, faucet_receiver: Receiver<Address>) -> Result<Self> {
+    ///
+    /// If a state file is configured, the transfers queued or inflight when
+    /// the faucet last stopped are resumed. Each inflight transfer is looked
+    /// up on chain first, so that transfers which were mined in the meantime
+    /// are not sent again.
+    pub async fn create(
+        options: Options,
+        faucet_receiver: Receiver<FaucetRequest>,
+    ) -> Result<Self> {
+        ensure!(
+            options.max_clients.is_none() || options.enable_funding,
+            "sizing the wallet pool by demand requires funding to be enabled, to fund new wallets \
+             and sweep removed ones"
+        );
+
         // Use a http provider for non-subscribe requests
         let provider = Provider::<Http>::try_from(options.provider_url_http.to_string())?;
         let chain_id = provider.get_chainid().await?.as_u64();
+        let fee_policy = FeePolicy::new(&options, &provider).await?;
+        let wallets = Wallets::load(&options).await?;
+        let bridge = Bridge::new(&options, &provider).await?;
+        ensure!(
+            bridge.is_none() || options.tokens.is_empty(),
+            "tokens can't be granted through the bridge"
+        );
+        if let Some(capacity) = wallets.capacity() {
+            ensure!(
+                options.num_clients <= capacity,
+                "{} wallets are configured, but there are only {capacity} keystores",
+                options.num_clients
+            );
+        }
+
+        let mut tokens = vec![];
+        for config in &options.tokens {
+            let token = Token::resolve(config, Arc::new(provider.clone())).await?;
+            tracing::info!(
+                "Granting {} {} from {:?}",
+                token.grant_amount,
+                token.symbol,
+                token.address
+            );
+            tokens.push(token);
+        }
 
         let mut state = State::default();
+        let audit_entries = options.audit_log.as_ref().map(|_| {
+            let (sender, receiver) = async_std::channel::bounded(AUDIT_QUEUE_LEN);
+            state.audit = Some(AuditSink::new(options.chain_name.clone(), sender));
+            receiver
+        });
         let mut clients = vec![];
-        let mut total_balance = U256::zero();
 
         // Create clients
         for index in 0..options.num_clients {
-            let wallet = MnemonicBuilder::<English>::default()
-                .phrase(options.mnemonic.as_str())
-                .index(index as u32)?
-                .build()?
-                .with_chain_id(chain_id);
-            let client = Arc::new(Middleware::new(provider.clone(), wallet));
+            let client = Self::derive_client(&wallets, &provider, chain_id, index)?;
 
             // On startup we may get a "[-32000] failed to get the last block
             // number from state" error even after the request for getChainId is
@@ -265,30 +1246,219 @@ impl Faucet {
                 client.address(),
             );
 
-            total_balance += balance;
+            for token in &tokens {
+                let balance = erc20(token.address, client.clone())
+                    .balance_of(client.address())
+                    .call()
+                    .await?;
+                state
+                    .clients
+                    .set_token_balance(client.address(), token.address, balance);
+            }
+
             clients.push((balance, client));
         }
 
-        let desired_balance = total_balance / options.num_clients * 8 / 10;
+        let persisted = match &options.state_file {
+            Some(path) => PersistedState::load(path).await?,
+            None => PersistedState::default(),
+        };
+
+        for status in persisted.requests {
+            if status.state.is_finished() {
+                state.finished_requests.push_back(status.id);
+            }
+            state.requests.insert(status.id, status);
+        }
+        state.next_request_id = persisted.next_request_id;
+
+        // Clients with a pending transaction only become available once that
+        // transaction is mined.
+        let mut busy = HashSet::new();
+        for transfer in persisted.inflight {
+            let sender = clients
+                .iter()
+                .find(|(_, client)| client.address() == transfer.sender)
+                .map(|(_, client)| client.clone());
+            if Self::resume_inflight(&provider, &mut state, sender, transfer.clone()).await? {
+                busy.insert(transfer.sender);
+            }
+        }
 
         for (balance, client) in clients {
-            // Fund all clients who have significantly less than average balance.
-            if options.enable_funding && balance < desired_balance {
-                tracing::info!("Queuing funding transfer for {:?}", client.address());
-                let transfer = TransferRequest::funding(client.address(), desired_balance);
-                state.transfer_queue.push_back(transfer);
-                state.clients_being_funded.insert(client.address(), client);
-            } else {
+            if !busy.contains(&client.address()) {
                 state.clients.push(balance, client);
             }
         }
+        // Fund all clients who have significantly less than average balance.
+        if options.enable_funding {
+            state.queue_funding();
+        }
+
+        // Funding transfers are recomputed from the current balances above, so
+        // only faucet requests are resumed.
+        for request in persisted.transfer_queue {
+            if !matches!(request, TransferRequest::Funding { .. }) {
+                tracing::info!("Resuming queued transfer {:?}", request);
+                state.transfer_queue.push_back(request);
+            }
+        }
+
+        // Requests which were received but not queued yet are lost, and must
+        // not block new requests for the same recipient.
+        let queued: HashSet<_> = state
+            .transfer_queue
+            .iter()
+            .filter_map(|transfer| transfer.id())
+            .collect();
+        let pending: Vec<_> = state
+            .requests
+            .values()
+            .filter(|status| status.state == RequestState::Queued)
+            .map(|status| (status.id, (status.to, status.asset.clone())))
+            .collect();
+        for (id, key) in pending {
+            if queued.contains(&id) {
+                state.pending.entry(key).or_insert(id);
+                state.queued_since.insert(id, Instant::now());
+            } else {
+                tracing::warn!("Request {id} was lost on restart");
+                state.update_request(Some(id), |status| {
+                    status.state = RequestState::Failed;
+                });
+            }
+        }
 
-        Ok(Self {
+        let autoscaler = Autoscaler::new(&options, wallets.capacity())
+            .map(|autoscaler| Arc::new(Mutex::new(autoscaler)));
+        let settings = Settings {
+            paused: false,
+            grant_amount: options.faucet_grant_amount,
+            tokens,
+            num_clients: options.num_clients,
+            blocklist: Default::default(),
+            allowlist: Default::default(),
+        };
+        let faucet = Self {
             config: options,
             state: Arc::new(RwLock::new(state)),
             provider,
             faucet_receiver: Arc::new(RwLock::new(faucet_receiver)),
-        })
+            settings: Arc::new(RwLock::new(settings)),
+            fee_policy,
+            autoscaler,
+            wallets: Arc::new(wallets),
+            bridge,
+            audit_entries,
+            persist_lock: Default::default(),
+            pool_lock: Default::default(),
+            chain_id,
+        };
+        faucet.persist().await;
+        Ok(faucet)
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.config
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn derive_client(
+        wallets: &Wallets,
+        provider: &Provider<Http>,
+        chain_id: u64,
+        index: usize,
+    ) -> Result<Arc<Middleware>> {
+        let wallet = wallets.wallet(index)?.with_chain_id(chain_id);
+        Ok(Arc::new(Middleware::new(provider.clone(), wallet)))
+    }
+
+    /// Resume tracking a transfer which was inflight when the faucet stopped.
+    ///
+    /// Returns `true` if the transfer has not been mined yet and is now
+    /// tracked as inflight again.
+    async fn resume_inflight(
+        provider: &Provider<Http>,
+        state: &mut State,
+        sender: Option<Arc<Middleware>>,
+        transfer: PersistedTransfer,
+    ) -> Result<bool> {
+        let PersistedTransfer {
+            tx_hash,
+            request,
+            tx,
+            replaced,
+            ..
+        } = transfer;
+
+        for hash in iter::once(tx_hash).chain(replaced.iter().copied()) {
+            if let Some(receipt) = provider.get_transaction_receipt(hash).await? {
+                let receipt_status = receipt.status.map(|status| status.as_u64());
+                state.update_request(request.id(), |status| {
+                    status.tx_hash = Some(hash);
+                    status.receipt_status = receipt_status;
+                });
+                if receipt_status == Some(0) && !matches!(request, TransferRequest::Funding { .. })
+                {
+                    tracing::warn!("Transfer failed tx_hash={hash:?}, will resend: {request:?}");
+                    state.requeue(request);
+                } else {
+                    tracing::info!("Transfer was mined while stopped tx_hash={hash:?}");
+                    state.update_request(request.id(), |status| {
+                        status.state = RequestState::Confirmed;
+                    });
+                }
+                return Ok(false);
+            }
+        }
+
+        match sender {
+            Some(sender) => {
+                // Even if the transaction was dropped, we must not send a new
+                // one right away: it may still be mined. Timeout processing
+                // will either replace it or resend the transfer once its nonce
+                // has been used by another transaction.
+                tracing::info!("Resuming inflight transfer tx_hash={tx_hash:?}: {request:?}");
+                state.insert_inflight(
+                    tx_hash,
+                    Transfer {
+                        replaced,
+                        ..Transfer::new(sender, request, tx)
+                    },
+                );
+                Ok(true)
+            }
+            None => {
+                // The sender is no longer one of our clients, so we cannot
+                // track the transaction. Do not resend it, it may still be
+                // mined.
+                tracing::warn!(
+                    "Pending transfer tx_hash={tx_hash:?} from unknown sender {:?}, not tracking",
+                    transfer.sender
+                );
+                Ok(false)
+            }
+        }
+    }
+
+    /// Write the queued and inflight transfers to the state file, if any.
+    async fn persist(&self) {
+        let Some(path) = &self.config.state_file else {
+            return;
+        };
+        // Take the snapshot while holding the lock, so that an older snapshot
+        // never overwrites a newer one.
+        let _guard = self.persist_lock.lock().await;
+        let snapshot = self.state.read().await.persisted();
+        if let Err(err) = snapshot.save(path).await {
+            tracing::error!(
+                "Failed to persist faucet state to {}: {err}",
+                path.display()
+            );
+        }
     }
 
     pub async fn start(
@@ -298,25 +1468,325 @@ impl Faucet {
         Result<(), Error>,
         Result<(), Error>,
         Result<(), Error>,
+        Result<(), Error>,
+        Result<(), Error>,
+        Result<(), Error>,
+        Result<(), Error>,
     )> {
         let futures = async move {
             futures::join!(
                 self.monitor_transactions(),
                 self.monitor_faucet_requests(),
                 self.monitor_transaction_timeouts(),
-                self.execute_transfers_loop()
+                self.monitor_balances(),
+                self.execute_transfers_loop(),
+                self.write_audit_log(),
+                self.monitor_deposits(),
+                self.monitor_pool_size()
             )
         };
         async_std::task::spawn(futures)
     }
 
-    async fn balance(&self, address: Address) -> Result<U256> {
+    async fn write_audit_log(&self) -> Result<()> {
+        let (Some(path), Some(entries)) = (&self.config.audit_log, &self.audit_entries) else {
+            return Ok(());
+        };
+        write_audit_log(path, entries.clone()).await
+    }
+
+    /// The entries of the audit log matching `filter`, if the log is enabled.
+    pub async fn audit_log(&self, filter: &AuditFilter) -> Result<Option<Vec<AuditEntry>>> {
+        match &self.config.audit_log {
+            Some(path) => Ok(Some(read_audit_log(path, filter).await?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn balance(&self, address: Address) -> Result<U256> {
         Ok(self.provider.get_balance(address, None).await?)
     }
 
     async fn request_transfer(&self, transfer: TransferRequest) {
         tracing::info!("Adding transfer to queue: {:?}", transfer);
         self.state.write().await.transfer_queue.push_back(transfer);
+        self.persist().await;
+    }
+
+    /// Start tracking a new faucet request, assigning it an id.
+    ///
+    /// This bypasses the deduplication and the queue limit of
+    /// [`enqueue_request`](Self::enqueue_request), so it is only available to tests.
+    #[cfg(test)]
+    async fn track_request(&self, to: Address, asset: Asset) -> FaucetRequest {
+        let mut state = self.state.write().await;
+        let request = state.track_request(to, asset, Source::default());
+        drop(state);
+        self.persist().await;
+        request
+    }
+
+    /// Start tracking a faucet request, unless it duplicates a queued request
+    /// or the queue is full.
+    pub async fn enqueue_request(
+        &self,
+        to: Address,
+        asset: Asset,
+        source: Source,
+    ) -> Result<Enqueued, QueueFull> {
+        let mut state = self.state.write().await;
+        if let Some(id) = state.pending.get(&(to, asset.clone())) {
+            tracing::info!("Merging request for {asset} to {to:?} into queued request {id}");
+            return Ok(Enqueued::Merged(*id));
+        }
+        if state.pending.len() >= self.config.max_queue_len {
+            return Err(QueueFull);
+        }
+        let request = state.track_request(to, asset, source);
+        drop(state);
+        self.persist().await;
+        Ok(Enqueued::New(request))
+    }
+
+    /// Mark a request tracked by [`enqueue_request`](Self::enqueue_request)
+    /// as failed, because it could not be handed over to the faucet.
+    ///
+    /// This removes it from the pending requests, so that later requests for
+    /// the same recipient and asset are not merged into it.
+    pub async fn fail_request(&self, id: RequestId) {
+        self.state
+            .write()
+            .await
+            .update_request(Some(id), |status| status.state = RequestState::Failed);
+        self.persist().await;
+    }
+
+    /// The status of the faucet request `id`, if it is known.
+    pub async fn request_status(&self, id: RequestId) -> Option<RequestStatus> {
+        self.state.read().await.request_status(id)
+    }
+
+    pub async fn info(&self) -> Result<FaucetInfo> {
+        let (queue_depth, inflight, wallets) = {
+            let state = self.state.read().await;
+            let wallets: Vec<_> = state
+                .clients
+                .clients
+                .keys()
+                .map(|address| (*address, WalletState::Available))
+                .chain(
+                    state
+                        .inflight
+                        .values()
+                        .map(|transfer| (transfer.sender.address(), WalletState::Busy)),
+                )
+                .chain(
+                    state
+                        .clients_being_funded
+                        .keys()
+                        .map(|address| (*address, WalletState::Funding)),
+                )
+                .collect();
+            (state.transfer_queue.len(), state.inflight.len(), wallets)
+        };
+
+        // Query the balances without holding the lock.
+        let mut infos = vec![];
+        for (address, state) in wallets {
+            infos.push(WalletInfo {
+                address,
+                balance: self.balance(address).await?,
+                state,
+            });
+        }
+        infos.sort_by_key(|info| info.address);
+
+        let scaling = match &self.autoscaler {
+            Some(autoscaler) => {
+                let queue_wait = self.state.read().await.queue_wait(Instant::now());
+                Some(autoscaler.lock().await.status(queue_wait))
+            }
+            None => None,
+        };
+
+        let settings = self.settings.read().await;
+        Ok(FaucetInfo {
+            grant_amount: settings.grant_amount,
+            tokens: settings.tokens.clone(),
+            queue_depth,
+            inflight,
+            wallets: infos,
+            scaling,
+        })
+    }
+
+    /// The number of transfers waiting to be sent.
+    pub async fn queue_depth(&self) -> usize {
+        self.state.read().await.transfer_queue.len()
+    }
+
+    /// The current runtime settings.
+    pub async fn settings(&self) -> Settings {
+        self.settings.read().await.clone()
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.settings.read().await.paused
+    }
+
+    pub async fn is_blocked(&self, address: Address) -> bool {
+        self.settings.read().await.blocklist.contains(&address)
+    }
+
+    pub async fn is_allowed(&self, address: Address) -> bool {
+        self.settings.read().await.allowlist.contains(&address)
+    }
+
+    /// Stop or resume sending transfers.
+    ///
+    /// Transfers which were already sent are still monitored while paused.
+    pub async fn set_paused(&self, paused: bool) {
+        self.settings.write().await.paused = paused;
+    }
+
+    /// Change the amount of `asset` granted per request.
+    ///
+    /// `amount` is given in whole units of the asset, e.g. ether. Returns the
+    /// new amount in the smallest unit of the asset.
+    pub async fn set_grant_amount(&self, asset: &Asset, amount: &str) -> Result<U256> {
+        let mut settings = self.settings.write().await;
+        match asset {
+            Asset::Native => {
+                settings.grant_amount = parse_ether(amount)?;
+                Ok(settings.grant_amount)
+            }
+            Asset::Token(symbol) => {
+                let token = settings
+                    .tokens
+                    .iter_mut()
+                    .find(|token| &token.symbol == symbol)
+                    .ok_or_else(|| anyhow!("unknown token {symbol}"))?;
+                token.grant_amount = parse_units(amount, token.decimals as u32)?.into();
+                Ok(token.grant_amount)
+            }
+        }
+    }
+
+    pub async fn set_blocked(&self, address: Address, blocked: bool) {
+        let mut settings = self.settings.write().await;
+        if blocked {
+            settings.blocklist.insert(address);
+        } else {
+            settings.blocklist.remove(&address);
+        }
+    }
+
+    pub async fn set_allowed(&self, address: Address, allowed: bool) {
+        let mut settings = self.settings.write().await;
+        if allowed {
+            settings.allowlist.insert(address);
+        } else {
+            settings.allowlist.remove(&address);
+        }
+    }
+
+    /// Drop all queued faucet requests, returning how many were dropped.
+    ///
+    /// Funding transfers stay queued, since the wallets they fund are not
+    /// available until they are sent.
+    pub async fn drain_queue(&self) -> usize {
+        let mut state = self.state.write().await;
+        let (funding, drained): (VecDeque<_>, Vec<_>) = state
+            .transfer_queue
+            .drain(..)
+            .partition(|transfer| matches!(transfer, TransferRequest::Funding { .. }));
+        state.transfer_queue = funding;
+        for transfer in &drained {
+            state.update_request(transfer.id(), |status| {
+                status.state = RequestState::Failed;
+            });
+        }
+        drop(state);
+        self.persist().await;
+        drained.len()
+    }
+
+    /// Change the number of wallets used to send transfers.
+    ///
+    /// New wallets are funded like on startup. Wallets which are no longer
+    /// used stop sending transfers once their current transfer completes, and
+    /// are swept like retired wallets.
+    pub async fn resize(&self, num_clients: usize) -> Result<()> {
+        ensure!(num_clients > 0, "the faucet needs at least one wallet");
+        let _guard = self.pool_lock.lock().await;
+        let (old, tokens) = {
+            let settings = self.settings.read().await;
+            (settings.num_clients, settings.tokens.clone())
+        };
+
+        if num_clients > old {
+            // Query the new wallets without holding the settings or state
+            // lock, so that requests are not blocked meanwhile.
+            let mut clients = vec![];
+            for index in old..num_clients {
+                let client =
+                    Self::derive_client(&self.wallets, &self.provider, self.chain_id, index)?;
+                let balance = self.balance(client.address()).await?;
+                let mut token_balances = vec![];
+                for token in &tokens {
+                    let balance = erc20(token.address, client.clone())
+                        .balance_of(client.address())
+                        .call()
+                        .await?;
+                    token_balances.push((token.address, balance));
+                }
+                tracing::info!(
+                    "Adding client {index} {:?} with balance {balance}",
+                    client.address()
+                );
+                clients.push((balance, client, token_balances));
+            }
+
+            let mut settings = self.settings.write().await;
+            let mut state = self.state.write().await;
+            for (balance, client, token_balances) in clients {
+                state.swept.remove(&client.address());
+                for (token, balance) in token_balances {
+                    state
+                        .clients
+                        .set_token_balance(client.address(), token, balance);
+                }
+                // A wallet which is still busy from before it was removed is
+                // simply kept.
+                if !state.retiring.remove(&client.address()) {
+                    state.clients.push(balance, client);
+                }
+            }
+            if self.config.enable_funding && state.clients_being_funded.is_empty() {
+                state.queue_funding();
+            }
+            settings.num_clients = num_clients;
+        } else {
+            let mut settings = self.settings.write().await;
+            let mut state = self.state.write().await;
+            for index in num_clients..old {
+                let address =
+                    Self::derive_client(&self.wallets, &self.provider, self.chain_id, index)?
+                        .address();
+                // The wallet may have been swept when it was retired before.
+                state.swept.remove(&address);
+                if state.clients.remove(address).is_none()
+                    && state.clients_being_funded.remove(&address).is_none()
+                {
+                    tracing::info!("Retiring client {index} {address:?} once it is idle");
+                    state.retiring.insert(address);
+                } else {
+                    tracing::info!("Removed client {index} {address:?}");
+                }
+            }
+            settings.num_clients = num_clients;
+        }
+        Ok(())
     }
 
     async fn execute_transfers_loop(&self) -> Result<()> {
@@ -337,7 +1807,10 @@ impl Faucet {
                     TransferError::NoClient => {
                         tracing::info!("No clients to handle transfer requests.")
                     }
-                    TransferError::NoRequests => {}
+                    TransferError::Fees(_) => {
+                        tracing::warn!("{err}")
+                    }
+                    TransferError::NoRequests | TransferError::Paused => {}
                 };
                 // Avoid creating a busy loop.
                 async_std::task::sleep(Duration::from_secs(1)).await;
@@ -346,31 +1819,67 @@ impl Faucet {
     }
 
     async fn execute_transfer(&self) -> Result<H256, TransferError> {
+        if self.settings.read().await.paused {
+            Err(TransferError::Paused)?;
+        }
+        // Avoid querying the fees while there is nothing to send.
+        if self.state.read().await.transfer_queue.is_empty() {
+            Err(TransferError::NoRequests)?;
+        }
+        let fees = self.fee_policy.fees(&self.provider).await?;
+
         let mut state = self.state.write().await;
         if state.transfer_queue.is_empty() {
             Err(TransferError::NoRequests)?;
         }
-        let transfer = state.transfer_queue.index(0);
-        if !state.clients.has_client_for(*transfer) {
-            Err(TransferError::NoClient)?;
-        }
-        let (balance, sender) = state.clients.pop().unwrap();
-        let transfer = state.transfer_queue.pop_front().unwrap();
+        let transfer = *state.transfer_queue.index(0);
+        let gas_limit = match (&self.bridge, transfer) {
+            (Some(_), TransferRequest::Faucet { .. }) => self.config.bridge_gas_limit.into(),
+            _ => transfer.gas_limit(self.config.token_gas_limit),
+        };
+        let Some((balance, sender)) = state.clients.pop_for(
+            transfer,
+            self.config.min_client_balance,
+            gas_limit,
+            fees.max_fee_per_gas(),
+        ) else {
+            return Err(TransferError::NoClient);
+        };
+        state.transfer_queue.pop_front();
 
         // Drop the guard while we are doing the request to the RPC.
         drop(state);
 
-        let amount = match transfer {
-            TransferRequest::Faucet { amount, .. } => amount,
-            TransferRequest::Funding { .. } => balance / 2,
+        // Fill in the nonce before sending, so that we can replace the
+        // transaction with one using the same nonce if it gets stuck.
+        let mut tx = match (&self.bridge, transfer) {
+            (Some(bridge), TransferRequest::Faucet { to, amount, .. }) => fees.transaction(
+                bridge.address(),
+                amount,
+                bridge.deposit_calldata(to, amount),
+                gas_limit,
+            ),
+            (
+                _,
+                TransferRequest::Token {
+                    to, token, amount, ..
+                },
+            ) => fees.transaction(
+                token,
+                U256::zero(),
+                erc20(token, sender.clone()).transfer(to, amount).calldata(),
+                gas_limit,
+            ),
+            _ => fees.transaction(transfer.to(), transfer.amount(), None, gas_limit),
         };
-        match sender
-            .clone()
-            .send_transaction(TransactionRequest::pay(transfer.to(), amount), None)
-            .await
-        {
-            Ok(tx) => {
-                tracing::info!("Sending transfer: {:?} hash={:?}", transfer, tx.tx_hash());
+        let result = match sender.fill_transaction(&mut tx, None).await {
+            Ok(()) => sender.send_transaction(tx.clone(), None).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(pending) => {
+                let tx_hash = pending.tx_hash();
+                tracing::info!("Sending transfer: {:?} hash={:?}", transfer, tx_hash);
                 // Note: if running against an *extremely* fast chain , it is possible
                 // that the transaction is mined before we have a chance to add it to
                 // the inflight transfers. In that case, the receipt handler may not yet
@@ -378,23 +1887,26 @@ impl Faucet {
                 // risk of this happening outside of local testing is neglible. We could
                 // sign the tx locally first and then insert it but this also means we
                 // would have to remove it again if the submission fails.
-                self.state
-                    .write()
-                    .await
-                    .inflight
-                    .insert(tx.tx_hash(), Transfer::new(sender.clone(), transfer));
-                Ok(tx.tx_hash())
+                let mut state = self.state.write().await;
+                state.update_request(transfer.id(), |status| {
+                    status.state = RequestState::Submitted;
+                    status.amount = Some(transfer.amount());
+                    status.sender = Some(sender.address());
+                    status.tx_hash = Some(tx_hash);
+                });
+                state.insert_inflight(tx_hash, Transfer::new(sender.clone(), transfer, tx));
+                drop(state);
+                self.persist().await;
+                Ok(tx_hash)
             }
             Err(err) => {
-                // Make the client available again.
-                self.state
-                    .write()
-                    .await
-                    .clients
-                    .push(balance, sender.clone());
-
-                // Requeue the transfer.
-                self.request_transfer(transfer).await;
+                // Make the client available again and requeue the transfer.
+                let mut state = self.state.write().await;
+                state.release_client(balance, sender.clone());
+                tracing::info!("Adding transfer to queue: {:?}", transfer);
+                state.requeue(transfer);
+                drop(state);
+                self.persist().await;
 
                 Err(TransferError::RpcSubmitError {
                     transfer,
@@ -408,30 +1920,79 @@ impl Faucet {
     async fn handle_receipt(&self, tx_hash: H256) -> Result<()> {
         tracing::debug!("Got tx hash {:?}", tx_hash);
 
-        let Transfer {
-            sender, request, ..
-        } = {
-            if let Some(inflight) = self.state.read().await.inflight.get(&tx_hash) {
-                inflight.clone()
-            } else {
-                // Not a transaction we are monitoring.
-                return Ok(());
-            }
+        let Some(key) = self.state.read().await.inflight_key(tx_hash) else {
+            // Not a transaction we are monitoring.
+            return Ok(());
         };
 
-        // In case there is a race condition and the receipt is not yet available, wait for it.
-        let receipt = loop {
-            if let Ok(Some(tx)) = self.provider.get_transaction_receipt(tx_hash).await {
-                break tx;
+        // In case there is a race condition and the receipt is not yet
+        // available, wait for it. If it never shows up, the transfer is
+        // completed by the next sweep or by timeout processing instead.
+        for _ in 0..RECEIPT_RETRIES {
+            if let Ok(Some(receipt)) = self.provider.get_transaction_receipt(tx_hash).await {
+                return self.complete_transfer(key, receipt).await;
             }
             tracing::warn!("No receipt for tx_hash={tx_hash:?}, will retry");
             async_std::task::sleep(Duration::from_secs(1)).await;
+        }
+        Err(anyhow::anyhow!("no receipt for tx_hash={tx_hash:?}"))
+    }
+
+    /// Complete the inflight transfers mined in a block with the given
+    /// transactions.
+    ///
+    /// Most transactions in a block are not ours, so the block is matched
+    /// against the inflight transactions first, and only the receipts of
+    /// matching transactions are requested, concurrently.
+    async fn process_block(&self, transactions: Vec<H256>) {
+        let ours: Vec<H256> = {
+            let state = self.state.read().await;
+            transactions
+                .into_iter()
+                .filter(|hash| state.inflight_key(*hash).is_some())
+                .collect()
+        };
+        let results = join_all(ours.iter().map(|hash| self.handle_receipt(*hash))).await;
+        for (hash, result) in ours.iter().zip(results) {
+            if let Err(err) = result {
+                tracing::error!("Failed to handle transaction tx_hash={hash:?}: {err}");
+            }
+        }
+    }
+
+    /// Complete the inflight transfer `key` given the receipt of one of its
+    /// transactions.
+    async fn complete_transfer(&self, key: H256, receipt: TransactionReceipt) -> Result<()> {
+        let Some(Transfer {
+            sender, request, ..
+        }) = self.state.read().await.inflight.get(&key).cloned()
+        else {
+            // Already completed, for instance by timeout processing.
+            return Ok(());
         };
 
         tracing::info!("Received receipt for {:?}", request);
 
         // Do all external calls before state modifications
         let new_sender_balance = self.balance(sender.address()).await?;
+        let token_update = match request.required_tokens() {
+            Some((token, _)) => Some((
+                token,
+                erc20(token, sender.clone())
+                    .balance_of(sender.address())
+                    .call()
+                    .await?,
+            )),
+            None => None,
+        };
+
+        // Find the deposit of a successful grant through the bridge.
+        let deposit = match (&self.bridge, request) {
+            (Some(bridge), TransferRequest::Faucet { .. }) if receipt.status == Some(1.into()) => {
+                bridge.deposit(&receipt).await?
+            }
+            _ => None,
+        };
 
         // For successful funding transfers, we also need to update the receiver's balance.
         let receiver_update = if receipt.status == Some(1.into()) {
@@ -447,8 +2008,20 @@ impl Faucet {
         // Update state, the rest of the operations must be atomic.
         let mut state = self.state.write().await;
 
+        // Remove the transaction from the inflight list. If it is no longer
+        // there, the transfer was completed concurrently and we must not make
+        // the sender available twice.
+        if state.remove_inflight(key).is_none() {
+            return Ok(());
+        }
+
         // Make the sender available
-        state.clients.push(new_sender_balance, sender.clone());
+        state.release_client(new_sender_balance, sender.clone());
+        if let Some((token, balance)) = token_update {
+            state
+                .clients
+                .set_token_balance(sender.address(), token, balance);
+        }
 
         // Apply the receiver update, if there is one.
         if let Some((receiver, balance)) = receiver_update {
@@ -463,69 +2036,291 @@ impl Faucet {
             }
         }
 
+        state.update_request(request.id(), |status| {
+            status.tx_hash = Some(receipt.transaction_hash);
+            status.receipt_status = receipt.status.map(|status| status.as_u64());
+        });
+
         // If the transaction failed, schedule it again.
         if receipt.status == Some(0.into()) {
-            // TODO: this code is currently untested.
             tracing::warn!(
                 "Transfer failed tx_hash={:?}, will resend: {:?}",
-                tx_hash,
+                receipt.transaction_hash,
                 request
             );
-            state.transfer_queue.push_back(request);
-        };
-
-        // Finally remove the transaction from the inflight list.
-        state.inflight.remove(&tx_hash);
-
-        // TODO: I think for transactions with bad nonces we would not even get
-        // a transactions receipt. As a result the sending client would remain
-        // stuck. As a workaround we could add a timeout to the inflight clients
-        // and unlock them after a while. It may be difficult to set a good
-        // fixed value for the timeout because the zkevm-node currently waits
-        // for hotshot blocks being sequenced in the contract.
+            state.requeue(request);
+        } else {
+            if let Some(deposit) = deposit {
+                tracing::info!(
+                    "Deposited {:?} through the bridge, deposit count {}",
+                    request,
+                    deposit.deposit_count
+                );
+            }
+            state.update_request(request.id(), |status| {
+                status.state = RequestState::Confirmed;
+                status.deposit = deposit;
+            });
+        }
 
+        drop(state);
+        self.persist().await;
         Ok(())
     }
 
-    async fn monitor_transactions(&self) -> Result<()> {
+    /// Track the deposits of grants through the bridge until they are claimed.
+    async fn monitor_deposits(&self) -> Result<()> {
+        if !self
+            .bridge
+            .as_ref()
+            .map_or(false, |bridge| bridge.tracks_claims())
+        {
+            return Ok(());
+        }
         loop {
-            let provider = match Provider::<Ws>::connect(self.config.provider_url_ws.clone()).await
-            {
-                Ok(provider) => provider,
-                Err(err) => {
-                    tracing::error!("Failed to connect to provider: {}, will retry", err);
-                    async_std::task::sleep(Duration::from_secs(5)).await;
+            self.update_deposits().await;
+            async_std::task::sleep(DEPOSIT_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn update_deposits(&self) {
+        let Some(bridge) = &self.bridge else {
+            return;
+        };
+        let deposits: Vec<_> = self
+            .state
+            .read()
+            .await
+            .requests
+            .values()
+            .filter_map(|status| match status.deposit {
+                Some(deposit) if deposit.state != DepositState::Claimed => {
+                    Some((status.id, deposit))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut changed = false;
+        for (id, deposit) in deposits {
+            match bridge.update(deposit).await {
+                Ok(updated) if updated != deposit => {
+                    tracing::info!(
+                        "Deposit {} of request {id} is {:?}",
+                        updated.deposit_count,
+                        updated.state
+                    );
+                    self.state
+                        .write()
+                        .await
+                        .update_request(Some(id), |status| status.deposit = Some(updated));
+                    changed = true;
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!(
+                    "Failed to check deposit {} of request {id}: {err}",
+                    deposit.deposit_count
+                ),
+            }
+        }
+        if changed {
+            self.persist().await;
+        }
+    }
+
+    /// Size the wallet pool by demand, if enabled.
+    async fn monitor_pool_size(&self) -> Result<()> {
+        if self.autoscaler.is_none() {
+            return Ok(());
+        }
+        loop {
+            async_std::task::sleep(SCALING_INTERVAL).await;
+            self.scale_pool().await;
+        }
+    }
+
+    async fn scale_pool(&self) {
+        let Some(autoscaler) = &self.autoscaler else {
+            return;
+        };
+        let now = Instant::now();
+        let (queue_depth, queue_wait) = {
+            let state = self.state.read().await;
+            (state.transfer_queue.len(), state.queue_wait(now))
+        };
+        let num_clients = self.settings.read().await.num_clients;
+        let mut autoscaler = autoscaler.lock().await;
+        let Some(decision) = autoscaler.decide(num_clients, queue_depth, queue_wait, now) else {
+            return;
+        };
+        tracing::info!(
+            "Resizing the wallet pool from {} to {}: {}",
+            decision.from,
+            decision.to,
+            decision.reason
+        );
+        match self.resize(decision.to).await {
+            Ok(()) => autoscaler.record(decision),
+            Err(err) => tracing::error!("Failed to resize the wallet pool: {err}"),
+        }
+    }
+
+    async fn monitor_transactions(&self) -> Result<()> {
+        let backend = self.config.monitor_backend;
+        loop {
+            if backend != MonitorBackend::Http {
+                match self.watch_blocks().await {
+                    Ok(()) => {
+                        // If we get here, the subscription was closed. This happens for example
+                        // if the RPC server is restarted.
+                        tracing::warn!("Block subscription closed, will restart ...");
+                        async_std::task::sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                    Err(err) if backend == MonitorBackend::Ws => {
+                        tracing::error!("Failed to subscribe to blocks: {}, will retry", err);
+                        async_std::task::sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to subscribe to blocks: {}, polling instead", err);
+                    }
+                }
+            }
+
+            // When falling back to polling, try the websocket again after a
+            // while, since the failure may have been temporary.
+            let until =
+                (backend == MonitorBackend::Auto).then(|| Instant::now() + WS_RETRY_INTERVAL);
+            self.poll_receipts(until).await;
+        }
+    }
+
+    /// Monitor transactions using a block subscription over the websocket.
+    ///
+    /// Returns when the subscription is closed, or an error if it cannot be
+    /// established.
+    async fn watch_blocks(&self) -> Result<(), ProviderError> {
+        let provider = Provider::<Ws>::connect(self.config.provider_url_ws.clone()).await?;
+        let mut stream = provider.subscribe_blocks().await?;
+
+        self.state.write().await.monitoring_started = true;
+        tracing::info!("Transaction monitoring started ...");
+
+        // Transfers may have been mined while we were not subscribed.
+        self.sweep_inflight().await;
+
+        let mut last_block: Option<U64> = None;
+        while let Some(block) = stream.next().await {
+            if let (Some(last), Some(number)) = (last_block, block.number) {
+                if number > last + 1 {
+                    tracing::warn!("Missed blocks {} to {}, sweeping", last + 1, number - 1);
+                    self.sweep_inflight().await;
+                }
+            }
+            last_block = block.number.or(last_block);
+            self.process_block(block.transactions).await;
+        }
+        Ok(())
+    }
+
+    /// Monitor transactions by polling over HTTP, until `until` if given.
+    ///
+    /// Whenever the block number increases, the receipts of all inflight
+    /// transactions are requested.
+    async fn poll_receipts(&self, until: Option<Instant>) {
+        self.state.write().await.monitoring_started = true;
+        tracing::info!("Transaction monitoring started, polling ...");
+        let mut last_block = None;
+        while until.map_or(true, |until| Instant::now() < until) {
+            async_std::task::sleep(self.config.poll_interval).await;
+            let block = match self.provider.get_block_number().await {
+                Ok(block) => block,
+                Err(err) => {
+                    tracing::warn!("Failed to get block number: {err}");
                     continue;
                 }
             };
+            if last_block == Some(block) {
+                continue;
+            }
+            last_block = Some(block);
+            self.sweep_inflight().await;
+        }
+    }
 
-            let mut stream = provider
-                .subscribe_blocks()
-                .await
-                .unwrap()
-                .flat_map(|block| futures::stream::iter(block.transactions));
-
-            self.state.write().await.monitoring_started = true;
-            tracing::info!("Transaction monitoring started ...");
-            while let Some(tx_hash) = stream.next().await {
-                self.handle_receipt(tx_hash).await?;
+    /// Complete all inflight transfers which have been mined.
+    ///
+    /// The receipts of all inflight transactions are requested concurrently.
+    async fn sweep_inflight(&self) {
+        let inflight: Vec<(H256, Vec<H256>)> = self
+            .state
+            .read()
+            .await
+            .inflight
+            .iter()
+            .map(|(key, transfer)| (*key, transfer.hashes(*key).collect()))
+            .collect();
+        let results = join_all(
+            inflight
+                .iter()
+                .map(|(key, hashes)| self.sweep_transfer(*key, hashes)),
+        )
+        .await;
+        for ((key, _), result) in inflight.iter().zip(results) {
+            if let Err(err) = result {
+                tracing::error!("Failed to check inflight transfer tx_hash={key:?}: {err}");
             }
+        }
+    }
 
-            // If we get here, the subscription was closed. This happens for example
-            // if the RPC server is restarted.
-            tracing::warn!("Block subscription closed, will restart ...");
-            async_std::task::sleep(Duration::from_secs(5)).await;
+    /// Complete the inflight transfer `key` if any of its transactions
+    /// `hashes` was mined.
+    async fn sweep_transfer(&self, key: H256, hashes: &[H256]) -> Result<()> {
+        for hash in hashes {
+            if let Some(receipt) = self.provider.get_transaction_receipt(*hash).await? {
+                tracing::info!("Found receipt while sweeping tx_hash={hash:?}");
+                return self.complete_transfer(key, receipt).await;
+            }
         }
+        Ok(())
     }
 
     async fn monitor_faucet_requests(&self) -> Result<()> {
         loop {
-            if let Ok(address) = self.faucet_receiver.write().await.recv().await {
-                self.request_transfer(TransferRequest::faucet(
-                    address,
-                    self.config.faucet_grant_amount,
-                ))
-                .await;
+            if let Ok(request) = self.faucet_receiver.write().await.recv().await {
+                match self.transfer_request(&request).await {
+                    Some(transfer) => self.request_transfer(transfer).await,
+                    None => {
+                        tracing::error!("Unknown asset in faucet request {request:?}");
+                        self.state
+                            .write()
+                            .await
+                            .update_request(Some(request.id), |status| {
+                                status.state = RequestState::Failed;
+                            });
+                    }
+                }
+            }
+        }
+    }
+
+    /// The transfer which grants a faucet request.
+    async fn transfer_request(&self, request: &FaucetRequest) -> Option<TransferRequest> {
+        let settings = self.settings.read().await;
+        match &request.asset {
+            Asset::Native => {
+                Some(TransferRequest::faucet(request.to, settings.grant_amount).with_id(request.id))
+            }
+            Asset::Token(symbol) => {
+                let token = settings
+                    .tokens
+                    .iter()
+                    .find(|token| &token.symbol == symbol)?;
+                Some(
+                    TransferRequest::token(request.to, token.address, token.grant_amount)
+                        .with_id(request.id),
+                )
             }
         }
     }
@@ -538,23 +2333,304 @@ impl Faucet {
     }
 
     async fn process_transaction_timeouts(&self) -> Result<()> {
-        let inflight = self.state.read().await.inflight.clone();
-
-        for (
-            tx_hash,
-            Transfer {
-                sender, request, ..
-            },
-        ) in inflight
+        let timed_out: Vec<_> = self
+            .state
+            .read()
+            .await
+            .inflight
             .iter()
             .filter(|(_, transfer)| transfer.timestamp.elapsed() > self.config.transaction_timeout)
-        {
-            tracing::warn!("Transfer timed out: {:?}", request);
-            let balance = self.balance(sender.address()).await?;
+            .map(|(tx_hash, transfer)| (*tx_hash, transfer.clone()))
+            .collect();
+
+        for (tx_hash, transfer) in timed_out {
+            if let Err(err) = self.process_transaction_timeout(tx_hash, transfer).await {
+                tracing::error!("Failed to process timed out transfer tx_hash={tx_hash:?}: {err}");
+            }
+        }
+        self.persist().await;
+        Ok(())
+    }
+
+    /// Handle an inflight transfer which was not mined within the timeout.
+    ///
+    /// Sending the transfer again with a new nonce could pay the recipient
+    /// twice, so instead:
+    /// * if the transaction (or one it replaced) was mined late, the transfer
+    ///   is completed as usual,
+    /// * if its nonce was used by another transaction, the transfer can never
+    ///   be mined and is queued again,
+    /// * otherwise the transaction is still pending or was dropped, and it is
+    ///   replaced by one with the same nonce and higher fees.
+    async fn process_transaction_timeout(&self, tx_hash: H256, transfer: Transfer) -> Result<()> {
+        tracing::warn!(
+            "Transfer timed out tx_hash={tx_hash:?}: {:?}",
+            transfer.request
+        );
+
+        if let Some(receipt) = self.mined_receipt(tx_hash, &transfer).await? {
+            return self.complete_transfer(tx_hash, receipt).await;
+        }
+        self.process_unmined_transfer(tx_hash, transfer).await
+    }
+
+    /// The receipt of the transaction of `transfer`, or of one it replaced, if
+    /// any of them was mined.
+    async fn mined_receipt(
+        &self,
+        tx_hash: H256,
+        transfer: &Transfer,
+    ) -> Result<Option<TransactionReceipt>> {
+        for hash in transfer.hashes(tx_hash) {
+            if let Some(receipt) = self.provider.get_transaction_receipt(hash).await? {
+                tracing::info!("Timed out transfer was mined tx_hash={hash:?}");
+                return Ok(Some(receipt));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Handle a timed out transfer for which no receipt was found.
+    async fn process_unmined_transfer(&self, tx_hash: H256, transfer: Transfer) -> Result<()> {
+        let sender = transfer.sender.address();
+        // The nonce is always set, since we fill the transaction before sending it.
+        let nonce = transfer.tx.nonce().copied().unwrap_or_default();
+        let next_nonce = self
+            .provider
+            .get_transaction_count(sender, Some(BlockNumber::Latest.into()))
+            .await?;
+        if next_nonce > nonce {
+            // The transfer may have been mined after we looked for its
+            // receipts, in which case the nonce was used by the transfer itself.
+            if let Some(receipt) = self.mined_receipt(tx_hash, &transfer).await? {
+                return self.complete_transfer(tx_hash, receipt).await;
+            }
+            tracing::warn!(
+                "Nonce {nonce} of {sender:?} was used by another transaction, will resend: {:?}",
+                transfer.request
+            );
+            let balance = self.balance(sender).await?;
             let mut state = self.state.write().await;
-            state.transfer_queue.push_back(*request);
-            state.inflight.remove(tx_hash);
-            state.clients.push(balance, sender.clone());
+            if state.remove_inflight(tx_hash).is_some() {
+                state.requeue(transfer.request);
+                state.release_client(balance, transfer.sender);
+            }
+            return Ok(());
+        }
+
+        let mut tx = transfer.tx.clone();
+        self.bump_fees(&mut tx).await?;
+        let new_hash = transfer
+            .sender
+            .send_transaction(tx.clone(), None)
+            .await?
+            .tx_hash();
+        tracing::info!("Replaced stuck transfer tx_hash={tx_hash:?} with {new_hash:?}");
+
+        let mut state = self.state.write().await;
+        // If the transfer was completed in the meantime, the replacement can
+        // never be mined, since it uses the same nonce.
+        if state.remove_inflight(tx_hash).is_some() {
+            state.update_request(transfer.request.id(), |status| {
+                status.tx_hash = Some(new_hash);
+            });
+            let mut replaced = transfer.replaced.clone();
+            replaced.push(tx_hash);
+            state.insert_inflight(
+                new_hash,
+                Transfer {
+                    tx,
+                    replaced,
+                    timestamp: Instant::now(),
+                    ..transfer
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Raise the fees of `tx` so that nodes accept it as a replacement for the
+    /// original transaction.
+    ///
+    /// Fails if the raised fees would exceed the fee cap.
+    async fn bump_fees(&self, tx: &mut TypedTransaction) -> Result<()> {
+        // Nodes require replacements to pay at least 10% more.
+        let bump = |fee: Option<&U256>| fee.copied().unwrap_or_default() * 125 / 100 + 1;
+        let current = self.fee_policy.estimate(&self.provider).await?;
+        match (tx, current) {
+            (
+                TypedTransaction::Eip1559(tx),
+                Fees::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                },
+            ) => {
+                let max_fee = bump(tx.max_fee_per_gas.as_ref()).max(max_fee_per_gas);
+                self.fee_policy.check(max_fee)?;
+                tx.max_fee_per_gas = Some(max_fee);
+                tx.max_priority_fee_per_gas = Some(
+                    bump(tx.max_priority_fee_per_gas.as_ref())
+                        .max(max_priority_fee_per_gas)
+                        .min(max_fee),
+                );
+            }
+            (tx, current) => {
+                let bumped = bump(tx.gas_price().as_ref()).max(current.max_fee_per_gas());
+                self.fee_policy.check(bumped)?;
+                tx.set_gas_price(bumped);
+            }
+        }
+        Ok(())
+    }
+
+    async fn monitor_balances(&self) -> Result<()> {
+        if !self.config.enable_funding {
+            return Ok(());
+        }
+        loop {
+            async_std::task::sleep(self.config.rebalance_interval).await;
+            if let Err(err) = self.rebalance().await {
+                tracing::error!("Failed to rebalance faucet wallets: {err}");
+            }
+        }
+    }
+
+    /// Sweep the funds of retired wallets and fund the wallets with
+    /// significantly less than average balance.
+    async fn rebalance(&self) -> Result<()> {
+        self.sweep_retired_clients().await?;
+
+        let mut state = self.state.write().await;
+        // Wait until the previous round of funding is complete, otherwise we
+        // would compute the average without the clients being funded.
+        if !state.clients_being_funded.is_empty() {
+            tracing::info!("Funding still in progress, not rebalancing");
+            return Ok(());
+        }
+        state.queue_funding();
+        drop(state);
+        self.persist().await;
+        Ok(())
+    }
+
+    /// Transfer the funds of the wallets after the first `num_clients` to the
+    /// active wallet with the least balance.
+    ///
+    /// Token balances are swept too, before the native balance which pays
+    /// for their transfers. Wallets which are found empty are not queried
+    /// again, until they are removed from the pool again.
+    async fn sweep_retired_clients(&self) -> Result<()> {
+        // Don't sweep wallets which are being added to the pool.
+        let _guard = self.pool_lock.lock().await;
+        let Some(&(_, receiver)) = self.state.read().await.clients.priority.iter().min() else {
+            return Ok(());
+        };
+        let fees = self.fee_policy.fees(&self.provider).await?;
+        let gas = U256::from(NATIVE_TRANSFER_GAS);
+        let fee = gas * fees.max_fee_per_gas();
+        let token_gas = U256::from(self.config.token_gas_limit);
+        let token_fee = token_gas * fees.max_fee_per_gas();
+
+        let (num_clients, tokens) = {
+            let settings = self.settings.read().await;
+            let tokens: Vec<_> = settings.tokens.iter().map(|token| token.address).collect();
+            (settings.num_clients, tokens)
+        };
+        // Wallets removed by autoscaling may be beyond the retired ones.
+        let end = (num_clients + self.config.retired_clients)
+            .max(self.config.max_clients.unwrap_or_default());
+        // There may be fewer keystores than active and retired wallets.
+        let end = self
+            .wallets
+            .capacity()
+            .map_or(end, |capacity| end.min(capacity));
+        let retired = num_clients..end;
+        for index in retired {
+            let client = Self::derive_client(&self.wallets, &self.provider, self.chain_id, index)?;
+            let address = client.address();
+
+            // Skip wallets which were removed from the pool while busy, and
+            // wallets which are already empty.
+            {
+                let state = self.state.read().await;
+                if state.retiring.contains(&address) || state.swept.contains(&address) {
+                    continue;
+                }
+            }
+
+            // Skip wallets with a pending sweep.
+            let pending = self
+                .provider
+                .get_transaction_count(address, Some(BlockNumber::Pending.into()))
+                .await?;
+            let mined = self
+                .provider
+                .get_transaction_count(address, Some(BlockNumber::Latest.into()))
+                .await?;
+            if pending > mined {
+                continue;
+            }
+
+            let mut balance = self.balance(address).await?;
+            let mut token_balances = vec![];
+            for &token in &tokens {
+                let amount = erc20(token, client.clone())
+                    .balance_of(address)
+                    .call()
+                    .await?;
+                if !amount.is_zero() {
+                    token_balances.push((token, amount));
+                }
+            }
+            // Leave dust which is not worth the fee.
+            if token_balances.is_empty() && balance <= fee * 2 {
+                self.state.write().await.swept.insert(address);
+                continue;
+            }
+
+            for (token, amount) in token_balances {
+                if balance < token_fee {
+                    tracing::warn!(
+                        "Retired client {index} {address:?} cannot pay the fee to sweep {amount} of token {token:?}"
+                    );
+                    continue;
+                }
+                let tx = fees.transaction(
+                    token,
+                    U256::zero(),
+                    erc20(token, client.clone())
+                        .transfer(receiver, amount)
+                        .calldata(),
+                    token_gas,
+                );
+                match client.send_transaction(tx, None).await {
+                    Ok(tx) => {
+                        balance -= token_fee;
+                        tracing::info!(
+                            "Sweeping {amount} of token {token:?} from retired client {index} {address:?} to {receiver:?} hash={:?}",
+                            tx.tx_hash()
+                        );
+                    }
+                    Err(err) => tracing::error!(
+                        "Failed to sweep token {token:?} from retired client {index} {address:?}: {err}"
+                    ),
+                }
+            }
+
+            if balance <= fee * 2 {
+                continue;
+            }
+            let tx = fees.transaction(receiver, balance - fee, None, gas);
+            match client.send_transaction(tx, None).await {
+                Ok(tx) => tracing::info!(
+                    "Sweeping {} from retired client {index} {address:?} to {receiver:?} hash={:?}",
+                    balance - fee,
+                    tx.tx_hash()
+                ),
+                Err(err) => {
+                    tracing::error!("Failed to sweep retired client {index} {address:?}: {err}")
+                }
+            }
         }
         Ok(())
     }
@@ -565,6 +2641,33 @@ mod test {
     use super::*;
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use sequencer_utils::AnvilOptions;
+    use zkevm_contract_bindings::{
+        erc20_permit_mock::ERC20PermitMock, polygon_zk_evm_bridge::PolygonZkEVMBridge, Deploy,
+        TestPolygonContracts,
+    };
+
+    /// Create a faucet with a single client on an anvil node which only mines
+    /// blocks on demand, and with an immediate transaction timeout.
+    async fn faucet_with_manual_mining(url: Url) -> Result<Faucet> {
+        let mut ws_url = url.clone();
+        ws_url.set_scheme("ws").unwrap();
+
+        let options = Options {
+            num_clients: 1,
+            provider_url_ws: ws_url,
+            provider_url_http: url,
+            transaction_timeout: Duration::from_secs(0),
+            ..Default::default()
+        };
+
+        let (_, receiver) = async_std::channel::unbounded();
+        Faucet::create(options, receiver).await
+    }
+
+    async fn mine_block(faucet: &Faucet) -> Result<()> {
+        faucet.provider.request::<_, U256>("evm_mine", ()).await?;
+        Ok(())
+    }
 
     #[async_std::test]
     async fn test_faucet_inflight_timeouts() -> Result<()> {
@@ -575,38 +2678,395 @@ mod test {
             .block_time(Duration::from_secs(3600))
             .spawn()
             .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+        let recipient = Address::random();
 
+        // Manually execute a transfer.
+        let transfer = TransferRequest::faucet(recipient, amount);
+        faucet.request_transfer(transfer).await;
+        let tx_hash = faucet.execute_transfer().await?;
+
+        // Assert that there is an inflight transaction.
+        let original = faucet.state.read().await.inflight[&tx_hash].clone();
+
+        // Process the timed out transaction. It is still pending, so it is
+        // replaced instead of being sent again.
+        faucet.process_transaction_timeouts().await?;
+        {
+            let state = faucet.state.read().await;
+            assert!(state.transfer_queue.is_empty());
+            assert_eq!(state.inflight.len(), 1);
+            let (new_hash, replacement) = state.inflight.iter().next().unwrap();
+            assert_ne!(*new_hash, tx_hash);
+            assert_eq!(replacement.replaced, [tx_hash]);
+            // Both transactions belong to the same transfer.
+            assert_eq!(state.inflight_key(tx_hash), Some(*new_hash));
+            assert_eq!(state.inflight_key(*new_hash), Some(*new_hash));
+            assert_eq!(replacement.tx.nonce(), original.tx.nonce());
+            assert!(replacement.tx.gas_price() > original.tx.gas_price());
+
+            // The client is still busy with the replacement.
+            assert!(state.clients.clients.is_empty());
+        }
+
+        // Assert that the transaction was not executed.
+        assert_eq!(faucet.balance(recipient).await?, 0.into());
+
+        // Once the replacement is mined the transfer completes, paying the
+        // recipient exactly once.
+        mine_block(&faucet).await?;
+        faucet.process_transaction_timeouts().await?;
+        let mut state = faucet.state.write().await;
+        assert!(state.inflight.is_empty());
+        assert!(state.transfer_queue.is_empty());
+        // Assert that the client is available again.
+        state.clients.pop().unwrap();
+        drop(state);
+        assert_eq!(faucet.balance(recipient).await?, amount);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_timeout_mined_late() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+        let recipient = Address::random();
+
+        faucet
+            .request_transfer(TransferRequest::faucet(recipient, amount))
+            .await;
+        faucet.execute_transfer().await?;
+
+        // The transaction is mined, but the faucet times out before it sees
+        // the receipt.
+        mine_block(&faucet).await?;
+        faucet.process_transaction_timeouts().await?;
+
+        let mut state = faucet.state.write().await;
+        assert!(state.inflight.is_empty());
+        // The transfer is not sent again.
+        assert!(state.transfer_queue.is_empty());
+        state.clients.pop().unwrap();
+        drop(state);
+        assert_eq!(faucet.balance(recipient).await?, amount);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_timeout_mined_during_check() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+        let recipient = Address::random();
+
+        faucet
+            .request_transfer(TransferRequest::faucet(recipient, amount))
+            .await;
+        let tx_hash = faucet.execute_transfer().await?;
+        let transfer = faucet.state.read().await.inflight[&tx_hash].clone();
+
+        // The transfer is mined after the faucet looked for its receipt, but
+        // before it checked the nonce.
+        assert!(faucet.mined_receipt(tx_hash, &transfer).await?.is_none());
+        mine_block(&faucet).await?;
+        faucet.process_unmined_transfer(tx_hash, transfer).await?;
+
+        // The transfer is completed instead of being sent again.
+        let mut state = faucet.state.write().await;
+        assert!(state.inflight.is_empty());
+        assert!(state.transfer_queue.is_empty());
+        state.clients.pop().unwrap();
+        drop(state);
+        assert_eq!(faucet.balance(recipient).await?, amount);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_sweep_inflight() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+        let recipient = Address::random();
+
+        faucet
+            .request_transfer(TransferRequest::faucet(recipient, amount))
+            .await;
+        let tx_hash = faucet.execute_transfer().await?;
+
+        // A pending transfer is left alone.
+        faucet.sweep_inflight().await;
+        assert_eq!(faucet.state.read().await.inflight.len(), 1);
+
+        // Once mined, a sweep completes the transfer even though the faucet
+        // never saw the block, for instance while reconnecting.
+        mine_block(&faucet).await?;
+        faucet.sweep_inflight().await;
+        let state = faucet.state.read().await;
+        assert!(state.inflight.is_empty());
+        assert_eq!(state.inflight_key(tx_hash), None);
+        assert!(state.transfer_queue.is_empty());
+        assert_eq!(state.clients.clients.len(), 1);
+        drop(state);
+        assert_eq!(faucet.balance(recipient).await?, amount);
+
+        // Blocks with only foreign transactions are ignored.
+        faucet.process_block(vec![H256::random(), tx_hash]).await;
+        assert_eq!(faucet.balance(recipient).await?, amount);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_timeout_nonce_reused() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+        let recipient = Address::random();
+
+        let transfer = TransferRequest::faucet(recipient, amount);
+        faucet.request_transfer(transfer).await;
+        let tx_hash = faucet.execute_transfer().await?;
+
+        // Replace the transfer with another transaction using the same nonce,
+        // as if the wallet had been used outside of the faucet.
+        let Transfer { sender, tx, .. } = faucet.state.read().await.inflight[&tx_hash].clone();
+        let other = TransactionRequest::pay(sender.address(), 0)
+            .nonce(*tx.nonce().unwrap())
+            .gas_price(tx.gas_price().unwrap() * 2);
+        sender.send_transaction(other, None).await?;
+        mine_block(&faucet).await?;
+
+        // The transfer can never be mined, so it is queued again.
+        faucet.process_transaction_timeouts().await?;
+        let mut state = faucet.state.write().await;
+        assert!(state.inflight.is_empty());
+        assert_eq!(state.transfer_queue, [transfer]);
+        state.clients.pop().unwrap();
+        drop(state);
+        assert_eq!(faucet.balance(recipient).await?, 0.into());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_admin_controls() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let faucet = faucet_with_manual_mining(anvil.url()).await?;
+        let amount = faucet.config.faucet_grant_amount;
+
+        // Nothing is sent while paused.
+        let request = faucet.track_request(Address::random(), Asset::Native).await;
+        faucet
+            .request_transfer(TransferRequest::faucet(request.to, amount).with_id(request.id))
+            .await;
+        faucet.set_paused(true).await;
+        assert!(matches!(
+            faucet.execute_transfer().await,
+            Err(TransferError::Paused)
+        ));
+
+        // Draining the queue fails the queued requests.
+        assert_eq!(faucet.drain_queue().await, 1);
+        assert!(faucet.state.read().await.transfer_queue.is_empty());
+        assert_eq!(
+            faucet.request_status(request.id).await.unwrap().state,
+            RequestState::Failed
+        );
+        faucet.set_paused(false).await;
+
+        // A busy wallet removed from the pool does not become available again.
+        faucet.resize(2).await?;
+        let retired =
+            Faucet::derive_client(&faucet.wallets, &faucet.provider, faucet.chain_id, 1)?.address();
+        let mut tx_hashes = vec![];
+        for _ in 0..2 {
+            faucet
+                .request_transfer(TransferRequest::faucet(Address::random(), amount))
+                .await;
+            tx_hashes.push(faucet.execute_transfer().await?);
+        }
+        faucet.resize(1).await?;
+        assert!(faucet.state.read().await.retiring.contains(&retired));
+        mine_block(&faucet).await?;
+        for tx_hash in tx_hashes {
+            faucet.handle_receipt(tx_hash).await?;
+        }
+        let state = faucet.state.read().await;
+        assert!(state.retiring.is_empty());
+        assert_eq!(state.clients.clients.len(), 1);
+        assert!(!state.clients.clients.contains_key(&retired));
+        drop(state);
+        assert_eq!(faucet.settings().await.num_clients, 1);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_queue_limits() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default()
+            .block_time(Duration::from_secs(3600))
+            .spawn()
+            .await;
+        let mut faucet = faucet_with_manual_mining(anvil.url()).await?;
+        faucet.config.max_queue_len = 2;
+        let amount = faucet.config.faucet_grant_amount;
+
+        // A second request for the same recipient and asset is merged.
+        let recipient = Address::random();
+        let Enqueued::New(request) = faucet
+            .enqueue_request(recipient, Asset::Native, Source::Web)
+            .await?
+        else {
+            panic!("first request was merged");
+        };
+        assert_eq!(
+            faucet
+                .enqueue_request(recipient, Asset::Native, Source::Web)
+                .await?,
+            Enqueued::Merged(request.id)
+        );
+
+        // Requests over capacity are rejected.
+        faucet
+            .enqueue_request(Address::random(), Asset::Native, Source::Web)
+            .await?;
+        assert_eq!(
+            faucet
+                .enqueue_request(Address::random(), Asset::Native, Source::Web)
+                .await,
+            Err(QueueFull)
+        );
+
+        // Once the first request is sent there is room again, and a new
+        // request for the same recipient is not merged.
+        faucet
+            .request_transfer(TransferRequest::faucet(recipient, amount).with_id(request.id))
+            .await;
+        faucet.execute_transfer().await?;
+        let Enqueued::New(second) = faucet
+            .enqueue_request(recipient, Asset::Native, Source::Web)
+            .await?
+        else {
+            panic!("request was merged into a submitted request");
+        };
+        assert_ne!(second.id, request.id);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_fee_cap() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
         let mut ws_url = anvil.url();
         ws_url.set_scheme("ws").unwrap();
 
+        // Required funds cover the value and the gas at the max fee.
+        let transfer = TransferRequest::faucet(Address::random(), 100.into());
+        let gas_limit = transfer.gas_limit(0);
+        assert_eq!(gas_limit, NATIVE_TRANSFER_GAS.into());
+        assert_eq!(
+            transfer.required_funds(gas_limit, 2.into()),
+            (100 + 2 * NATIVE_TRANSFER_GAS).into()
+        );
+
+        // Anvil charges more than 1 wei per gas, so no transfers are sent.
         let options = Options {
             num_clients: 1,
             provider_url_ws: ws_url,
             provider_url_http: anvil.url(),
-            transaction_timeout: Duration::from_secs(0),
+            fee_cap: Some(1.into()),
             ..Default::default()
         };
-
         let (_, receiver) = async_std::channel::unbounded();
-        let faucet = Faucet::create(options.clone(), receiver).await?;
-
-        // Manually execute a transfer.
-        let transfer = TransferRequest::faucet(Address::zero(), options.faucet_grant_amount);
+        let faucet = Faucet::create(options, receiver).await?;
         faucet.request_transfer(transfer).await;
-        faucet.execute_transfer().await?;
+        assert!(matches!(
+            faucet.execute_transfer().await,
+            Err(TransferError::Fees(FeeError::TooHigh { .. }))
+        ));
+        assert_eq!(faucet.queue_depth().await, 1);
 
-        // Assert that there is an inflight transaction.
-        assert!(!faucet.state.read().await.inflight.is_empty());
+        Ok(())
+    }
 
-        // Process the timed out transaction.
-        faucet.process_transaction_timeouts().await?;
-        assert!(faucet.state.read().await.inflight.is_empty());
+    #[async_std::test]
+    async fn test_faucet_polling_fallback() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
 
-        // Assert that the client is available again.
-        faucet.state.write().await.clients.pop().unwrap();
+        let anvil = AnvilOptions::default().spawn().await;
 
-        // Assert that the transaction was not executed.
-        assert_eq!(faucet.balance(Address::zero()).await?, 0.into());
+        // Nothing listens on this port, so subscribing over the websocket
+        // fails and the faucet has to poll.
+        let ws_url = format!("ws://localhost:{}", portpicker::pick_unused_port().unwrap());
+        let options = Options {
+            num_clients: 1,
+            provider_url_ws: ws_url.parse()?,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+
+        let (sender, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        let _handle = faucet.clone().start().await;
+
+        let recipient = Address::random();
+        let request = faucet.track_request(recipient, Asset::Native).await;
+        let id = request.id;
+        sender.send(request).await?;
+        while faucet.request_status(id).await.unwrap().state != RequestState::Confirmed {
+            async_std::task::sleep(Duration::from_secs(1)).await;
+        }
+
+        assert_eq!(
+            faucet.balance(recipient).await?,
+            options.faucet_grant_amount
+        );
+        // The receipt was processed, so the client is available again.
+        let state = faucet.state.read().await;
+        assert!(state.inflight.is_empty());
+        assert_eq!(state.clients.clients.len(), 1);
 
         Ok(())
     }
@@ -651,4 +3111,424 @@ mod test {
 
         Ok(())
     }
+
+    /// Execute and complete all queued transfers.
+    async fn execute_queued_transfers(faucet: &Faucet) -> Result<()> {
+        while !faucet.state.read().await.transfer_queue.is_empty() {
+            let tx_hash = faucet.execute_transfer().await?;
+            faucet.handle_receipt(tx_hash).await?;
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_autoscaling() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            num_clients: 1,
+            max_clients: Some(3),
+            target_queue_wait: Duration::ZERO,
+            scale_down_idle_period: Duration::ZERO,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+        // New wallets could not be funded, nor removed ones swept, without
+        // funding.
+        let (_, receiver) = async_std::channel::unbounded();
+        assert!(Faucet::create(
+            Options {
+                enable_funding: false,
+                ..options.clone()
+            },
+            receiver
+        )
+        .await
+        .is_err());
+
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options, receiver).await?;
+        let second =
+            Faucet::derive_client(&faucet.wallets, &faucet.provider, faucet.chain_id, 1)?.address();
+
+        // The pool does not shrink below the minimum.
+        faucet.scale_pool().await;
+        assert_eq!(faucet.settings().await.num_clients, 1);
+
+        // The pool grows while requests wait longer than the target.
+        let request = faucet.track_request(Address::random(), Asset::Native).await;
+        let transfer = faucet.transfer_request(&request).await.unwrap();
+        faucet.request_transfer(transfer).await;
+        async_std::task::sleep(Duration::from_millis(10)).await;
+        faucet.scale_pool().await;
+        assert_eq!(faucet.settings().await.num_clients, 2);
+
+        // Once the queue is empty, the pool shrinks again, and the funds of
+        // the removed wallet are swept back.
+        execute_queued_transfers(&faucet).await?;
+        faucet.scale_pool().await;
+        assert_eq!(faucet.settings().await.num_clients, 1);
+        faucet.rebalance().await?;
+        assert!(faucet.balance(second).await? < parse_ether("0.01")?);
+
+        // The decisions are reported in the faucet info.
+        let scaling = faucet.info().await?.scaling.unwrap();
+        assert_eq!((scaling.min_clients, scaling.max_clients), (1, 3));
+        assert_eq!(scaling.queue_wait, None);
+        let sizes: Vec<_> = scaling
+            .decisions
+            .iter()
+            .map(|decision| (decision.from, decision.to))
+            .collect();
+        assert_eq!(sizes, [(1, 2), (2, 1)]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_grow_clients() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            // 10 clients are already funded with anvil
+            num_clients: 10,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        assert!(faucet.state.read().await.transfer_queue.is_empty());
+
+        // Restart with more clients, the new clients are funded before they
+        // become available.
+        let options = Options {
+            num_clients: 12,
+            ..options
+        };
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        {
+            let state = faucet.state.read().await;
+            assert_eq!(state.clients_being_funded.len(), 2);
+            assert_eq!(state.clients.clients.len(), 10);
+            assert!(state
+                .transfer_queue
+                .iter()
+                .all(|transfer| matches!(transfer, TransferRequest::Funding { .. })));
+        }
+
+        execute_queued_transfers(&faucet).await?;
+
+        let state = faucet.state.read().await;
+        assert!(state.clients_being_funded.is_empty());
+        assert_eq!(state.clients.clients.len(), 12);
+        // All clients now have at least 80% of the average balance.
+        let balances: Vec<_> = state.clients.priority.iter().map(|(b, _)| *b).collect();
+        let total = balances.iter().fold(U256::zero(), |total, b| total + b);
+        let average = total / balances.len();
+        for balance in balances {
+            assert!(balance >= average * 7 / 10, "{balance} {average}");
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_shrink_clients() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            num_clients: 12,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        execute_queued_transfers(&faucet).await?;
+
+        let retired: Vec<_> = (10..12)
+            .map(|index| {
+                Faucet::derive_client(&faucet.wallets, &faucet.provider, faucet.chain_id, index)
+                    .map(|client| client.address())
+            })
+            .collect::<Result<_>>()?;
+        for address in &retired {
+            assert!(faucet.balance(*address).await? > parse_ether(1)?);
+        }
+
+        // One of the retired clients holds tokens.
+        let holder = Faucet::derive_client(&faucet.wallets, &faucet.provider, faucet.chain_id, 11)?;
+        let (_, token) = ERC20PermitMock::deploy_contract(
+            &holder,
+            (
+                "Matic Token".to_string(),
+                "MATIC".to_string(),
+                holder.address(),
+                parse_ether(1000)?,
+            ),
+        )
+        .await;
+
+        // Restart with fewer clients, the funds of the retired clients are
+        // swept back into the active ones.
+        let options = Options {
+            num_clients: 10,
+            retired_clients: 2,
+            tokens: vec![format!("MATIC:{:?}:10", token.address()).parse()?],
+            ..options
+        };
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        assert_eq!(faucet.state.read().await.clients.clients.len(), 10);
+
+        faucet.rebalance().await?;
+        for address in &retired {
+            // Only dust which is not worth the fee for another transfer is left.
+            assert!(faucet.balance(*address).await? < parse_ether("0.01")?);
+        }
+        assert_eq!(token.balance_of(holder.address()).call().await?, 0.into());
+
+        // Rebalancing again does not sweep anything, and finds the retired
+        // clients empty, so that later sweeps skip them.
+        faucet.rebalance().await?;
+        let state = faucet.state.read().await;
+        for address in &retired {
+            assert!(state.swept.contains(address));
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_token_grant() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let mut options = Options {
+            num_clients: 2,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+
+        // Deploy a token held by the second faucet client.
+        let provider = Provider::<Http>::try_from(anvil.url().to_string())?;
+        let chain_id = provider.get_chainid().await?.as_u64();
+        let wallets = Wallets::load(&options).await?;
+        let holder = Faucet::derive_client(&wallets, &provider, chain_id, 1)?;
+        let (_, token) = ERC20PermitMock::deploy_contract(
+            &holder,
+            (
+                "Matic Token".to_string(),
+                "MATIC".to_string(),
+                holder.address(),
+                parse_ether(1000)?,
+            ),
+        )
+        .await;
+        options.tokens = vec![format!("MATIC:{:?}:10", token.address()).parse()?];
+
+        let (sender, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+
+        // The request is granted by the client holding the token, even though
+        // both clients have the same native balance.
+        let recipient = Address::random();
+        sender
+            .send(faucet.track_request(recipient, Asset::parse("matic")).await)
+            .await?;
+        let request = faucet.faucet_receiver.read().await.recv().await?;
+        let transfer = faucet.transfer_request(&request).await.unwrap();
+        assert_eq!(
+            transfer,
+            TransferRequest::token(recipient, token.address(), parse_ether(10)?)
+                .with_id(request.id)
+        );
+        faucet.request_transfer(transfer).await;
+        let tx_hash = faucet.execute_transfer().await?;
+        faucet.handle_receipt(tx_hash).await?;
+
+        assert_eq!(token.balance_of(recipient).call().await?, parse_ether(10)?);
+        assert_eq!(faucet.balance(recipient).await?, 0.into());
+        assert_eq!(
+            faucet
+                .state
+                .read()
+                .await
+                .clients
+                .token_balance(holder.address(), token.address()),
+            parse_ether(990)?
+        );
+
+        // The request can be followed until it is confirmed.
+        let status = faucet.request_status(request.id).await.unwrap();
+        assert_eq!(status.state, RequestState::Confirmed);
+        assert_eq!(status.sender, Some(holder.address()));
+        assert_eq!(status.tx_hash, Some(tx_hash));
+        assert_eq!(status.receipt_status, Some(1));
+
+        // Unknown tokens are not granted.
+        let request = FaucetRequest::new(request.id + 1, recipient, Asset::parse("DAI"));
+        assert_eq!(faucet.transfer_request(&request).await, None);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_bridge_deposit() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let l1 = TestPolygonContracts::deploy(anvil.url(), "http://dummy:1234").await;
+
+        // Simulate the L2 side of the bridge on the same node, with a second
+        // bridge which shares the global exit root contract of the L1, so that
+        // deposits are claimable right away.
+        let deployer = l1.clients.deployer.provider.clone();
+        let (_, l2_bridge) = PolygonZkEVMBridge::deploy_contract(&deployer, ()).await;
+        l2_bridge
+            .initialize(1, l1.global_exit_root.address(), l1.rollup.address())
+            .send()
+            .await?
+            .await?;
+
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            num_clients: 1,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            bridge_address: Some(l1.bridge.address()),
+            bridge_l2_provider_url: Some(anvil.url()),
+            bridge_l2_address: Some(l2_bridge.address()),
+            ..Default::default()
+        };
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+
+        let recipient = Address::random();
+        let request = faucet.track_request(recipient, Asset::Native).await;
+        let transfer = faucet.transfer_request(&request).await.unwrap();
+        faucet.request_transfer(transfer).await;
+        let tx_hash = faucet.execute_transfer().await?;
+        faucet.handle_receipt(tx_hash).await?;
+
+        // The grant is deposited into the bridge instead of sent to the
+        // recipient.
+        assert_eq!(faucet.balance(recipient).await?, 0.into());
+        assert_eq!(
+            faucet.balance(l1.bridge.address()).await?,
+            options.faucet_grant_amount
+        );
+        let status = faucet.request_status(request.id).await.unwrap();
+        assert_eq!(status.state, RequestState::Confirmed);
+        let deposit = status.deposit.unwrap();
+        assert_eq!(deposit.deposit_count, 0);
+        assert_eq!(deposit.state, DepositState::Pending);
+
+        // The deposit is claimable once the L2 knows its global exit root.
+        faucet.update_deposits().await;
+        let deposit = faucet
+            .request_status(request.id)
+            .await
+            .unwrap()
+            .deposit
+            .unwrap();
+        assert_eq!(deposit.state, DepositState::Claimable);
+
+        // Tokens can't be granted through the bridge.
+        let options = Options {
+            tokens: vec![format!("MATIC:{:?}:10", l1.matic.address()).parse()?],
+            ..options
+        };
+        let (_, receiver) = async_std::channel::unbounded();
+        assert!(Faucet::create(options, receiver).await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_restart() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let dir = tempfile::tempdir()?;
+
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            num_clients: 1,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            state_file: Some(dir.path().join("faucet.json")),
+            ..Default::default()
+        };
+
+        // Send one transfer and queue another one, then stop the faucet before
+        // it processes the receipt.
+        let recipient = Address::random();
+        let transfer = TransferRequest::faucet(recipient, options.faucet_grant_amount);
+        {
+            let (_, receiver) = async_std::channel::unbounded();
+            let faucet = Faucet::create(options.clone(), receiver).await?;
+            faucet.request_transfer(transfer).await;
+            faucet.execute_transfer().await?;
+            faucet.request_transfer(transfer).await;
+        }
+
+        // Anvil mines the transfer instantly, so after a restart it must not
+        // be tracked or sent again, but the queued transfer is resumed.
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        {
+            let state = faucet.state.read().await;
+            assert!(state.inflight.is_empty());
+            assert_eq!(state.transfer_queue, [transfer]);
+            assert_eq!(state.clients.clients.len(), 1);
+        }
+        assert_eq!(
+            faucet.balance(recipient).await?,
+            options.faucet_grant_amount
+        );
+
+        // Executing the resumed transfer pays the recipient a second time,
+        // as requested.
+        let tx_hash = faucet.execute_transfer().await?;
+        faucet.handle_receipt(tx_hash).await?;
+        assert_eq!(
+            faucet.balance(recipient).await?,
+            options.faucet_grant_amount * 2
+        );
+        assert_eq!(
+            PersistedState::load(options.state_file.unwrap()).await?,
+            PersistedState::default()
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,236 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Fees of faucet transactions.
+//!
+//! Fees are either fixed by the options or estimated by the node, and are always subject to an
+//! optional hard cap. While the fees exceed the cap the faucet does not send transfers.
+use crate::Options;
+use clap::ValueEnum;
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes,
+        Eip1559TransactionRequest, TransactionRequest, U256,
+    },
+};
+use thiserror::Error;
+
+/// The gas limit of a transfer of the native asset.
+pub const NATIVE_TRANSFER_GAS: u64 = 21_000;
+
+/// The type of the transactions sent by the faucet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TxType {
+    /// EIP-1559 transactions if the latest block has a base fee, legacy
+    /// transactions otherwise.
+    #[default]
+    Auto,
+    Eip1559,
+    Legacy,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum FeeError {
+    #[error("fee per gas {fee} exceeds the cap of {cap}")]
+    TooHigh { fee: U256, cap: U256 },
+    #[error("failed to estimate fees: {0}")]
+    Estimate(String),
+}
+
+/// The fees paid by a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fees {
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+    Legacy {
+        gas_price: U256,
+    },
+}
+
+impl Fees {
+    /// The most a transaction pays per unit of gas.
+    pub fn max_fee_per_gas(&self) -> U256 {
+        match self {
+            Self::Eip1559 {
+                max_fee_per_gas, ..
+            } => *max_fee_per_gas,
+            Self::Legacy { gas_price } => *gas_price,
+        }
+    }
+
+    /// A transaction paying these fees.
+    pub fn transaction(
+        &self,
+        to: Address,
+        value: U256,
+        data: Option<Bytes>,
+        gas: U256,
+    ) -> TypedTransaction {
+        match *self {
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let mut tx = Eip1559TransactionRequest::new()
+                    .to(to)
+                    .value(value)
+                    .gas(gas)
+                    .max_fee_per_gas(max_fee_per_gas)
+                    .max_priority_fee_per_gas(max_priority_fee_per_gas);
+                tx.data = data;
+                tx.into()
+            }
+            Self::Legacy { gas_price } => {
+                let mut tx = TransactionRequest::new()
+                    .to(to)
+                    .value(value)
+                    .gas(gas)
+                    .gas_price(gas_price);
+                tx.data = data;
+                tx.into()
+            }
+        }
+    }
+}
+
+/// How the faucet determines the fees of its transactions.
+#[derive(Clone, Debug)]
+pub struct FeePolicy {
+    eip1559: bool,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    cap: Option<U256>,
+}
+
+impl FeePolicy {
+    /// The fee policy of a faucet, detecting the transaction type if needed.
+    pub async fn new<M: Middleware>(options: &Options, provider: &M) -> Result<Self, FeeError> {
+        let eip1559 = match options.tx_type {
+            TxType::Eip1559 => true,
+            TxType::Legacy => false,
+            TxType::Auto => provider
+                .get_block(BlockNumber::Latest)
+                .await
+                .map_err(|err| FeeError::Estimate(err.to_string()))?
+                .and_then(|block| block.base_fee_per_gas)
+                .is_some(),
+        };
+        Ok(Self {
+            eip1559,
+            max_fee_per_gas: options.max_fee_per_gas,
+            max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+            cap: options.fee_cap,
+        })
+    }
+
+    /// The fees of a new transaction, unless they exceed the cap.
+    pub async fn fees<M: Middleware>(&self, provider: &M) -> Result<Fees, FeeError> {
+        let fees = self.estimate(provider).await?;
+        self.check(fees.max_fee_per_gas())?;
+        Ok(fees)
+    }
+
+    /// The fees of a new transaction, ignoring the cap.
+    pub async fn estimate<M: Middleware>(&self, provider: &M) -> Result<Fees, FeeError> {
+        let estimate = |err: M::Error| FeeError::Estimate(err.to_string());
+        if !self.eip1559 {
+            let gas_price = match self.max_fee_per_gas {
+                Some(gas_price) => gas_price,
+                None => provider.get_gas_price().await.map_err(estimate)?,
+            };
+            return Ok(Fees::Legacy { gas_price });
+        }
+        let (max_fee_per_gas, max_priority_fee_per_gas) =
+            match (self.max_fee_per_gas, self.max_priority_fee_per_gas) {
+                (Some(max_fee), Some(priority_fee)) => (max_fee, priority_fee),
+                (max_fee, priority_fee) => {
+                    let (estimated_max_fee, estimated_priority_fee) = provider
+                        .estimate_eip1559_fees(None)
+                        .await
+                        .map_err(estimate)?;
+                    (
+                        max_fee.unwrap_or(estimated_max_fee),
+                        priority_fee.unwrap_or(estimated_priority_fee),
+                    )
+                }
+            };
+        Ok(Fees::Eip1559 {
+            max_fee_per_gas,
+            // The priority fee is part of the max fee.
+            max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas),
+        })
+    }
+
+    /// Check a fee per gas against the cap.
+    pub fn check(&self, fee: U256) -> Result<(), FeeError> {
+        match self.cap {
+            Some(cap) if fee > cap => Err(FeeError::TooHigh { fee, cap }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::utils::parse_units;
+
+    fn gwei(amount: u64) -> U256 {
+        parse_units(amount, "gwei").unwrap().into()
+    }
+
+    #[test]
+    fn test_fee_cap() {
+        let policy = FeePolicy {
+            eip1559: true,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            cap: Some(gwei(100)),
+        };
+        policy.check(gwei(100)).unwrap();
+        assert_eq!(
+            policy.check(gwei(101)),
+            Err(FeeError::TooHigh {
+                fee: gwei(101),
+                cap: gwei(100)
+            })
+        );
+
+        let uncapped = FeePolicy {
+            cap: None,
+            ..policy
+        };
+        uncapped.check(U256::MAX).unwrap();
+    }
+
+    #[test]
+    fn test_fee_transactions() {
+        let to = Address::random();
+        let fees = Fees::Eip1559 {
+            max_fee_per_gas: gwei(30),
+            max_priority_fee_per_gas: gwei(2),
+        };
+        let tx = fees.transaction(to, 1.into(), None, NATIVE_TRANSFER_GAS.into());
+        let TypedTransaction::Eip1559(tx) = tx else {
+            panic!("expected an EIP-1559 transaction, got {tx:?}");
+        };
+        assert_eq!(tx.max_fee_per_gas, Some(gwei(30)));
+        assert_eq!(tx.max_priority_fee_per_gas, Some(gwei(2)));
+        assert_eq!(tx.gas, Some(NATIVE_TRANSFER_GAS.into()));
+
+        let fees = Fees::Legacy {
+            gas_price: gwei(30),
+        };
+        let tx = fees.transaction(to, 1.into(), None, NATIVE_TRANSFER_GAS.into());
+        assert!(matches!(tx, TypedTransaction::Legacy(_)));
+        assert_eq!(tx.gas_price(), Some(gwei(30)));
+        assert_eq!(fees.max_fee_per_gas(), gwei(30));
+    }
+}
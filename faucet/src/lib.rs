@@ -8,8 +8,43 @@
 mod faucet;
 pub(crate) use crate::faucet::*;
 
+mod audit;
+pub use audit::*;
+
+mod bridge;
+pub use bridge::*;
+
+mod chains;
+
+mod fees;
+pub use fees::*;
+
+mod pow;
+pub use pow::*;
+
+mod rate_limit;
+pub(crate) use rate_limit::*;
+
+mod scaling;
+pub use scaling::*;
+
+mod signature;
+pub use signature::*;
+
+mod store;
+pub(crate) use store::*;
+
+mod token;
+pub use token::*;
+
+mod wallets;
+pub use wallets::*;
+
 mod web;
 pub(crate) use web::*;
 
+mod chat;
+pub use chat::*;
+
 mod discord;
 pub use discord::*;
@@ -0,0 +1,293 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Proof-of-work challenges for web requests.
+//!
+//! A client first gets a challenge, consisting of a random nonce and a difficulty. To request
+//! funds it then has to find a solution, a number such that
+//!
+//! ```text
+//! keccak256(nonce ++ recipient ++ solution as 8 big endian bytes)
+//! ```
+//!
+//! starts with at least `difficulty` zero bits. The solution is bound to the recipient, so it can't
+//! be reused for another address, and each challenge can only be redeemed once.
+//!
+//! The difficulty grows by one bit, which doubles the expected work, every time the queue of the
+//! faucet doubles in length.
+use crate::Options;
+use ethers::{
+    types::{Address, H256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// How long a challenge can be redeemed after it was issued.
+const CHALLENGE_TTL: Duration = Duration::from_secs(600);
+
+/// The maximum number of outstanding challenges, so memory use is bounded.
+const MAX_CHALLENGES: usize = 100_000;
+
+/// The maximum number of bits added to the base difficulty when the queue is long.
+const MAX_EXTRA_DIFFICULTY: u32 = 12;
+
+/// A challenge to solve before requesting funds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Challenge {
+    pub nonce: H256,
+    /// The number of leading zero bits required in the hash of a solution.
+    pub difficulty: u32,
+    /// The number of seconds for which the challenge can be redeemed.
+    pub expires_in: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum PowError {
+    #[error("unknown or expired challenge")]
+    UnknownChallenge,
+    #[error("the solution does not meet the difficulty of the challenge")]
+    InsufficientWork,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Issued {
+    difficulty: u32,
+    expires: Instant,
+}
+
+/// The outstanding challenges of a faucet.
+#[derive(Clone, Debug)]
+pub struct Challenges {
+    base_difficulty: u32,
+    issued: HashMap<H256, Issued>,
+    /// Outstanding challenges, oldest first.
+    order: VecDeque<H256>,
+}
+
+impl Challenges {
+    /// The challenges of a faucet, if proof-of-work is enabled.
+    pub fn new(options: &Options) -> Option<Self> {
+        Some(Self {
+            base_difficulty: options.pow_difficulty?,
+            issued: Default::default(),
+            order: Default::default(),
+        })
+    }
+
+    /// The difficulty of a new challenge when `queue_depth` transfers are queued.
+    pub fn difficulty(&self, queue_depth: usize) -> u32 {
+        let extra = usize::BITS - queue_depth.leading_zeros();
+        self.base_difficulty + extra.min(MAX_EXTRA_DIFFICULTY)
+    }
+
+    /// Issue a new challenge.
+    pub fn issue(&mut self, queue_depth: usize) -> Challenge {
+        self.issue_at(queue_depth, Instant::now())
+    }
+
+    fn issue_at(&mut self, queue_depth: usize, now: Instant) -> Challenge {
+        self.prune(now);
+        if self.order.len() >= MAX_CHALLENGES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.issued.remove(&oldest);
+            }
+        }
+
+        let nonce = H256::random();
+        let difficulty = self.difficulty(queue_depth);
+        self.issued.insert(
+            nonce,
+            Issued {
+                difficulty,
+                expires: now + CHALLENGE_TTL,
+            },
+        );
+        self.order.push_back(nonce);
+        Challenge {
+            nonce,
+            difficulty,
+            expires_in: CHALLENGE_TTL.as_secs(),
+        }
+    }
+
+    /// Check a solution of the challenge `nonce` for `recipient`.
+    ///
+    /// The challenge is used up if the solution is valid.
+    pub fn redeem(
+        &mut self,
+        nonce: H256,
+        recipient: Address,
+        solution: u64,
+    ) -> Result<(), PowError> {
+        self.redeem_at(nonce, recipient, solution, Instant::now())
+    }
+
+    fn redeem_at(
+        &mut self,
+        nonce: H256,
+        recipient: Address,
+        solution: u64,
+        now: Instant,
+    ) -> Result<(), PowError> {
+        self.prune(now);
+        let issued = self.issued.get(&nonce).ok_or(PowError::UnknownChallenge)?;
+        if !verify(nonce, recipient, solution, issued.difficulty) {
+            return Err(PowError::InsufficientWork);
+        }
+        // The entry in `order` is removed when it expires.
+        self.issued.remove(&nonce);
+        Ok(())
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while let Some(nonce) = self.order.front() {
+            match self.issued.get(nonce) {
+                Some(issued) if issued.expires > now => break,
+                _ => {
+                    self.issued.remove(nonce);
+                    self.order.pop_front();
+                }
+            }
+        }
+    }
+}
+
+fn hash(nonce: H256, recipient: Address, solution: u64) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(32 + 20 + 8);
+    preimage.extend_from_slice(nonce.as_bytes());
+    preimage.extend_from_slice(recipient.as_bytes());
+    preimage.extend_from_slice(&solution.to_be_bytes());
+    keccak256(preimage)
+}
+
+fn leading_zeros(hash: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+/// Whether `solution` solves the challenge `nonce` with `difficulty` for `recipient`.
+pub fn verify(nonce: H256, recipient: Address, solution: u64, difficulty: u32) -> bool {
+    leading_zeros(&hash(nonce, recipient, solution)) >= difficulty
+}
+
+/// Find a solution of the challenge `nonce` with `difficulty` for `recipient`.
+///
+/// This is what clients have to do, it is provided for tests and for clients written in Rust.
+pub fn solve(nonce: H256, recipient: Address, difficulty: u32) -> u64 {
+    (0..)
+        .find(|solution| verify(nonce, recipient, *solution, difficulty))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn challenges(difficulty: u32) -> Challenges {
+        Challenges::new(&Options {
+            pow_difficulty: Some(difficulty),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pow_valid_solution() {
+        let mut challenges = challenges(8);
+        let recipient = Address::random();
+        let challenge = challenges.issue(0);
+        assert_eq!(challenge.difficulty, 8);
+
+        let solution = solve(challenge.nonce, recipient, challenge.difficulty);
+        challenges
+            .redeem(challenge.nonce, recipient, solution)
+            .unwrap();
+
+        // Each challenge can only be redeemed once.
+        assert_eq!(
+            challenges.redeem(challenge.nonce, recipient, solution),
+            Err(PowError::UnknownChallenge)
+        );
+    }
+
+    #[test]
+    fn test_pow_invalid_solution() {
+        let mut challenges = challenges(8);
+        let recipient = Address::random();
+        let challenge = challenges.issue(0);
+        let solution = solve(challenge.nonce, recipient, challenge.difficulty);
+
+        // The solution is bound to the recipient.
+        let other = Address::random();
+        let stolen = (0..)
+            .find(|solution| {
+                verify(challenge.nonce, recipient, *solution, challenge.difficulty)
+                    && !verify(challenge.nonce, other, *solution, challenge.difficulty)
+            })
+            .unwrap();
+        assert_eq!(
+            challenges.redeem(challenge.nonce, other, stolen),
+            Err(PowError::InsufficientWork)
+        );
+
+        // A wrong solution does not use up the challenge.
+        let wrong = (0..)
+            .find(|solution| !verify(challenge.nonce, recipient, *solution, challenge.difficulty))
+            .unwrap();
+        assert_eq!(
+            challenges.redeem(challenge.nonce, recipient, wrong),
+            Err(PowError::InsufficientWork)
+        );
+
+        // Challenges which were not issued are rejected.
+        assert_eq!(
+            challenges.redeem(H256::random(), recipient, solution),
+            Err(PowError::UnknownChallenge)
+        );
+
+        challenges
+            .redeem(challenge.nonce, recipient, solution)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pow_expiry() {
+        let mut challenges = challenges(4);
+        let recipient = Address::random();
+        let start = Instant::now();
+        let challenge = challenges.issue_at(0, start);
+        let solution = solve(challenge.nonce, recipient, challenge.difficulty);
+        assert_eq!(
+            challenges.redeem_at(challenge.nonce, recipient, solution, start + CHALLENGE_TTL),
+            Err(PowError::UnknownChallenge)
+        );
+        assert!(challenges.issued.is_empty());
+        assert!(challenges.order.is_empty());
+    }
+
+    #[test]
+    fn test_pow_difficulty_adapts_to_queue() {
+        let challenges = challenges(10);
+        assert_eq!(challenges.difficulty(0), 10);
+        assert_eq!(challenges.difficulty(1), 11);
+        assert_eq!(challenges.difficulty(3), 12);
+        assert_eq!(challenges.difficulty(4), 13);
+        assert_eq!(challenges.difficulty(1000), 20);
+        assert_eq!(challenges.difficulty(usize::MAX), 10 + MAX_EXTRA_DIFFICULTY);
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Rate limiting of faucet requests.
+//!
+//! Each request is identified by a set of keys: the recipient address, and the HTTP client IP or
+//! the Discord user that made the request. A request is only accepted if none of its keys has been
+//! granted funds within the configured cooldown and if the total number of grants in the current
+//! window is below the global cap.
+use crate::Options;
+use ethers::types::Address;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    Address(Address),
+    Ip(String),
+    DiscordUser(u64),
+}
+
+/// A request accepted by [`RateLimiter::check`].
+///
+/// If the request is not served after all, the grant can be [rolled back](RateLimiter::rollback)
+/// so that it does not count against later requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Grant {
+    at: Instant,
+    /// The keys recorded for this grant, with the time of their previous grant.
+    keys: Vec<(RateLimitKey, Option<Instant>)>,
+    /// Whether this grant counts against the global cap.
+    in_window: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    address_cooldown: Duration,
+    ip_cooldown: Duration,
+    discord_user_cooldown: Duration,
+    max_grants_per_window: Option<usize>,
+    grant_window: Duration,
+    /// The time of the last accepted request for each key.
+    last_grant: HashMap<RateLimitKey, Instant>,
+    /// The times of the accepted requests in the current window, oldest first.
+    window: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(options: &Options) -> Self {
+        Self {
+            address_cooldown: options.address_cooldown,
+            ip_cooldown: options.ip_cooldown,
+            discord_user_cooldown: options.discord_user_cooldown,
+            max_grants_per_window: options.max_grants_per_window,
+            grant_window: options.grant_window,
+            last_grant: Default::default(),
+            window: Default::default(),
+        }
+    }
+
+    fn cooldown(&self, key: &RateLimitKey) -> Duration {
+        match key {
+            RateLimitKey::Address(_) => self.address_cooldown,
+            RateLimitKey::Ip(_) => self.ip_cooldown,
+            RateLimitKey::DiscordUser(_) => self.discord_user_cooldown,
+        }
+    }
+
+    /// Check whether a request identified by `keys` may be granted now.
+    ///
+    /// If the request is accepted it is recorded, so that it counts against the limits of later
+    /// requests. Otherwise, returns how long the caller has to wait before trying again.
+    pub fn check(&mut self, keys: &[RateLimitKey]) -> Result<Grant, Duration> {
+        self.check_at(keys, Instant::now())
+    }
+
+    /// Undo a grant of a request which was not served.
+    ///
+    /// Keys which have been granted again since are left alone.
+    pub fn rollback(&mut self, grant: Grant) {
+        for (key, previous) in grant.keys {
+            if self.last_grant.get(&key) != Some(&grant.at) {
+                continue;
+            }
+            match previous {
+                Some(previous) => {
+                    self.last_grant.insert(key, previous);
+                }
+                None => {
+                    self.last_grant.remove(&key);
+                }
+            }
+        }
+        if grant.in_window {
+            if let Some(pos) = self.window.iter().rposition(|granted| *granted == grant.at) {
+                self.window.remove(pos);
+            }
+        }
+    }
+
+    fn check_at(&mut self, keys: &[RateLimitKey], now: Instant) -> Result<Grant, Duration> {
+        // Forget about requests which no longer affect any limit, so memory use is bounded by the
+        // number of requests within the longest cooldown.
+        let max_cooldown = self
+            .address_cooldown
+            .max(self.ip_cooldown)
+            .max(self.discord_user_cooldown);
+        self.last_grant
+            .retain(|_, granted| now.saturating_duration_since(*granted) < max_cooldown);
+        while let Some(granted) = self.window.front() {
+            if now.saturating_duration_since(*granted) < self.grant_window {
+                break;
+            }
+            self.window.pop_front();
+        }
+
+        let mut retry_after = Duration::ZERO;
+        for key in keys {
+            if let Some(granted) = self.last_grant.get(key) {
+                let elapsed = now.saturating_duration_since(*granted);
+                retry_after = retry_after.max(self.cooldown(key).saturating_sub(elapsed));
+            }
+        }
+        if let Some(max_grants) = self.max_grants_per_window {
+            if self.window.len() >= max_grants {
+                // A slot frees up when the oldest grant in the window expires.
+                let oldest = self.window.front().copied().unwrap_or(now);
+                let elapsed = now.saturating_duration_since(oldest);
+                retry_after = retry_after.max(self.grant_window.saturating_sub(elapsed));
+            }
+        }
+        if !retry_after.is_zero() {
+            return Err(retry_after);
+        }
+
+        let mut grant = Grant {
+            at: now,
+            keys: vec![],
+            in_window: self.max_grants_per_window.is_some(),
+        };
+        for key in keys {
+            if !self.cooldown(key).is_zero() {
+                let previous = self.last_grant.insert(key.clone(), now);
+                grant.keys.push((key.clone(), previous));
+            }
+        }
+        if grant.in_window {
+            self.window.push_back(now);
+        }
+        Ok(grant)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter(options: Options) -> RateLimiter {
+        RateLimiter::new(&options)
+    }
+
+    #[test]
+    fn test_cooldowns() {
+        let mut limiter = limiter(Options {
+            address_cooldown: Duration::from_secs(60),
+            ip_cooldown: Duration::from_secs(10),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let address = RateLimitKey::Address(Address::random());
+        let ip = RateLimitKey::Ip("127.0.0.1".to_string());
+
+        limiter
+            .check_at(&[address.clone(), ip.clone()], start)
+            .unwrap();
+
+        // The same address from another IP is limited by the address cooldown.
+        let other_ip = RateLimitKey::Ip("127.0.0.2".to_string());
+        assert_eq!(
+            limiter.check_at(&[address.clone(), other_ip], start + Duration::from_secs(5)),
+            Err(Duration::from_secs(55))
+        );
+
+        // Another address from the same IP is limited by the IP cooldown.
+        let other_address = RateLimitKey::Address(Address::random());
+        assert_eq!(
+            limiter.check_at(
+                &[other_address.clone(), ip.clone()],
+                start + Duration::from_secs(5)
+            ),
+            Err(Duration::from_secs(5))
+        );
+        limiter
+            .check_at(&[other_address, ip], start + Duration::from_secs(10))
+            .unwrap();
+
+        // Rejected requests do not reset the cooldown.
+        limiter
+            .check_at(&[address], start + Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_global_cap() {
+        let mut limiter = limiter(Options {
+            max_grants_per_window: Some(2),
+            grant_window: Duration::from_secs(100),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let key = || RateLimitKey::Address(Address::random());
+
+        limiter.check_at(&[key()], start).unwrap();
+        limiter
+            .check_at(&[key()], start + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(
+            limiter.check_at(&[key()], start + Duration::from_secs(20)),
+            Err(Duration::from_secs(80))
+        );
+
+        // Once the first grant leaves the window there is room for one more.
+        limiter
+            .check_at(&[key()], start + Duration::from_secs(100))
+            .unwrap();
+        assert_eq!(
+            limiter.check_at(&[key()], start + Duration::from_secs(100)),
+            Err(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut limiter = limiter(Options {
+            address_cooldown: Duration::from_secs(60),
+            max_grants_per_window: Some(1),
+            grant_window: Duration::from_secs(100),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let address = RateLimitKey::Address(Address::random());
+
+        // A rolled back grant counts against neither the cooldown nor the global cap.
+        let grant = limiter.check_at(&[address.clone()], start).unwrap();
+        limiter.rollback(grant);
+        limiter
+            .check_at(&[address.clone()], start + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            limiter.check_at(&[address], start + Duration::from_secs(2)),
+            Err(Duration::from_secs(99))
+        );
+    }
+
+    #[test]
+    fn test_rollback_restores_previous_grant() {
+        let mut limiter = limiter(Options {
+            address_cooldown: Duration::from_secs(60),
+            ip_cooldown: Duration::from_secs(10),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let ip = RateLimitKey::Ip("127.0.0.1".to_string());
+
+        limiter.check_at(&[ip.clone()], start).unwrap();
+        let grant = limiter
+            .check_at(
+                &[RateLimitKey::Address(Address::random()), ip.clone()],
+                start + Duration::from_secs(10),
+            )
+            .unwrap();
+        limiter.rollback(grant);
+
+        // The cooldown of the IP runs from its earlier grant again.
+        limiter
+            .check_at(&[ip], start + Duration::from_secs(15))
+            .unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Sizing the wallet pool by demand.
+//!
+//! Each wallet sends about one transfer per block, so the number of wallets bounds the throughput of
+//! the faucet. If autoscaling is enabled, the faucet periodically compares how long the oldest
+//! queued request has been waiting with a target:
+//!
+//! * If the wait exceeds the target, the pool doubles, up to the maximum size. New wallets are
+//!   derived and funded like on startup.
+//! * If the queue stayed empty for the idle period, the pool halves, down to the minimum size. The
+//!   funds of the removed wallets are swept back like those of retired wallets.
+//!
+//! Recent decisions are reported in the faucet info, so operators can see why the pool changed.
+use crate::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How often the size of the wallet pool is reconsidered.
+pub const SCALING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The number of recent decisions which are reported.
+const MAX_DECISIONS: usize = 20;
+
+/// A change of the size of the wallet pool.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScalingDecision {
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    pub from: usize,
+    pub to: usize,
+    pub reason: String,
+}
+
+/// The state of autoscaling, as reported in the faucet info.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScalingStatus {
+    pub min_clients: usize,
+    pub max_clients: usize,
+    /// The target wait of queued requests in seconds.
+    pub target_queue_wait: u64,
+    /// How long the oldest queued request has been waiting in seconds, if any.
+    pub queue_wait: Option<u64>,
+    /// Recent decisions, oldest first.
+    pub decisions: Vec<ScalingDecision>,
+}
+
+/// Decides on the size of the wallet pool.
+#[derive(Clone, Debug)]
+pub struct Autoscaler {
+    min_clients: usize,
+    max_clients: usize,
+    target_queue_wait: Duration,
+    idle_period: Duration,
+    /// The last time the queue was not empty, or the pool changed.
+    last_busy: Instant,
+    decisions: VecDeque<ScalingDecision>,
+}
+
+impl Autoscaler {
+    /// The autoscaler of a faucet, if autoscaling is enabled.
+    ///
+    /// `capacity` is the number of wallets available, if limited.
+    pub fn new(options: &Options, capacity: Option<usize>) -> Option<Self> {
+        let max_clients = options.max_clients?;
+        let max_clients = capacity
+            .map_or(max_clients, |capacity| max_clients.min(capacity))
+            .max(1);
+        Some(Self {
+            min_clients: options.min_clients.clamp(1, max_clients),
+            max_clients,
+            target_queue_wait: options.target_queue_wait,
+            idle_period: options.scale_down_idle_period,
+            last_busy: Instant::now(),
+            decisions: Default::default(),
+        })
+    }
+
+    /// The new size of a pool of `num_clients` wallets, if it should change.
+    ///
+    /// `queue_wait` is how long the oldest queued request has been waiting.
+    pub fn decide(
+        &mut self,
+        num_clients: usize,
+        queue_depth: usize,
+        queue_wait: Option<Duration>,
+        now: Instant,
+    ) -> Option<ScalingDecision> {
+        if queue_depth > 0 {
+            self.last_busy = now;
+        }
+        let (to, reason) = match queue_wait {
+            Some(wait) if wait > self.target_queue_wait && num_clients < self.max_clients => (
+                (num_clients * 2).clamp(self.min_clients, self.max_clients),
+                format!(
+                    "queue wait of {}s exceeds the target of {}s with {queue_depth} transfers queued",
+                    wait.as_secs(),
+                    self.target_queue_wait.as_secs()
+                ),
+            ),
+            _ if queue_depth == 0
+                && now.saturating_duration_since(self.last_busy) >= self.idle_period
+                && num_clients > self.min_clients =>
+            {
+                (
+                    (num_clients / 2).clamp(self.min_clients, self.max_clients),
+                    format!("queue was empty for {}s", self.idle_period.as_secs()),
+                )
+            }
+            _ => return None,
+        };
+        // Give the new pool size time to take effect.
+        self.last_busy = now;
+        Some(ScalingDecision {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            from: num_clients,
+            to,
+            reason,
+        })
+    }
+
+    /// Remember a decision which was applied.
+    pub fn record(&mut self, decision: ScalingDecision) {
+        self.decisions.push_back(decision);
+        while self.decisions.len() > MAX_DECISIONS {
+            self.decisions.pop_front();
+        }
+    }
+
+    pub fn status(&self, queue_wait: Option<Duration>) -> ScalingStatus {
+        ScalingStatus {
+            min_clients: self.min_clients,
+            max_clients: self.max_clients,
+            target_queue_wait: self.target_queue_wait.as_secs(),
+            queue_wait: queue_wait.map(|wait| wait.as_secs()),
+            decisions: self.decisions.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn autoscaler(min_clients: usize, max_clients: usize) -> Autoscaler {
+        Autoscaler::new(
+            &Options {
+                min_clients,
+                max_clients: Some(max_clients),
+                target_queue_wait: Duration::from_secs(30),
+                scale_down_idle_period: Duration::from_secs(600),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_autoscaler_grows_within_bounds() {
+        let mut scaler = autoscaler(2, 10);
+        let now = Instant::now();
+
+        // A short wait does not grow the pool.
+        assert_eq!(
+            scaler.decide(2, 5, Some(Duration::from_secs(30)), now),
+            None
+        );
+
+        let decision = scaler
+            .decide(2, 5, Some(Duration::from_secs(31)), now)
+            .unwrap();
+        assert_eq!((decision.from, decision.to), (2, 4));
+        let decision = scaler
+            .decide(6, 5, Some(Duration::from_secs(31)), now)
+            .unwrap();
+        assert_eq!((decision.from, decision.to), (6, 10));
+        assert_eq!(
+            scaler.decide(10, 5, Some(Duration::from_secs(31)), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_autoscaler_shrinks_when_idle() {
+        let mut scaler = autoscaler(3, 10);
+        let start = Instant::now();
+        let idle = Duration::from_secs(600);
+
+        // The pool only shrinks once the queue was empty for the idle period.
+        assert_eq!(scaler.decide(10, 1, None, start), None);
+        assert_eq!(scaler.decide(10, 0, None, start + idle / 2), None);
+        let decision = scaler.decide(10, 0, None, start + idle).unwrap();
+        assert_eq!((decision.from, decision.to), (10, 5));
+
+        // The idle period starts again after each change.
+        assert_eq!(scaler.decide(5, 0, None, start + idle * 3 / 2), None);
+        let decision = scaler.decide(5, 0, None, start + idle * 2).unwrap();
+        assert_eq!((decision.from, decision.to), (5, 3));
+        assert_eq!(scaler.decide(3, 0, None, start + idle * 3), None);
+    }
+
+    #[test]
+    fn test_autoscaler_bounds() {
+        // The maximum is limited by the number of available wallets.
+        let scaler = Autoscaler::new(
+            &Options {
+                min_clients: 5,
+                max_clients: Some(10),
+                ..Default::default()
+            },
+            Some(4),
+        )
+        .unwrap();
+        assert_eq!((scaler.min_clients, scaler.max_clients), (4, 4));
+
+        // Autoscaling is disabled without a maximum.
+        assert!(Autoscaler::new(&Options::default(), None).is_none());
+
+        let mut scaler = autoscaler(1, 10);
+        let decision = scaler
+            .decide(1, 1, Some(Duration::from_secs(60)), Instant::now())
+            .unwrap();
+        scaler.record(decision.clone());
+        let status = scaler.status(Some(Duration::from_secs(60)));
+        assert_eq!(status.queue_wait, Some(60));
+        assert_eq!(status.decisions, [decision]);
+    }
+}
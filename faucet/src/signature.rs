@@ -0,0 +1,138 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Signed faucet requests.
+//!
+//! If signatures are required, a web request must prove that the requester controls the
+//! recipient address. The recipient signs the message returned by [`request_message`] with
+//! `personal_sign` (EIP-191), which is supported by every wallet. The message names the faucet, the
+//! chain and an expiry, so a signature can't be used on another chain or kept around forever.
+use ethers::types::{Address, Signature};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// How far in the future the expiry of a signature may be.
+const MAX_SIGNATURE_LIFETIME: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SignatureError {
+    #[error("the signature expired")]
+    Expired,
+    #[error("the expiry is more than {} seconds in the future", MAX_SIGNATURE_LIFETIME.as_secs())]
+    ExpiryTooLate,
+    #[error("the signature is by {signer:?}, not by the recipient")]
+    WrongSigner { signer: Address },
+    #[error("invalid signature: {0}")]
+    Invalid(String),
+}
+
+/// The message the recipient signs to request funds on the chain `chain_id`.
+///
+/// `expiry` is a unix timestamp in seconds.
+pub fn request_message(chain_id: u64, recipient: Address, expiry: u64) -> String {
+    format!(
+        "Espresso faucet request\nChain ID: {chain_id}\nRecipient: {recipient:?}\nExpiry: {expiry}"
+    )
+}
+
+/// Check that `signature` was made by `recipient` over the request message.
+pub fn verify_request_signature(
+    chain_id: u64,
+    recipient: Address,
+    expiry: u64,
+    signature: &Signature,
+) -> Result<(), SignatureError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    verify_at(chain_id, recipient, expiry, signature, now)
+}
+
+fn verify_at(
+    chain_id: u64,
+    recipient: Address,
+    expiry: u64,
+    signature: &Signature,
+    now: u64,
+) -> Result<(), SignatureError> {
+    if expiry <= now {
+        return Err(SignatureError::Expired);
+    }
+    if expiry > now + MAX_SIGNATURE_LIFETIME.as_secs() {
+        return Err(SignatureError::ExpiryTooLate);
+    }
+    let signer = signature
+        .recover(request_message(chain_id, recipient, expiry))
+        .map_err(|err| SignatureError::Invalid(err.to_string()))?;
+    if signer != recipient {
+        return Err(SignatureError::WrongSigner { signer });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{
+        core::rand::thread_rng,
+        signers::{LocalWallet, Signer},
+    };
+
+    #[async_std::test]
+    async fn test_request_signature() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let recipient = wallet.address();
+        let now = 1_000_000;
+        let expiry = now + 60;
+        let signature = wallet
+            .sign_message(request_message(1001, recipient, expiry))
+            .await
+            .unwrap();
+        verify_at(1001, recipient, expiry, &signature, now).unwrap();
+
+        // The signature is only valid for the signed chain, recipient and expiry.
+        assert!(verify_at(1002, recipient, expiry, &signature, now).is_err());
+        assert!(verify_at(1001, Address::random(), expiry, &signature, now).is_err());
+        assert!(verify_at(1001, recipient, expiry + 1, &signature, now).is_err());
+
+        // Expired signatures are rejected.
+        assert_eq!(
+            verify_at(1001, recipient, expiry, &signature, expiry),
+            Err(SignatureError::Expired)
+        );
+
+        // Signatures which are valid for too long are rejected.
+        let expiry = now + MAX_SIGNATURE_LIFETIME.as_secs() + 1;
+        let signature = wallet
+            .sign_message(request_message(1001, recipient, expiry))
+            .await
+            .unwrap();
+        assert_eq!(
+            verify_at(1001, recipient, expiry, &signature, now),
+            Err(SignatureError::ExpiryTooLate)
+        );
+    }
+
+    #[async_std::test]
+    async fn test_request_signature_by_other_wallet() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let recipient = Address::random();
+        let now = 1_000_000;
+        let expiry = now + 60;
+        let signature = wallet
+            .sign_message(request_message(1001, recipient, expiry))
+            .await
+            .unwrap();
+        assert_eq!(
+            verify_at(1001, recipient, expiry, &signature, now),
+            Err(SignatureError::WrongSigner {
+                signer: wallet.address()
+            })
+        );
+    }
+}
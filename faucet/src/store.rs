@@ -0,0 +1,62 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Persistent storage of the faucet state.
+//!
+//! The transfer queue and the inflight transfers are written to a JSON file whenever they change,
+//! so that a restarted faucet neither loses queued requests nor re-sends transfers which were
+//! already submitted.
+use crate::{RequestId, RequestStatus, TransferRequest};
+use anyhow::Result;
+use async_std::{fs, path::Path};
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, H256};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PersistedTransfer {
+    pub tx_hash: H256,
+    pub sender: Address,
+    pub request: TransferRequest,
+    pub tx: TypedTransaction,
+    /// Earlier transactions for this transfer, replaced by `tx`.
+    pub replaced: Vec<H256>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PersistedState {
+    pub transfer_queue: Vec<TransferRequest>,
+    pub inflight: Vec<PersistedTransfer>,
+    /// The status of recent faucet requests.
+    #[serde(default)]
+    pub requests: Vec<RequestStatus>,
+    #[serde(default)]
+    pub next_request_id: RequestId,
+}
+
+impl PersistedState {
+    /// Load the state from `path`, or an empty state if the file does not exist.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists().await {
+            tracing::info!("No faucet state at {}, starting fresh", path.display());
+            return Ok(Self::default());
+        }
+        let bytes = fs::read(path).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Atomically replace the state stored at `path`.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        // Write to a temporary file first, so that a crash while writing never leaves a corrupted
+        // state file behind.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?).await?;
+        fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! ERC-20 token grants.
+//!
+//! Besides the native asset, the faucet can grant any number of ERC-20 tokens, for instance the
+//! MATIC mock (`ERC20PermitMock`) or tokens bridged to an L2 (`TokenWrapped`). Both implement the
+//! standard ERC-20 interface, so the `ERC20PermitMock` bindings are used for all tokens.
+use anyhow::{Context, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+    utils::parse_units,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+use zkevm_contract_bindings::erc20_permit_mock::ERC20PermitMock;
+
+/// The configuration of a token grant, as `SYMBOL:ADDRESS:AMOUNT`.
+///
+/// The amount is in whole tokens, and is converted using the decimals of the token contract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenConfig {
+    pub symbol: String,
+    pub address: Address,
+    pub amount: String,
+}
+
+impl FromStr for TokenConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let (Some(symbol), Some(address), Some(amount), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("expected SYMBOL:ADDRESS:AMOUNT, got {s}");
+        };
+        Ok(Self {
+            symbol: symbol.to_uppercase(),
+            address: address.parse().context("invalid token address")?,
+            amount: amount.to_string(),
+        })
+    }
+}
+
+/// A token granted by the faucet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub symbol: String,
+    pub address: Address,
+    /// The amount granted per request, in the smallest unit of the token.
+    pub grant_amount: U256,
+    pub decimals: u8,
+}
+
+impl Token {
+    /// Resolve a token configuration using the token contract.
+    pub async fn resolve<M: Middleware + 'static>(
+        config: &TokenConfig,
+        client: Arc<M>,
+    ) -> Result<Self> {
+        let decimals = erc20(config.address, client)
+            .decimals()
+            .call()
+            .await
+            .with_context(|| format!("failed to get decimals of {}", config.symbol))?;
+        Ok(Self {
+            symbol: config.symbol.clone(),
+            address: config.address,
+            grant_amount: parse_units(&config.amount, decimals as u32)?.into(),
+            decimals,
+        })
+    }
+}
+
+/// The asset requested from the faucet.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Asset {
+    /// The native asset of the chain.
+    #[default]
+    Native,
+    /// An ERC-20 token, by symbol.
+    Token(String),
+}
+
+impl Asset {
+    /// Parse an asset from user input.
+    ///
+    /// Any symbol which is not `ETH` refers to a token.
+    pub fn parse(symbol: &str) -> Self {
+        let symbol = symbol.trim().to_uppercase();
+        if symbol == "ETH" {
+            Self::Native
+        } else {
+            Self::Token(symbol)
+        }
+    }
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Native => write!(f, "ETH"),
+            Self::Token(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+pub fn erc20<M: Middleware>(address: Address, client: Arc<M>) -> ERC20PermitMock<M> {
+    ERC20PermitMock::new(address, client)
+}
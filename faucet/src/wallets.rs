@@ -0,0 +1,189 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The wallets of the faucet.
+//!
+//! Wallets are either derived from a mnemonic or loaded from a directory of encrypted JSON
+//! keystores, like the ones written by `keygen`. Either way wallet `i` of a chain is the wallet at
+//! index `client_index_offset + i`: the HD wallet index for a mnemonic, or the position of the
+//! keystore in the directory sorted by file name. A mnemonic provides any number of wallets, while
+//! the number of keystores limits the number of wallets of the faucet.
+use crate::Options;
+use anyhow::{bail, ensure, Context, Result};
+use ethers::signers::{coins_bip39::English, LocalWallet, MnemonicBuilder};
+use std::{fs, path::Path};
+
+#[derive(Clone, Debug)]
+enum WalletSource {
+    Mnemonic(String),
+    /// Decrypted keystores, sorted by file name.
+    Keystores(Vec<LocalWallet>),
+}
+
+/// The wallets available to the faucet of one chain.
+#[derive(Clone, Debug)]
+pub struct Wallets {
+    source: WalletSource,
+    offset: usize,
+}
+
+impl Wallets {
+    /// Load the wallets configured by `options`.
+    ///
+    /// Decrypting keystores is slow, so they are all decrypted once, here.
+    pub async fn load(options: &Options) -> Result<Self> {
+        let source = match (&options.mnemonic, &options.keystore_dir) {
+            (Some(_), Some(_)) => {
+                bail!("a mnemonic and a keystore directory are mutually exclusive")
+            }
+            (Some(mnemonic), None) => WalletSource::Mnemonic(mnemonic.clone()),
+            (None, Some(dir)) => {
+                let password_file = options
+                    .keystore_password_file
+                    .as_ref()
+                    .context("a keystore directory requires a keystore password file")?;
+                let password = fs::read_to_string(password_file)
+                    .with_context(|| format!("failed to read {}", password_file.display()))?;
+                // Password files usually end with a newline, which is not part of the password.
+                let password = password.trim_end_matches(['\r', '\n']).to_string();
+                let dir = dir.clone();
+                let keystores =
+                    async_std::task::spawn_blocking(move || load_keystores(&dir, &password))
+                        .await?;
+                WalletSource::Keystores(keystores)
+            }
+            (None, None) => bail!("either a mnemonic or a keystore directory is required"),
+        };
+        Ok(Self {
+            source,
+            offset: options.client_index_offset,
+        })
+    }
+
+    /// The number of wallets available, or `None` if there is no limit.
+    pub fn capacity(&self) -> Option<usize> {
+        match &self.source {
+            WalletSource::Mnemonic(_) => None,
+            WalletSource::Keystores(keystores) => Some(keystores.len().saturating_sub(self.offset)),
+        }
+    }
+
+    /// The wallet with index `index`.
+    pub fn wallet(&self, index: usize) -> Result<LocalWallet> {
+        match &self.source {
+            WalletSource::Mnemonic(mnemonic) => Ok(MnemonicBuilder::<English>::default()
+                .phrase(mnemonic.as_str())
+                .index((self.offset + index) as u32)?
+                .build()?),
+            WalletSource::Keystores(keystores) => keystores
+                .get(self.offset + index)
+                .cloned()
+                .with_context(|| {
+                    format!(
+                        "wallet {index} needs keystore {}, but there are only {} keystores",
+                        self.offset + index,
+                        keystores.len()
+                    )
+                }),
+        }
+    }
+}
+
+fn load_keystores(dir: &Path, password: &str) -> Result<Vec<LocalWallet>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        // Skip subdirectories and hidden files like `.gitignore`.
+        if !entry.file_type()?.is_file() || entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        paths.push(entry.path());
+    }
+    paths.sort();
+    ensure!(!paths.is_empty(), "no keystores in {}", dir.display());
+
+    paths
+        .iter()
+        .map(|path| {
+            let wallet = LocalWallet::decrypt_keystore(path, password)
+                .with_context(|| format!("failed to decrypt keystore {}", path.display()))?;
+            tracing::info!("Loaded keystore {}", path.display());
+            Ok(wallet)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{core::rand::thread_rng, signers::Signer, types::Address};
+
+    #[async_std::test]
+    async fn test_keystore_wallets() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let keystores = dir.path().join("keystores");
+        fs::create_dir(&keystores)?;
+        let mut addresses = vec![];
+        for name in ["b.json", "a.json", "c.json"] {
+            let (wallet, _) =
+                LocalWallet::new_keystore(&keystores, &mut thread_rng(), "secret", Some(name))?;
+            addresses.push((name, wallet.address()));
+        }
+        addresses.sort();
+        fs::write(keystores.join(".gitignore"), "*")?;
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "secret\n")?;
+
+        // Wallets are ordered by file name.
+        let options = Options {
+            mnemonic: None,
+            keystore_dir: Some(keystores.clone()),
+            keystore_password_file: Some(password_file.clone()),
+            ..Default::default()
+        };
+        let wallets = Wallets::load(&options).await?;
+        assert_eq!(wallets.capacity(), Some(3));
+        for (index, (_, address)) in addresses.iter().enumerate() {
+            assert_eq!(wallets.wallet(index)?.address(), *address);
+        }
+        assert!(wallets.wallet(3).is_err());
+
+        // The offset applies to keystores like to HD wallet indices.
+        let wallets = Wallets::load(&Options {
+            client_index_offset: 1,
+            ..options.clone()
+        })
+        .await?;
+        assert_eq!(wallets.capacity(), Some(2));
+        assert_eq!(wallets.wallet(0)?.address(), addresses[1].1);
+
+        // A wrong password is an error.
+        fs::write(&password_file, "wrong")?;
+        assert!(Wallets::load(&options).await.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_mnemonic_wallets() -> Result<()> {
+        let options = Options::default();
+        let wallets = Wallets::load(&options).await?;
+        assert_eq!(wallets.capacity(), None);
+        // The first account of the test mnemonic.
+        assert_eq!(
+            wallets.wallet(0)?.address(),
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse::<Address>()?
+        );
+
+        let options = Options {
+            keystore_dir: Some("keystores".into()),
+            ..options
+        };
+        assert!(Wallets::load(&options).await.is_err());
+        Ok(())
+    }
+}
@@ -11,16 +11,29 @@
 //! 1. Provide a healthcheck endpoint for the discord bot, so it can be automatically
 //!    restarted if it fails.
 //! 2. Test and use the faucet locally without connecting to Discord.
+//! 3. Let users follow their requests, and operators monitor the faucet.
+use crate::{
+    verify_request_signature, Asset, AuditEntry, AuditFilter, Challenge, Challenges, Enqueued,
+    Faucet, FaucetInfo, FaucetRequest, Grant, Options, QueueFull, RateLimitKey, RateLimiter,
+    RequestId, RequestStatus, Source,
+};
 use async_std::channel::Sender;
-use async_std::sync::RwLock;
-use ethers::types::Address;
+use async_std::sync::{Mutex, RwLock};
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
 use futures::FutureExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::fmt::{self, Display, Formatter};
 use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tide_disco::RequestError;
+use tide::listener::{ListenInfo, Listener, ToListener};
 use tide_disco::{http::StatusCode, Api, App, Error};
+use tide_disco::{RequestError, RequestParams};
 
 #[derive(Clone, Debug, Deserialize, Serialize, Error)]
 pub enum FaucetError {
@@ -28,6 +41,47 @@ pub enum FaucetError {
     FaucetError { status: StatusCode, msg: String },
     #[error("unable to parse Ethereum address: {input}")]
     BadAddress { status: StatusCode, input: String },
+    /// The request exceeds a rate limit.
+    ///
+    /// The web server also sends `retry_after` in the `Retry-After` header of the response.
+    #[error("too many requests, retry after {retry_after} seconds")]
+    RateLimited {
+        status: StatusCode,
+        retry_after: u64,
+    },
+    #[error("unknown asset: {asset}")]
+    UnknownAsset { status: StatusCode, asset: String },
+    #[error("unknown request: {id}")]
+    UnknownRequest { status: StatusCode, id: RequestId },
+    #[error("unknown chain: {chain}")]
+    UnknownChain { status: StatusCode, chain: String },
+    #[error("the faucet is paused, try again later")]
+    Paused { status: StatusCode },
+    #[error("address {address:?} is not eligible for funds")]
+    Blocked {
+        status: StatusCode,
+        address: Address,
+    },
+    #[error("missing or invalid admin token")]
+    Unauthorized { status: StatusCode },
+    #[error("proof of work required: {msg}")]
+    ProofOfWork { status: StatusCode, msg: String },
+    #[error("the faucet does not require a challenge")]
+    NoChallenge { status: StatusCode },
+    #[error("signature required: {msg}")]
+    Signature { status: StatusCode, msg: String },
+    #[error("the faucet queue is full, try again later")]
+    QueueFull { status: StatusCode },
+}
+
+impl FaucetError {
+    pub fn rate_limited(retry_after: Duration) -> Self {
+        Self::RateLimited {
+            status: StatusCode::TooManyRequests,
+            // Round up, so that retrying after the given number of seconds succeeds.
+            retry_after: retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0),
+        }
+    }
 }
 
 impl tide_disco::Error for FaucetError {
@@ -39,6 +93,17 @@ impl tide_disco::Error for FaucetError {
         match self {
             Self::FaucetError { status, .. } => *status,
             Self::BadAddress { status, .. } => *status,
+            Self::RateLimited { status, .. } => *status,
+            Self::UnknownAsset { status, .. } => *status,
+            Self::UnknownRequest { status, .. } => *status,
+            Self::UnknownChain { status, .. } => *status,
+            Self::Paused { status } => *status,
+            Self::Blocked { status, .. } => *status,
+            Self::Unauthorized { status } => *status,
+            Self::ProofOfWork { status, .. } => *status,
+            Self::NoChallenge { status } => *status,
+            Self::Signature { status, .. } => *status,
+            Self::QueueFull { status } => *status,
         }
     }
 }
@@ -49,7 +114,119 @@ impl From<RequestError> for FaucetError {
     }
 }
 
+/// The header carrying the solution of a proof-of-work challenge, as `<nonce>:<solution>`.
+pub const POW_HEADER: &str = "X-Faucet-Proof-Of-Work";
+
+/// The header carrying the signature of a request, as `<expiry>:<signature>`.
+pub const SIGNATURE_HEADER: &str = "X-Faucet-Signature";
+
+/// A listener which adds a `Retry-After` header to rate limited responses.
+///
+/// tide-disco does not let routes set response headers, but it hands the underlying tide server to
+/// the listener it serves on, so we install the header middleware there. Middleware added there
+/// runs inside the tide-disco middleware which renders errors into response bodies, so the
+/// [`FaucetError`] of a failed route is still attached to the response.
+#[derive(Debug)]
+struct WithRetryAfter<L>(L);
+
+impl<L: Display> Display for WithRetryAfter<L> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<State, L> ToListener<State> for WithRetryAfter<L>
+where
+    State: Clone + Send + Sync + 'static,
+    L: ToListener<State>,
+{
+    type Listener = WithRetryAfter<L::Listener>;
+
+    fn to_listener(self) -> io::Result<Self::Listener> {
+        Ok(WithRetryAfter(self.0.to_listener()?))
+    }
+}
+
+#[async_trait]
+impl<State, L> Listener<State> for WithRetryAfter<L>
+where
+    State: Clone + Send + Sync + 'static,
+    L: Listener<State>,
+{
+    async fn bind(&mut self, mut app: tide::Server<State>) -> io::Result<()> {
+        app.with(RetryAfter);
+        self.0.bind(app).await
+    }
+
+    async fn accept(&mut self) -> io::Result<()> {
+        self.0.accept().await
+    }
+
+    fn info(&self) -> Vec<ListenInfo> {
+        self.0.info()
+    }
+}
+
+/// Middleware setting the `Retry-After` header of [`FaucetError::RateLimited`] responses.
+#[derive(Debug)]
+struct RetryAfter;
+
+#[async_trait]
+impl<State: Clone + Send + Sync + 'static> tide::Middleware<State> for RetryAfter {
+    async fn handle(&self, req: tide::Request<State>, next: tide::Next<'_, State>) -> tide::Result {
+        let mut res = next.run(req).await;
+        let retry_after = match res
+            .error()
+            .and_then(|err| err.downcast_ref::<FaucetError>())
+        {
+            Some(FaucetError::RateLimited { retry_after, .. }) => Some(*retry_after),
+            _ => None,
+        };
+        if let Some(retry_after) = retry_after {
+            res.insert_header("Retry-After", retry_after.to_string());
+        }
+        Ok(res)
+    }
+}
+
+/// The IP address of the client which made a request.
+///
+/// `remote` is the peer address of the connection. If it is one of the `trusted_proxies`, the
+/// client is the last address in `forwarded_for`, the value of the `X-Forwarded-For` header, which
+/// is not itself a trusted proxy.
+fn client_ip(
+    remote: Option<&str>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> Option<String> {
+    let parse = |addr: &str| {
+        let addr = addr.trim();
+        addr.parse::<SocketAddr>()
+            .map(|addr| addr.ip())
+            .or_else(|_| addr.parse::<IpAddr>())
+            .ok()
+    };
+    let remote = remote?;
+    let Some(mut client) = parse(remote) else {
+        return Some(remote.to_string());
+    };
+    if let Some(forwarded_for) = forwarded_for {
+        for hop in forwarded_for.rsplit(',') {
+            if !trusted_proxies.contains(&client) {
+                break;
+            }
+            match parse(hop) {
+                Some(ip) => client = ip,
+                // The proxy forwarded something which is not an address, use it as is.
+                None => return Some(hop.trim().to_string()),
+            }
+        }
+    }
+    Some(client.to_string())
+}
+
 pub(crate) async fn serve(port: u16, state: WebState) -> io::Result<()> {
+    let admin_enabled = state.admin_token.is_some();
     let mut app = App::<_, FaucetError>::with_state(RwLock::new(state));
     app.with_version(env!("CARGO_PKG_VERSION").parse().unwrap());
 
@@ -62,63 +239,694 @@ pub(crate) async fn serve(port: u16, state: WebState) -> io::Result<()> {
 
     // Can invoke with
     //    `curl -i -X POST http://0.0.0.0:8111/faucet/request/0x1234567890123456789012345678901234567890`
+    // or, to request a token
+    //    `curl -i -X POST http://0.0.0.0:8111/faucet/request/0x1234567890123456789012345678901234567890/MATIC`
+    // or, to request funds on another chain
+    //    `curl -i -X POST http://0.0.0.0:8111/faucet/zkevm-1/request/0x1234567890123456789012345678901234567890`
     api.post("request", |req, state| {
         async move {
-            let address = req.string_param("address")?;
-            let address = address.parse().map_err(|_| FaucetError::BadAddress {
-                status: StatusCode::BadRequest,
-                input: address.to_string(),
-            })?;
-            tracing::info!("Received faucet request for {:?}", address);
-            state.request(address).await?;
-            Ok(())
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = address_param(&req)?;
+            chain.check_signature(
+                req.header(SIGNATURE_HEADER).map(|value| value.as_str()),
+                address,
+            )?;
+            let asset = req
+                .opt_string_param("asset")?
+                .map(Asset::parse)
+                .unwrap_or_default();
+            tracing::info!(
+                "Received faucet request for {asset} to {address:?} on {}",
+                chain.name()
+            );
+
+            // Prefer the address of the original client if we are behind a trusted proxy.
+            let ip = client_ip(
+                req.remote(),
+                req.header("X-Forwarded-For").map(|ips| ips.as_str()),
+                &chain.options().trusted_proxies,
+            );
+            let mut keys = vec![RateLimitKey::Address(address)];
+            keys.extend(ip.map(RateLimitKey::Ip));
+            let grant = chain.check_request(&keys).await?;
+
+            // Only use up the challenge once the request passed the rate limits, and give the
+            // rate limits back if the solution is rejected.
+            if let Err(err) = chain
+                .check_proof_of_work(req.header(POW_HEADER).map(|value| value.as_str()), address)
+                .await
+            {
+                chain.rollback(grant).await;
+                return Err(err);
+            }
+            chain.request(grant, address, asset, Source::Web).await
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    // Can invoke with
+    //    `curl http://0.0.0.0:8111/faucet/challenge`
+    api.get("challenge", |req, state| {
+        async move {
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            chain.challenge().await
         }
         .boxed()
     })
     .unwrap();
 
+    // Can invoke with
+    //    `curl http://0.0.0.0:8111/faucet/status/0`
+    api.get("status", |req, state| {
+        async move {
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let id = req.integer_param("id")?;
+            chain.status(id).await
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    // Can invoke with
+    //    `curl http://0.0.0.0:8111/faucet/info`
+    api.get("info", |req, state| {
+        async move {
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            chain.info().await
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    // Can invoke with
+    //    `curl http://0.0.0.0:8111/faucet/chains`
+    api.get("chains", |_req, state| {
+        async move { Ok(state.chain_names()) }.boxed()
+    })
+    .unwrap();
+
     app.register_module("faucet", api).unwrap();
-    app.serve(format!("0.0.0.0:{}", port)).await
+    if admin_enabled {
+        app.register_module("admin", admin_api()?).unwrap();
+    }
+    app.serve(WithRetryAfter(format!("0.0.0.0:{}", port))).await
 }
 
-#[derive(Clone, Debug)]
+/// The admin API, for controlling the faucet at runtime.
+///
+/// Every admin action is logged.
+fn admin_api() -> io::Result<Api<RwLock<WebState>, FaucetError>> {
+    let toml = toml::from_str::<toml::value::Value>(include_str!("admin.toml"))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let mut api = Api::<RwLock<WebState>, FaucetError>::new(toml).unwrap();
+    api.with_version(env!("CARGO_PKG_VERSION").parse().unwrap());
+
+    // Can invoke with
+    //    `curl -i -X POST -H "Authorization: Bearer $TOKEN" http://0.0.0.0:8111/admin/pause`
+    api.post("pause", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            tracing::info!("Admin: pausing faucet on {}", chain.name());
+            chain.faucet.set_paused(true).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("resume", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            tracing::info!("Admin: resuming faucet on {}", chain.name());
+            chain.faucet.set_paused(false).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("grant", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let amount = req.string_param("amount")?;
+            let asset = req
+                .opt_string_param("asset")?
+                .map(Asset::parse)
+                .unwrap_or_default();
+            chain.check_asset(&asset)?;
+            let amount = chain
+                .faucet
+                .set_grant_amount(&asset, amount)
+                .await
+                .map_err(|err| FaucetError::FaucetError {
+                    status: StatusCode::BadRequest,
+                    msg: err.to_string(),
+                })?;
+            tracing::info!(
+                "Admin: granting {amount} {asset} per request on {}",
+                chain.name()
+            );
+            Ok(amount)
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("block", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = address_param(&req)?;
+            tracing::info!("Admin: blocking {address:?} on {}", chain.name());
+            chain.faucet.set_blocked(address, true).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("unblock", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = address_param(&req)?;
+            tracing::info!("Admin: unblocking {address:?} on {}", chain.name());
+            chain.faucet.set_blocked(address, false).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("allow", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = address_param(&req)?;
+            tracing::info!(
+                "Admin: exempting {address:?} from rate limits on {}",
+                chain.name()
+            );
+            chain.faucet.set_allowed(address, true).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("disallow", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = address_param(&req)?;
+            tracing::info!("Admin: rate limiting {address:?} again on {}", chain.name());
+            chain.faucet.set_allowed(address, false).await;
+            Ok(())
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("drain", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let drained = chain.faucet.drain_queue().await;
+            tracing::info!(
+                "Admin: drained {drained} queued requests on {}",
+                chain.name()
+            );
+            Ok(drained)
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.post("resize", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let num_clients = req.integer_param("num_clients")?;
+            tracing::info!(
+                "Admin: resizing wallet pool to {num_clients} on {}",
+                chain.name()
+            );
+            chain
+                .faucet
+                .resize(num_clients)
+                .await
+                .map_err(|err| FaucetError::FaucetError {
+                    status: StatusCode::BadRequest,
+                    msg: err.to_string(),
+                })
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.get("audit", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            let address = match req.opt_string_param("address")? {
+                Some(_) => Some(address_param(&req)?),
+                None => None,
+            };
+            let filter = AuditFilter {
+                address,
+                from: req.opt_integer_param("from")?,
+                to: req.opt_integer_param("to")?,
+            };
+            chain.audit_log(&filter).await
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    api.get("settings", |req, state| {
+        async move {
+            state.authorize(&req)?;
+            let chain = state.chain(req.opt_string_param("chain")?)?;
+            Ok(chain.faucet.settings().await)
+        }
+        .boxed()
+    })
+    .unwrap();
+
+    Ok(api)
+}
+
+fn address_param(req: &RequestParams) -> Result<Address, FaucetError> {
+    let address = req.string_param("address")?;
+    address.parse().map_err(|_| FaucetError::BadAddress {
+        status: StatusCode::BadRequest,
+        input: address.to_string(),
+    })
+}
+
+/// Compare secrets in time independent of the position of the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Clone, Debug, Default)]
 pub(crate) struct WebState {
-    faucet_queue: Sender<Address>,
+    /// The chains served by the faucet, primary chain first.
+    chains: Vec<ChainState>,
+    /// The chain and id of the last request of each chat user.
+    pub(crate) last_requests: Arc<Mutex<HashMap<u64, (String, RequestId)>>>,
+    /// Bearer token for the admin API, which is disabled if not set.
+    admin_token: Option<String>,
 }
 
 impl WebState {
-    pub fn new(faucet_queue: Sender<Address>) -> Self {
-        Self { faucet_queue }
+    pub fn new(faucet_queue: Sender<FaucetRequest>, faucet: &Faucet) -> Self {
+        Self {
+            admin_token: faucet
+                .options()
+                .admin_token
+                .clone()
+                .filter(|token| !token.is_empty()),
+            ..Default::default()
+        }
+        .with_chain(faucet_queue, faucet)
+    }
+
+    /// Serve an additional chain.
+    pub fn with_chain(mut self, faucet_queue: Sender<FaucetRequest>, faucet: &Faucet) -> Self {
+        self.chains.push(ChainState::new(faucet_queue, faucet));
+        self
+    }
+
+    /// The chain called `name`, or the primary chain if no name is given.
+    pub fn chain(&self, name: Option<&str>) -> Result<&ChainState, FaucetError> {
+        match name {
+            None => self.chains.first().ok_or_else(|| FaucetError::FaucetError {
+                status: StatusCode::ServiceUnavailable,
+                msg: "no chains configured".to_string(),
+            }),
+            Some(name) => self
+                .chains
+                .iter()
+                .find(|chain| chain.name() == name)
+                .ok_or_else(|| FaucetError::UnknownChain {
+                    status: StatusCode::NotFound,
+                    chain: name.to_string(),
+                }),
+        }
+    }
+
+    /// Check the admin token in the `Authorization` header of `req`.
+    fn authorize(&self, req: &RequestParams) -> Result<(), FaucetError> {
+        let token = req
+            .header("Authorization")
+            .and_then(|value| value.as_str().strip_prefix("Bearer "));
+        match (&self.admin_token, token) {
+            (Some(expected), Some(token))
+                if constant_time_eq(expected.as_bytes(), token.trim().as_bytes()) =>
+            {
+                Ok(())
+            }
+            _ => Err(FaucetError::Unauthorized {
+                status: StatusCode::Unauthorized,
+            }),
+        }
     }
 
-    pub async fn request(&self, address: Address) -> Result<(), FaucetError> {
-        self.faucet_queue
-            .send(address)
+    pub fn chain_names(&self) -> Vec<String> {
+        self.chains
+            .iter()
+            .map(|chain| chain.name().to_string())
+            .collect()
+    }
+}
+
+/// The faucet of a single chain.
+#[derive(Clone, Debug)]
+pub(crate) struct ChainState {
+    faucet: Faucet,
+    faucet_queue: Sender<FaucetRequest>,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    /// Outstanding proof-of-work challenges, if web requests require one.
+    challenges: Option<Arc<Mutex<Challenges>>>,
+    /// Symbols of the tokens granted by the faucet.
+    tokens: Vec<String>,
+}
+
+impl ChainState {
+    fn new(faucet_queue: Sender<FaucetRequest>, faucet: &Faucet) -> Self {
+        let options = faucet.options();
+        Self {
+            faucet: faucet.clone(),
+            faucet_queue,
+            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(options))),
+            challenges: Challenges::new(options).map(|challenges| Arc::new(Mutex::new(challenges))),
+            tokens: options
+                .tokens
+                .iter()
+                .map(|token| token.symbol.clone())
+                .collect(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.faucet.options().chain_name
+    }
+
+    pub fn options(&self) -> &Options {
+        self.faucet.options()
+    }
+
+    /// Check whether a request identified by `keys` is accepted.
+    ///
+    /// Requests are rejected while the faucet is paused or if their address is blocked. Requests
+    /// for allowed addresses skip the rate limits, other requests which are accepted count against
+    /// the rate limits of later requests, unless their grant is rolled back.
+    ///
+    /// This must be called before [`request`](Self::request), which takes the returned grant.
+    pub async fn check_request(&self, keys: &[RateLimitKey]) -> Result<Option<Grant>, FaucetError> {
+        if self.faucet.is_paused().await {
+            return Err(FaucetError::Paused {
+                status: StatusCode::ServiceUnavailable,
+            });
+        }
+        for key in keys {
+            if let RateLimitKey::Address(address) = key {
+                if self.faucet.is_blocked(*address).await {
+                    tracing::info!("Rejected faucet request for blocked address {address:?}");
+                    return Err(FaucetError::Blocked {
+                        status: StatusCode::Forbidden,
+                        address: *address,
+                    });
+                }
+                if self.faucet.is_allowed(*address).await {
+                    return Ok(None);
+                }
+            }
+        }
+        self.rate_limiter
+            .lock()
+            .await
+            .check(keys)
+            .map(Some)
+            .map_err(|retry_after| {
+                tracing::info!("Rate limited faucet request {keys:?}, retry after {retry_after:?}");
+                FaucetError::rate_limited(retry_after)
+            })
+    }
+
+    /// Undo the grant of a request which was accepted by [`check_request`](Self::check_request)
+    /// but not served.
+    pub async fn rollback(&self, grant: Option<Grant>) {
+        if let Some(grant) = grant {
+            self.rate_limiter.lock().await.rollback(grant);
+        }
+    }
+
+    /// Issue a proof-of-work challenge, with a difficulty depending on the current queue.
+    pub async fn challenge(&self) -> Result<Challenge, FaucetError> {
+        let challenges = self.challenges.as_ref().ok_or(FaucetError::NoChallenge {
+            status: StatusCode::NotFound,
+        })?;
+        let queue_depth = self.faucet.queue_depth().await;
+        Ok(challenges.lock().await.issue(queue_depth))
+    }
+
+    /// Check that a request for `address` is signed by `address`, if signatures are required.
+    ///
+    /// `signature` is the value of the [`SIGNATURE_HEADER`] header, if any.
+    pub fn check_signature(
+        &self,
+        signature: Option<&str>,
+        address: Address,
+    ) -> Result<(), FaucetError> {
+        if !self.options().require_signature {
+            return Ok(());
+        }
+        let invalid = |msg: &str| FaucetError::Signature {
+            status: StatusCode::Forbidden,
+            msg: msg.to_string(),
+        };
+        let signature = signature.ok_or_else(|| invalid("missing signature"))?;
+        let (expiry, signature) = signature
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| invalid("expected <expiry>:<signature>"))?;
+        let expiry = expiry.parse().map_err(|_| invalid("invalid expiry"))?;
+        let signature = signature
+            .parse()
+            .map_err(|_| invalid("invalid signature"))?;
+        verify_request_signature(self.faucet.chain_id(), address, expiry, &signature)
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    /// Check the solution of a proof-of-work challenge for a request for `address`.
+    ///
+    /// `solution` is the value of the [`POW_HEADER`] header, if any. Always succeeds if no
+    /// challenge is required.
+    pub async fn check_proof_of_work(
+        &self,
+        solution: Option<&str>,
+        address: Address,
+    ) -> Result<(), FaucetError> {
+        let Some(challenges) = &self.challenges else {
+            return Ok(());
+        };
+        let invalid = |msg: &str| FaucetError::ProofOfWork {
+            status: StatusCode::Forbidden,
+            msg: msg.to_string(),
+        };
+        let solution = solution.ok_or_else(|| invalid("missing solution"))?;
+        let (nonce, solution) = solution
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| invalid("expected <nonce>:<solution>"))?;
+        let nonce = nonce.parse().map_err(|_| invalid("invalid nonce"))?;
+        let solution = solution.parse().map_err(|_| invalid("invalid solution"))?;
+        challenges
+            .lock()
+            .await
+            .redeem(nonce, address, solution)
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    /// Request funds for `address`, returning the id of the request.
+    ///
+    /// `grant` is the result of [`check_request`](Self::check_request). It is rolled back if the
+    /// request is rejected, so that the requester can try again without waiting for the rate
+    /// limits. Once enqueued the request is not rejected anymore: if the fees exceed the fee cap
+    /// it waits in the queue until they drop.
+    pub async fn request(
+        &self,
+        grant: Option<Grant>,
+        address: Address,
+        asset: Asset,
+        source: Source,
+    ) -> Result<RequestId, FaucetError> {
+        let result = self.enqueue(address, asset, source).await;
+        if result.is_err() {
+            self.rollback(grant).await;
+        }
+        result
+    }
+
+    async fn enqueue(
+        &self,
+        address: Address,
+        asset: Asset,
+        source: Source,
+    ) -> Result<RequestId, FaucetError> {
+        self.check_asset(&asset)?;
+        let request = match self.faucet.enqueue_request(address, asset, source).await {
+            Ok(Enqueued::New(request)) => request,
+            Ok(Enqueued::Merged(id)) => return Ok(id),
+            Err(QueueFull) => {
+                tracing::warn!("Rejected faucet request for {address:?}, the queue is full");
+                return Err(FaucetError::QueueFull {
+                    status: StatusCode::ServiceUnavailable,
+                });
+            }
+        };
+        let id = request.id;
+        if let Err(err) = self.faucet_queue.send(request).await {
+            self.faucet.fail_request(id).await;
+            return Err(FaucetError::FaucetError {
+                status: StatusCode::InternalServerError,
+                msg: err.to_string(),
+            });
+        }
+        Ok(id)
+    }
+
+    fn check_asset(&self, asset: &Asset) -> Result<(), FaucetError> {
+        match asset {
+            Asset::Token(symbol) if !self.tokens.contains(symbol) => {
+                Err(FaucetError::UnknownAsset {
+                    status: StatusCode::NotFound,
+                    asset: symbol.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn status(&self, id: RequestId) -> Result<RequestStatus, FaucetError> {
+        self.faucet
+            .request_status(id)
+            .await
+            .ok_or(FaucetError::UnknownRequest {
+                status: StatusCode::NotFound,
+                id,
+            })
+    }
+
+    pub async fn balance(&self, address: Address) -> Result<U256, FaucetError> {
+        self.faucet
+            .balance(address)
             .await
             .map_err(|err| FaucetError::FaucetError {
                 status: StatusCode::InternalServerError,
                 msg: err.to_string(),
-            })?;
-        Ok(())
+            })
+    }
+
+    pub async fn audit_log(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, FaucetError> {
+        self.faucet
+            .audit_log(filter)
+            .await
+            .map_err(|err| FaucetError::FaucetError {
+                status: StatusCode::InternalServerError,
+                msg: err.to_string(),
+            })?
+            .ok_or_else(|| FaucetError::FaucetError {
+                status: StatusCode::NotFound,
+                msg: "the audit log is disabled".to_string(),
+            })
+    }
+
+    pub async fn info(&self) -> Result<FaucetInfo, FaucetError> {
+        self.faucet
+            .info()
+            .await
+            .map_err(|err| FaucetError::FaucetError {
+                status: StatusCode::InternalServerError,
+                msg: err.to_string(),
+            })
     }
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
-    use crate::faucet::{Faucet, Options};
+    use crate::faucet::{Faucet, Options, RequestState, Settings};
     use anyhow::Result;
     use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
     use async_std::task::spawn;
     use ethers::{
+        core::rand::thread_rng,
         providers::{Http, Middleware, Provider},
+        signers::{LocalWallet, Signer},
         types::U256,
         utils::parse_ether,
     };
     use polygon_zkevm_adaptor::{Layer1Backend, SequencerZkEvmDemo};
     use sequencer_utils::AnvilOptions;
-    use std::time::Duration;
+    use std::{
+        any::Any,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
     use surf_disco::Client;
+    use url::Url;
+
+    /// A faucet serving the web API on a fresh anvil node.
+    pub(crate) struct TestFaucet {
+        pub options: Options,
+        pub state: WebState,
+        pub client: Client<FaucetError>,
+        // Keep the node and the faucet tasks running until the end of the test.
+        _anvil: Box<dyn Any>,
+        _handle: Box<dyn Any>,
+    }
+
+    /// Start a faucet and its web server on a fresh anvil node.
+    ///
+    /// The provider URLs and the port of `options` are replaced by those of the node and an unused
+    /// port.
+    pub(crate) async fn start_test_faucet(options: Options) -> Result<TestFaucet> {
+        let anvil = AnvilOptions::default().spawn().await;
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            port: portpicker::pick_unused_port().unwrap(),
+            ..options
+        };
+
+        let (sender, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options.clone(), receiver).await?;
+        let state = WebState::new(sender, &faucet);
+        let handle = faucet.start().await;
+        spawn(serve(options.port, state.clone()));
+
+        let client =
+            Client::<FaucetError>::new(format!("http://127.0.0.1:{}", options.port).parse()?);
+        // Avoids waiting 10 seconds for the retry in `connect`.
+        async_std::task::sleep(Duration::from_millis(100)).await;
+        client.connect(None).await;
+
+        Ok(TestFaucet {
+            options,
+            state,
+            client,
+            _anvil: Box::new(anvil),
+            _handle: Box::new(handle),
+        })
+    }
 
     async fn run_faucet_test(options: Options, num_transfers: usize) -> Result<()> {
         let client =
@@ -129,12 +937,14 @@ mod test {
 
         let recipient = Address::random();
         let mut total_transfer_amount = U256::zero();
+        let mut ids = vec![];
 
         for _ in 0..num_transfers {
-            client
+            let id: RequestId = client
                 .post(&format!("faucet/request/{recipient:?}"))
                 .send()
                 .await?;
+            ids.push(id);
 
             total_transfer_amount += options.faucet_grant_amount;
         }
@@ -149,6 +959,34 @@ mod test {
             async_std::task::sleep(Duration::from_secs(1)).await;
         }
 
+        // The receipts may be processed shortly after the balance changes.
+        for id in ids {
+            loop {
+                let status: RequestStatus =
+                    client.get(&format!("faucet/status/{id}")).send().await?;
+                tracing::info!("Status of request {id} is {status:?}");
+                if status.state == RequestState::Confirmed {
+                    assert_eq!(status.to, recipient);
+                    assert_eq!(status.receipt_status, Some(1));
+                    assert!(status.sender.is_some());
+                    assert!(status.tx_hash.is_some());
+                    break;
+                }
+                async_std::task::sleep(Duration::from_secs(1)).await;
+            }
+        }
+
+        let info: FaucetInfo = client.get("faucet/info").send().await?;
+        assert_eq!(info.grant_amount, options.faucet_grant_amount);
+        assert_eq!(info.wallets.len(), options.num_clients);
+
+        // Unknown requests are reported as such.
+        assert!(client
+            .get::<RequestStatus>(&format!("faucet/status/{}", u64::MAX))
+            .send()
+            .await
+            .is_err());
+
         Ok(())
     }
 
@@ -177,15 +1015,144 @@ mod test {
 
         // Start the faucet
         let faucet = Faucet::create(options.clone(), receiver).await?;
+        let state = WebState::new(sender, &faucet);
         let _handle = faucet.start().await;
 
         // Start the web server
-        spawn(async move { serve(options.port, WebState::new(sender)).await });
+        spawn(serve(options.port, state));
 
         run_faucet_test(options, 30).await?;
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_faucet_enqueue_failure() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let mut ws_url = anvil.url();
+        ws_url.set_scheme("ws").unwrap();
+        let options = Options {
+            num_clients: 1,
+            provider_url_ws: ws_url,
+            provider_url_http: anvil.url(),
+            ..Default::default()
+        };
+        let (_, receiver) = async_std::channel::unbounded();
+        let faucet = Faucet::create(options, receiver).await?;
+        // Nobody receives the requests sent to this queue.
+        let (sender, _) = async_std::channel::unbounded();
+        let state = WebState::new(sender, &faucet);
+        let chain = state.chain(None)?;
+
+        let recipient = Address::random();
+        assert!(chain
+            .request(None, recipient, Asset::Native, Source::Web)
+            .await
+            .is_err());
+
+        // The request failed instead of staying queued, so later requests are
+        // not merged into it.
+        assert_eq!(
+            faucet.request_status(0).await.unwrap().state,
+            RequestState::Failed
+        );
+        assert!(matches!(
+            faucet
+                .enqueue_request(recipient, Asset::Native, Source::Web)
+                .await,
+            Ok(Enqueued::New(_))
+        ));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_multi_chain() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let l1 = AnvilOptions::default().spawn().await;
+        let l2 = AnvilOptions::default().spawn().await;
+        let port = portpicker::pick_unused_port().unwrap();
+
+        // Anvil pre-funds the first 10 wallets, so both ranges are funded.
+        let chain = |name: &str, url: Url, client_index_offset| {
+            let mut ws_url = url.clone();
+            ws_url.set_scheme("ws").unwrap();
+            Options {
+                chain_name: name.to_string(),
+                num_clients: 2,
+                client_index_offset,
+                faucet_grant_amount: parse_ether(1).unwrap(),
+                provider_url_ws: ws_url,
+                provider_url_http: url,
+                port,
+                ..Default::default()
+            }
+        };
+
+        let mut state = WebState::default();
+        let mut _handles = vec![];
+        for options in [chain("l1", l1.url(), 0), chain("l2", l2.url(), 5)] {
+            let (sender, receiver) = async_std::channel::unbounded();
+            let faucet = Faucet::create(options, receiver).await?;
+            state = state.with_chain(sender, &faucet);
+            _handles.push(faucet.start().await);
+        }
+        spawn(serve(port, state));
+
+        let client = Client::<FaucetError>::new(format!("http://localhost:{port}").parse()?);
+        async_std::task::sleep(Duration::from_millis(100)).await;
+        client.connect(None).await;
+
+        let chains: Vec<String> = client.get("faucet/chains").send().await?;
+        assert_eq!(chains, ["l1", "l2"]);
+
+        // The chains use different wallets.
+        let l1_info: FaucetInfo = client.get("faucet/l1/info").send().await?;
+        let l2_info: FaucetInfo = client.get("faucet/l2/info").send().await?;
+        assert_eq!(l1_info, client.get("faucet/info").send().await?);
+        for wallet in &l2_info.wallets {
+            assert!(l1_info
+                .wallets
+                .iter()
+                .all(|other| other.address != wallet.address));
+        }
+
+        // Funds are only sent on the requested chain.
+        let recipient = Address::random();
+        let id: RequestId = client
+            .post(&format!("faucet/l2/request/{recipient:?}"))
+            .send()
+            .await?;
+        loop {
+            let status: RequestStatus =
+                client.get(&format!("faucet/l2/status/{id}")).send().await?;
+            if status.state == RequestState::Confirmed {
+                break;
+            }
+            async_std::task::sleep(Duration::from_secs(1)).await;
+        }
+        let l1_provider = Provider::<Http>::try_from(l1.url().to_string())?;
+        let l2_provider = Provider::<Http>::try_from(l2.url().to_string())?;
+        assert_eq!(
+            l2_provider.get_balance(recipient, None).await?,
+            parse_ether(1)?
+        );
+        assert_eq!(l1_provider.get_balance(recipient, None).await?, 0.into());
+
+        // Unknown chains are rejected.
+        assert!(client
+            .post::<RequestId>(&format!("faucet/l3/request/{recipient:?}"))
+            .send()
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
     // Currently this test fails if run together with the others tests due to
     // errors when talking to the zkevm-node weboscket RPC. It passes if its the
     // only running test.
@@ -253,10 +1220,11 @@ mod test {
 
         // Start the faucet
         let faucet = Faucet::create(options.clone(), receiver).await?;
+        let state = WebState::new(sender, &faucet);
         let _handle = faucet.start().await;
 
         // Start the web server
-        spawn(async move { serve(options.port, WebState::new(sender)).await });
+        spawn(serve(options.port, state));
 
         run_faucet_test(options.clone(), 3).await?;
 
@@ -267,4 +1235,324 @@ mod test {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_faucet_admin() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let TestFaucet {
+            options, client, ..
+        } = &start_test_faucet(Options {
+            num_clients: 2,
+            faucet_grant_amount: parse_ether(1).unwrap(),
+            admin_token: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .await?;
+        let auth = ("Authorization", "Bearer secret");
+
+        // Admin routes require the token.
+        assert!(client.post::<()>("admin/pause").send().await.is_err());
+        assert!(client
+            .post::<()>("admin/pause")
+            .header("Authorization", "Bearer wrong")
+            .send()
+            .await
+            .is_err());
+        assert!(
+            !client
+                .get::<Settings>("admin/settings")
+                .header(auth.0, auth.1)
+                .send()
+                .await?
+                .paused
+        );
+
+        // Requests are rejected while paused.
+        let recipient = Address::random();
+        client
+            .post::<()>("admin/pause")
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+        assert!(client
+            .post::<RequestId>(&format!("faucet/request/{recipient:?}"))
+            .send()
+            .await
+            .is_err());
+        client
+            .post::<()>("admin/resume")
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+
+        // Blocked addresses are rejected.
+        let blocked = Address::random();
+        client
+            .post::<()>(&format!("admin/block/{blocked:?}"))
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+        assert!(client
+            .post::<RequestId>(&format!("faucet/request/{blocked:?}"))
+            .send()
+            .await
+            .is_err());
+
+        // Grant amounts apply to new requests.
+        let amount: U256 = client
+            .post("admin/grant/2.5")
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+        assert_eq!(amount, parse_ether("2.5").unwrap());
+        client
+            .post::<RequestId>(&format!("faucet/request/{recipient:?}"))
+            .send()
+            .await?;
+        let provider = Provider::<Http>::try_from(options.provider_url_http.to_string())?;
+        while provider.get_balance(recipient, None).await? != amount {
+            async_std::task::sleep(Duration::from_secs(1)).await;
+        }
+
+        // The wallet pool can be resized.
+        client
+            .post::<()>("admin/resize/3")
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+        let settings: Settings = client
+            .get("admin/settings")
+            .header(auth.0, auth.1)
+            .send()
+            .await?;
+        assert_eq!(settings.num_clients, 3);
+        assert_eq!(settings.grant_amount, amount);
+        assert!(settings.blocklist.contains(&blocked));
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_proof_of_work() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let TestFaucet { client, .. } = &start_test_faucet(Options {
+            num_clients: 1,
+            faucet_grant_amount: parse_ether(1).unwrap(),
+            pow_difficulty: Some(8),
+            ..Default::default()
+        })
+        .await?;
+
+        // Requests without a solution are rejected.
+        let recipient = Address::random();
+        let path = format!("faucet/request/{recipient:?}");
+        assert!(client.post::<RequestId>(&path).send().await.is_err());
+
+        // A solution for another recipient is rejected.
+        let challenge: Challenge = client.get("faucet/challenge").send().await?;
+        assert!(challenge.difficulty >= 8);
+        let other = Address::random();
+        let solution = (0..)
+            .find(|solution| {
+                crate::verify(challenge.nonce, other, *solution, challenge.difficulty)
+                    && !crate::verify(challenge.nonce, recipient, *solution, challenge.difficulty)
+            })
+            .unwrap();
+        assert!(client
+            .post::<RequestId>(&path)
+            .header(POW_HEADER, format!("{:?}:{solution}", challenge.nonce))
+            .send()
+            .await
+            .is_err());
+
+        // A valid solution is accepted once.
+        let solution = crate::solve(challenge.nonce, recipient, challenge.difficulty);
+        let header = format!("{:?}:{solution}", challenge.nonce);
+        client
+            .post::<RequestId>(&path)
+            .header(POW_HEADER, header.clone())
+            .send()
+            .await?;
+        assert!(client
+            .post::<RequestId>(&path)
+            .header(POW_HEADER, header)
+            .send()
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_faucet_signature() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let TestFaucet {
+            options, client, ..
+        } = &start_test_faucet(Options {
+            num_clients: 1,
+            faucet_grant_amount: parse_ether(1).unwrap(),
+            require_signature: true,
+            pow_difficulty: Some(8),
+            address_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        })
+        .await?;
+        let provider = Provider::<Http>::try_from(options.provider_url_http.to_string())?;
+        let chain_id = provider.get_chainid().await?.as_u64();
+
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let recipient = wallet.address();
+        let path = format!("faucet/request/{recipient:?}");
+        let expiry = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 600;
+        let message = crate::request_message(chain_id, recipient, expiry);
+        let signature = format!("{expiry}:{}", wallet.sign_message(&message).await?);
+        let solve = move || async move {
+            let challenge: Challenge = client.get("faucet/challenge").send().await?;
+            let solution = crate::solve(challenge.nonce, recipient, challenge.difficulty);
+            anyhow::Ok(format!("{:?}:{solution}", challenge.nonce))
+        };
+
+        // Unsigned requests are rejected.
+        assert!(matches!(
+            client
+                .post::<RequestId>(&path)
+                .header(POW_HEADER, solve().await?)
+                .send()
+                .await,
+            Err(FaucetError::Signature { .. })
+        ));
+
+        // So are requests signed by someone other than the recipient.
+        let other = LocalWallet::new(&mut thread_rng());
+        let forged = format!("{expiry}:{}", other.sign_message(&message).await?);
+        assert!(matches!(
+            client
+                .post::<RequestId>(&path)
+                .header(SIGNATURE_HEADER, forged)
+                .header(POW_HEADER, solve().await?)
+                .send()
+                .await,
+            Err(FaucetError::Signature { .. })
+        ));
+
+        // A signed request which fails a later check does not count against the rate limits.
+        assert!(matches!(
+            client
+                .post::<RequestId>(&path)
+                .header(SIGNATURE_HEADER, signature.clone())
+                .send()
+                .await,
+            Err(FaucetError::ProofOfWork { .. })
+        ));
+
+        // A valid signed request is accepted, after which the address cooldown applies.
+        client
+            .post::<RequestId>(&path)
+            .header(SIGNATURE_HEADER, signature.clone())
+            .header(POW_HEADER, solve().await?)
+            .send()
+            .await?;
+        assert!(matches!(
+            client
+                .post::<RequestId>(&path)
+                .header(SIGNATURE_HEADER, signature)
+                .header(POW_HEADER, solve().await?)
+                .send()
+                .await,
+            Err(FaucetError::RateLimited { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let other_proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let ip = |ip: &str| Some(ip.to_string());
+
+        // The header is ignored unless the request comes from a trusted proxy.
+        assert_eq!(
+            client_ip(Some("1.2.3.4:5678"), Some("9.9.9.9"), &[]),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            client_ip(Some("1.2.3.4:5678"), Some("9.9.9.9"), &[proxy]),
+            ip("1.2.3.4")
+        );
+
+        // Behind trusted proxies, the client is the last hop which is not a trusted proxy. Earlier
+        // hops may have been forged by the client.
+        assert_eq!(
+            client_ip(Some("10.0.0.1:5678"), Some("6.6.6.6, 9.9.9.9"), &[proxy]),
+            ip("9.9.9.9")
+        );
+        assert_eq!(
+            client_ip(
+                Some("10.0.0.1:5678"),
+                Some("6.6.6.6, 9.9.9.9, 10.0.0.2"),
+                &[proxy, other_proxy]
+            ),
+            ip("9.9.9.9")
+        );
+        assert_eq!(
+            client_ip(Some("10.0.0.1:5678"), None, &[proxy]),
+            ip("10.0.0.1")
+        );
+
+        assert_eq!(client_ip(Some("[::1]:5678"), None, &[]), ip("::1"));
+        assert_eq!(client_ip(None, Some("9.9.9.9"), &[proxy]), None);
+    }
+
+    #[async_std::test]
+    async fn test_faucet_rate_limits() -> Result<()> {
+        setup_logging();
+        setup_backtrace();
+
+        let TestFaucet { options, .. } = &start_test_faucet(Options {
+            num_clients: 1,
+            faucet_grant_amount: parse_ether(1).unwrap(),
+            address_cooldown: Duration::from_secs(60),
+            ip_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        })
+        .await?;
+        let request = |path: String, forwarded_for: &'static str| {
+            let url = format!("http://127.0.0.1:{}/faucet/{path}", options.port);
+            async move {
+                surf::post(url)
+                    .header("X-Forwarded-For", forwarded_for)
+                    .await
+                    .map_err(|err| err.into_inner())
+            }
+        };
+
+        // A rejected request does not count against the rate limits.
+        let recipient = Address::random();
+        let res = request(format!("request/{recipient:?}/UNKNOWN"), "1.1.1.1").await?;
+        assert_eq!(res.status(), StatusCode::NotFound);
+        let res = request(format!("request/{recipient:?}"), "1.1.1.1").await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+
+        // Rate limited requests tell the client when to retry.
+        let res = request(format!("request/{recipient:?}"), "1.1.1.1").await?;
+        assert_eq!(res.status(), StatusCode::TooManyRequests);
+        let retry_after: u64 = res.header("Retry-After").unwrap().as_str().parse()?;
+        assert!((1..=60).contains(&retry_after), "{retry_after}");
+
+        // The faucet is not behind a trusted proxy, so a forged header does not evade the IP
+        // cooldown.
+        let other = Address::random();
+        let res = request(format!("request/{other:?}"), "2.2.2.2").await?;
+        assert_eq!(res.status(), StatusCode::TooManyRequests);
+        assert!(res.header("Retry-After").is_some());
+
+        Ok(())
+    }
 }
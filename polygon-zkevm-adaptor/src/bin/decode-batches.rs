@@ -0,0 +1,216 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::Parser;
+use ethers::prelude::*;
+use polygon_zkevm_adaptor::query_service::PolygonZkevmBlock;
+use serde::Serialize;
+use snafu::{ResultExt, Whatever};
+use std::sync::Arc;
+use surf_disco::Url;
+use tide_disco::error::ServerError;
+use zkevm::{
+    polygon_zkevm::decode_transactions,
+    verified_batches::{verified_batches_in_range, verified_batches_in_transaction},
+    EvmTransaction,
+};
+use zkevm_contract_bindings::{
+    polygon_zk_evm::PolygonZkEVM, polygon_zk_evm_global_exit_root::PolygonZkEVMGlobalExitRoot,
+};
+
+/// Decode the batches verified on L1 by `verifyBatches` and `verifyBatchesTrustedAggregator`.
+///
+/// Prints one JSON object per verified range of batches. If an adaptor query service URL is given,
+/// also prints one JSON object per batch with the transactions of the block served by the adaptor
+/// for the same height, and checks the accumulated input hash of those blocks against the one
+/// verified on L1.
+#[derive(Parser)]
+pub struct Options {
+    /// URL of layer 1 Ethereum JSON-RPC provider.
+    #[arg(long, env = "ESPRESSO_ZKEVM_L1_PROVIDER")]
+    pub l1_provider: Url,
+
+    /// Address of the rollup contract.
+    #[arg(long, env = "ESPRESSO_ZKEVM_ROLLUP_ADDRESS")]
+    pub rollup_address: Address,
+
+    /// Hash of a single verifying transaction to decode.
+    #[arg(
+        long,
+        required_unless_present = "from_block",
+        conflicts_with = "from_block"
+    )]
+    pub transaction: Option<H256>,
+
+    /// First L1 block to search for verifying transactions.
+    #[arg(long)]
+    pub from_block: Option<u64>,
+
+    /// Last L1 block to search for verifying transactions.
+    ///
+    /// Defaults to the latest L1 block.
+    #[arg(long, requires = "from_block")]
+    pub to_block: Option<u64>,
+
+    /// URL of the Polygon zkEVM query service adaptor to compare the batches against.
+    #[arg(long, env = "ESPRESSO_ZKEVM_ADAPTOR_QUERY_URL")]
+    pub adaptor_url: Option<Url>,
+}
+
+/// A batch as served by the adaptor.
+#[derive(Serialize)]
+struct AdaptorBatch {
+    batch_number: u64,
+    adaptor_height: u64,
+    l1_block: u64,
+    timestamp: u64,
+    global_exit_root: H256,
+    transactions: String,
+    transaction_hashes: Vec<H256>,
+}
+
+/// Comparison of a range of batches verified on L1 with the blocks served by the adaptor.
+#[derive(Serialize)]
+struct RangeCheck {
+    init_num_batch: u64,
+    final_new_batch: u64,
+    acc_input_hash_matches: bool,
+    error: Option<String>,
+}
+
+/// Print `value` as a line of JSON.
+fn print(value: &impl Serialize) -> Result<(), Whatever> {
+    println!(
+        "{}",
+        serde_json::to_string(value).whatever_context("failed to serialize output")?
+    );
+    Ok(())
+}
+
+#[async_std::main]
+async fn main() -> Result<(), Whatever> {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+    let l1 = Arc::new(
+        Provider::<Http>::try_from(opt.l1_provider.to_string())
+            .whatever_context("invalid L1 provider URL")?,
+    );
+
+    let ranges = if let Some(hash) = opt.transaction {
+        vec![verified_batches_in_transaction(&*l1, hash)
+            .await
+            .whatever_context("failed to decode verifying transaction")?]
+    } else {
+        let to_block = match opt.to_block {
+            Some(block) => block,
+            None => l1
+                .get_block_number()
+                .await
+                .whatever_context("failed to get L1 block number")?
+                .as_u64(),
+        };
+        // `from_block` is required unless `transaction` is given.
+        let from_block = opt.from_block.unwrap_or_default();
+        verified_batches_in_range(&*l1, opt.rollup_address, from_block, to_block)
+            .await
+            .whatever_context("failed to decode verifying transactions")?
+    };
+
+    let Some(url) = opt.adaptor_url else {
+        for range in ranges {
+            print(&range)?;
+        }
+        return Ok(());
+    };
+
+    let adaptor = surf_disco::Client::<ServerError>::new(
+        url.join("availability")
+            .whatever_context("invalid adaptor URL")?,
+    );
+    adaptor.connect(None).await;
+    let rollup = PolygonZkEVM::new(opt.rollup_address, l1.clone());
+    let sequencer = rollup
+        .trusted_sequencer()
+        .call()
+        .await
+        .whatever_context("failed to get trusted sequencer")?;
+    let global_exit_root = PolygonZkEVMGlobalExitRoot::new(
+        rollup
+            .global_exit_root_manager()
+            .call()
+            .await
+            .whatever_context("failed to get global exit root manager")?,
+        l1.clone(),
+    );
+
+    for range in ranges {
+        print(&range)?;
+
+        let mut inputs = vec![];
+        for batch_number in range.batch_numbers() {
+            // Batch numbers on L1 are 1-indexed but HotShot block numbers are 0-indexed.
+            let height = batch_number - 1;
+            let block: PolygonZkevmBlock = adaptor
+                .get(&format!("block/{height}"))
+                .send()
+                .await
+                .with_whatever_context(|_| {
+                    format!("failed to fetch block {height} from adaptor")
+                })?;
+            let ger = global_exit_root
+                .get_last_global_exit_root()
+                .block(block.l1_block)
+                .call()
+                .await
+                .with_whatever_context(|_| {
+                    format!(
+                        "failed to get global exit root at L1 block {}",
+                        block.l1_block
+                    )
+                })?;
+            let input = block
+                .batch_input(ger.into())
+                .with_whatever_context(|_| format!("block {height} has invalid transactions"))?;
+            let transaction_hashes = decode_transactions(&input.transactions)
+                .with_whatever_context(|_| format!("block {height} has invalid transactions"))?
+                .iter()
+                .map(EvmTransaction::hash)
+                .collect();
+            let batch = AdaptorBatch {
+                batch_number,
+                adaptor_height: height,
+                l1_block: block.l1_block,
+                timestamp: block.timestamp,
+                global_exit_root: input.global_exit_root,
+                transactions: block.transactions,
+                transaction_hashes,
+            };
+            print(&batch)?;
+            inputs.push(input);
+        }
+
+        let result = range.check(&inputs, sequencer);
+        if let Err(err) = &result {
+            tracing::warn!(
+                "batches {}..={} differ from adaptor blocks: {err}",
+                range.init_num_batch + 1,
+                range.final_new_batch
+            );
+        }
+        let check = RangeCheck {
+            init_num_batch: range.init_num_batch,
+            final_new_batch: range.final_new_batch,
+            acc_input_hash_matches: result.is_ok(),
+            error: result.err().map(|err| err.to_string()),
+        };
+        print(&check)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,613 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use async_compatibility_layer::logging::{setup_backtrace, setup_logging};
+use clap::{Parser, Subcommand, ValueEnum};
+use ethers::{
+    abi::{
+        token::{LenientTokenizer, Tokenizer},
+        AbiParser, ParamType, Token,
+    },
+    prelude::*,
+    signers::coins_bip39::English,
+    types::transaction::{eip2718::TypedTransaction, eip2930::AccessList},
+    utils::parse_ether,
+};
+use futures::StreamExt;
+use hotshot_query_service::availability::BlockQueryData;
+use sequencer::SeqTypes;
+use serde::Serialize;
+use snafu::{ensure_whatever, whatever, ResultExt, Whatever};
+use std::{fs, path::PathBuf};
+use surf_disco::Url;
+use tide_disco::error::ServerError;
+use zkevm::{polygon_zkevm::encode_transactions, EvmTransaction, ZkEvm};
+
+/// Build and sign a transaction for a zkEVM rollup.
+///
+/// The transaction is built and signed offline, from a mnemonic or from an encrypted JSON keystore
+/// like the ones written by `keygen`. Fields which are not given on the command line are filled in
+/// from `--rpc` if it is set. Prints the signed transaction as JSON, including the Polygon zkEVM
+/// batch encoding of the transaction.
+///
+/// With `--submit`, the transaction is also sent to the `eth_sendRawTransaction` endpoint of the
+/// zkEVM adaptor, and with `--sequencer-url` this waits until the transaction is sequenced.
+#[derive(Parser)]
+struct Options {
+    /// Mnemonic of the signing wallet.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_TX_MNEMONIC",
+        required_unless_present = "keystore",
+        conflicts_with = "keystore"
+    )]
+    mnemonic: Option<String>,
+
+    /// Index of the account in the mnemonic wallet.
+    #[arg(long, env = "ESPRESSO_ZKEVM_TX_ACCOUNT_INDEX", default_value = "0")]
+    account_index: u32,
+
+    /// Path to an encrypted JSON keystore for the signing wallet.
+    #[arg(long, env = "ESPRESSO_ZKEVM_KEYSTORE", requires = "password_file")]
+    keystore: Option<PathBuf>,
+
+    /// File containing the password of the keystore.
+    #[arg(long, env = "ESPRESSO_ZKEVM_KEYSTORE_PASSWORD_FILE")]
+    password_file: Option<PathBuf>,
+
+    /// Chain ID of the zkEVM.
+    ///
+    /// Required unless `--rpc` is given.
+    #[arg(
+        long,
+        env = "ESPRESSO_ZKEVM_L2_CHAIN_ID",
+        required_unless_present = "rpc"
+    )]
+    chain_id: Option<u64>,
+
+    /// Nonce of the transaction.
+    ///
+    /// Required unless `--rpc` is given.
+    #[arg(long, required_unless_present = "rpc")]
+    nonce: Option<U256>,
+
+    /// Gas limit of the transaction.
+    ///
+    /// Required unless `--rpc` is given, in which case gas is estimated.
+    #[arg(long, required_unless_present = "rpc")]
+    gas_limit: Option<U256>,
+
+    /// Type of the transaction.
+    #[arg(long, value_enum, default_value = "legacy")]
+    tx_type: TxType,
+
+    /// Gas price, in wei, of a legacy or EIP-2930 transaction.
+    ///
+    /// If not given, this is read from `--rpc`, or else defaults to 1 gwei.
+    #[arg(long)]
+    gas_price: Option<U256>,
+
+    /// Maximum fee per gas, in wei, of an EIP-1559 transaction.
+    ///
+    /// If not given, this is estimated from `--rpc`, or else defaults to 1 gwei.
+    #[arg(long)]
+    max_fee_per_gas: Option<U256>,
+
+    /// Maximum priority fee per gas, in wei, of an EIP-1559 transaction.
+    ///
+    /// If not given, this is estimated from `--rpc`, or else defaults to 0.
+    #[arg(long)]
+    max_priority_fee_per_gas: Option<U256>,
+
+    /// Amount of ether to send with the transaction.
+    #[arg(long, default_value = "0", value_parser = |arg: &str| parse_ether(arg))]
+    value: U256,
+
+    /// JSON-RPC of a zkEVM node used to fill in the chain ID, nonce, gas limit and gas price.
+    #[arg(long, env = "ESPRESSO_ZKEVM_L2_PROVIDER")]
+    rpc: Option<Url>,
+
+    /// JSON-RPC of the zkEVM adaptor to submit the transaction to.
+    #[arg(long, env = "ESPRESSO_ZKEVM_ADAPTOR_RPC_URL")]
+    submit: Option<Url>,
+
+    /// URL of a HotShot query service, used to wait until a submitted transaction is sequenced.
+    #[arg(long, env = "ESPRESSO_SEQUENCER_URL", requires = "submit")]
+    sequencer_url: Option<Url>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TxType {
+    Legacy,
+    Eip2930,
+    Eip1559,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Transfer ether to an address.
+    Transfer {
+        /// Recipient of the transfer.
+        to: Address,
+    },
+    /// Deploy a contract.
+    Deploy {
+        /// Hex encoded bytecode of the contract, or `@path` to read it from a file.
+        bytecode: String,
+        /// Constructor signature, e.g. `constructor(uint256,address)`.
+        #[arg(long)]
+        constructor: Option<String>,
+        /// Constructor arguments.
+        args: Vec<String>,
+    },
+    /// Call a contract function.
+    Call {
+        /// Address of the contract.
+        to: Address,
+        /// Function signature, e.g. `transfer(address,uint256)`.
+        function: String,
+        /// Function arguments.
+        args: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct Output {
+    from: Address,
+    /// The signed transaction, as accepted by `eth_sendRawTransaction`.
+    raw: Bytes,
+    /// The hash of the transaction.
+    hash: H256,
+    /// The transaction encoded as in a Polygon zkEVM batch.
+    batch_encoding: Bytes,
+    /// The HotShot block in which the transaction was sequenced, if we waited for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequenced_in_block: Option<u64>,
+}
+
+fn tokenize(types: &[ParamType], args: &[String]) -> Result<Vec<Token>, Whatever> {
+    ensure_whatever!(
+        types.len() == args.len(),
+        "expected {} arguments, got {}",
+        types.len(),
+        args.len()
+    );
+    types
+        .iter()
+        .zip(args)
+        .map(|(ty, arg)| {
+            LenientTokenizer::tokenize(ty, arg)
+                .with_whatever_context(|_| format!("invalid {ty} argument {arg}"))
+        })
+        .collect()
+}
+
+fn read_bytecode(bytecode: &str) -> Result<Bytes, Whatever> {
+    let hex = match bytecode.strip_prefix('@') {
+        Some(path) => {
+            fs::read_to_string(path).with_whatever_context(|_| format!("failed to read {path}"))?
+        }
+        None => bytecode.to_string(),
+    };
+    hex.trim()
+        .parse()
+        .whatever_context("bytecode is not valid hex")
+}
+
+/// Load the signing wallet from a mnemonic or from a keystore and password file.
+fn load_wallet(opt: &Options) -> Result<LocalWallet, Whatever> {
+    match (&opt.mnemonic, &opt.keystore, &opt.password_file) {
+        (Some(mnemonic), _, _) => MnemonicBuilder::<English>::default()
+            .phrase(mnemonic.as_str())
+            .index(opt.account_index)
+            .whatever_context("invalid account index")?
+            .build()
+            .whatever_context("invalid mnemonic"),
+        (None, Some(keystore), Some(password_file)) => {
+            let password = fs::read_to_string(password_file)
+                .with_whatever_context(|_| format!("failed to read {}", password_file.display()))?;
+            // Password files usually end with a newline, which is not part of the password.
+            let password = password.trim_end_matches(['\r', '\n']);
+            LocalWallet::decrypt_keystore(keystore, password)
+                .with_whatever_context(|_| format!("failed to decrypt {}", keystore.display()))
+        }
+        _ => unreachable!("clap requires a mnemonic or keystore and password file"),
+    }
+}
+
+impl Command {
+    /// The recipient and calldata of the transaction.
+    fn to_and_data(&self) -> Result<(Option<Address>, Bytes), Whatever> {
+        match self {
+            Self::Transfer { to } => Ok((Some(*to), Bytes::default())),
+            Self::Deploy {
+                bytecode,
+                constructor,
+                args,
+            } => {
+                let bytecode = read_bytecode(bytecode)?;
+                let data = match constructor {
+                    Some(constructor) => {
+                        let constructor = AbiParser::default()
+                            .parse_constructor(constructor)
+                            .whatever_context("invalid constructor signature")?;
+                        let types: Vec<_> =
+                            constructor.inputs.iter().map(|p| p.kind.clone()).collect();
+                        constructor
+                            .encode_input(bytecode.to_vec(), &tokenize(&types, args)?)
+                            .whatever_context("failed to encode constructor arguments")?
+                            .into()
+                    }
+                    None => {
+                        ensure_whatever!(args.is_empty(), "arguments given without --constructor");
+                        bytecode
+                    }
+                };
+                Ok((None, data))
+            }
+            Self::Call { to, function, args } => {
+                let function = AbiParser::default()
+                    .parse_function(function)
+                    .whatever_context("invalid function signature")?;
+                let types: Vec<_> = function.inputs.iter().map(|p| p.kind.clone()).collect();
+                let data = function
+                    .encode_input(&tokenize(&types, args)?)
+                    .whatever_context("failed to encode function arguments")?;
+                Ok((Some(*to), data.into()))
+            }
+        }
+    }
+}
+
+async fn build_transaction(
+    opt: &Options,
+    from: Address,
+    rpc: Option<&Provider<Http>>,
+) -> Result<TypedTransaction, Whatever> {
+    let (to, data) = opt.command.to_and_data()?;
+
+    let chain_id = match (opt.chain_id, rpc) {
+        (Some(chain_id), _) => chain_id,
+        (None, Some(rpc)) => rpc
+            .get_chainid()
+            .await
+            .whatever_context("failed to get chain ID")?
+            .as_u64(),
+        (None, None) => unreachable!("clap requires --chain-id or --rpc"),
+    };
+    let nonce = match (opt.nonce, rpc) {
+        (Some(nonce), _) => nonce,
+        (None, Some(rpc)) => rpc
+            .get_transaction_count(from, None)
+            .await
+            .whatever_context("failed to get nonce")?,
+        (None, None) => unreachable!("clap requires --nonce or --rpc"),
+    };
+
+    let mut tx: TypedTransaction = match opt.tx_type {
+        TxType::Legacy | TxType::Eip2930 => {
+            let gas_price = match (opt.gas_price, rpc) {
+                (Some(price), _) => price,
+                (None, Some(rpc)) => rpc
+                    .get_gas_price()
+                    .await
+                    .whatever_context("failed to get gas price")?,
+                (None, None) => U256::exp10(9),
+            };
+            let req = TransactionRequest::new()
+                .from(from)
+                .value(opt.value)
+                .data(data)
+                .nonce(nonce)
+                .gas_price(gas_price)
+                .chain_id(chain_id);
+            let req = match to {
+                Some(to) => req.to(to),
+                None => req,
+            };
+            match opt.tx_type {
+                TxType::Eip2930 => req.with_access_list(AccessList::default()).into(),
+                _ => req.into(),
+            }
+        }
+        TxType::Eip1559 => {
+            let (max_fee_per_gas, max_priority_fee_per_gas) =
+                match (opt.max_fee_per_gas, opt.max_priority_fee_per_gas, rpc) {
+                    (Some(fee), Some(tip), _) => (fee, tip),
+                    (fee, tip, Some(rpc)) => {
+                        let (estimated_fee, estimated_tip) = rpc
+                            .estimate_eip1559_fees(None)
+                            .await
+                            .whatever_context("failed to estimate fees")?;
+                        (fee.unwrap_or(estimated_fee), tip.unwrap_or(estimated_tip))
+                    }
+                    (fee, tip, None) => (fee.unwrap_or(U256::exp10(9)), tip.unwrap_or_default()),
+                };
+            let req = Eip1559TransactionRequest::new()
+                .from(from)
+                .value(opt.value)
+                .data(data)
+                .nonce(nonce)
+                .max_fee_per_gas(max_fee_per_gas)
+                .max_priority_fee_per_gas(max_priority_fee_per_gas)
+                .chain_id(chain_id);
+            match to {
+                Some(to) => req.to(to).into(),
+                None => req.into(),
+            }
+        }
+    };
+
+    let gas = match (opt.gas_limit, rpc) {
+        (Some(gas), _) => gas,
+        (None, Some(rpc)) => rpc
+            .estimate_gas(&tx, None)
+            .await
+            .whatever_context("failed to estimate gas")?,
+        (None, None) => unreachable!("clap requires --gas-limit or --rpc"),
+    };
+    tx.set_gas(gas);
+    Ok(tx)
+}
+
+/// Sign a transaction built by [build_transaction].
+async fn sign_transaction(
+    wallet: &LocalWallet,
+    tx: TypedTransaction,
+) -> Result<EvmTransaction, Whatever> {
+    // `build_transaction` always sets the chain ID.
+    let chain_id = tx.chain_id().unwrap().as_u64();
+    let sig = wallet
+        .clone()
+        .with_chain_id(chain_id)
+        .sign_transaction(&tx)
+        .await
+        .whatever_context("failed to sign transaction")?;
+    Ok(EvmTransaction::new(tx, sig))
+}
+
+/// Wait for a transaction to be sequenced, starting from HotShot block `from`.
+async fn wait_for_sequencing(
+    sequencer: &Url,
+    zkevm: ZkEvm,
+    hash: H256,
+    from: u64,
+) -> Result<u64, Whatever> {
+    let client = surf_disco::Client::<ServerError>::new(sequencer.clone());
+    client.connect(None).await;
+    let mut blocks = client
+        .socket(&format!("availability/stream/blocks/{from}"))
+        .subscribe::<BlockQueryData<SeqTypes>>()
+        .await
+        .whatever_context("failed to subscribe to blocks")?;
+    while let Some(block) = blocks.next().await {
+        let block = block.whatever_context("failed to fetch block")?;
+        if zkevm
+            .vm_transactions(block.block())
+            .iter()
+            .any(|txn| txn.hash() == hash)
+        {
+            return Ok(block.height());
+        }
+    }
+    whatever!("block stream ended before transaction {hash:?} was sequenced")
+}
+
+#[async_std::main]
+async fn main() -> Result<(), Whatever> {
+    setup_logging();
+    setup_backtrace();
+
+    let opt = Options::parse();
+
+    let wallet = load_wallet(&opt)?;
+    let rpc = opt
+        .rpc
+        .as_ref()
+        .map(|url| Provider::<Http>::try_from(url.to_string()))
+        .transpose()
+        .whatever_context("invalid RPC URL")?;
+
+    let from = wallet.address();
+    let tx = build_transaction(&opt, from, rpc.as_ref()).await?;
+    let tx = sign_transaction(&wallet, tx).await?;
+    let zkevm = ZkEvm {
+        chain_id: tx.transaction().chain_id().unwrap().as_u64(),
+    };
+
+    let mut output = Output {
+        from,
+        raw: tx.rlp_signed(),
+        hash: tx.hash(),
+        batch_encoding: encode_transactions([&tx]),
+        sequenced_in_block: None,
+    };
+
+    if let Some(adaptor) = &opt.submit {
+        // Remember the current block height before submitting, so we don't miss the block which
+        // includes our transaction.
+        let start_height = match &opt.sequencer_url {
+            Some(sequencer) => {
+                let client = surf_disco::Client::<ServerError>::new(sequencer.clone());
+                client.connect(None).await;
+                Some(
+                    client
+                        .get::<u64>("status/latest_block_height")
+                        .send()
+                        .await
+                        .whatever_context("failed to get block height")?,
+                )
+            }
+            None => None,
+        };
+
+        let adaptor = Provider::<Http>::try_from(adaptor.to_string())
+            .whatever_context("invalid adaptor URL")?;
+        let hash = adaptor
+            .send_raw_transaction(output.raw.clone())
+            .await
+            .whatever_context("failed to submit transaction")?
+            .tx_hash();
+        ensure_whatever!(
+            hash == output.hash,
+            "adaptor returned unexpected hash {hash:?}"
+        );
+        tracing::info!("submitted transaction {hash:?}");
+
+        if let (Some(sequencer), Some(start_height)) = (&opt.sequencer_url, start_height) {
+            output.sequenced_in_block =
+                Some(wait_for_sequencing(sequencer, zkevm, hash, start_height).await?);
+        }
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).whatever_context("failed to serialize output")?
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sequencer_utils::AnvilOptions;
+    use tempfile::TempDir;
+    use zkevm::polygon_zkevm::decode_transactions;
+
+    const MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    fn options(tx_type: &str, to: Address) -> Options {
+        options_with(tx_type, to, &[])
+    }
+
+    fn options_with(tx_type: &str, to: Address, args: &[&str]) -> Options {
+        let to = format!("{to:?}");
+        let args = [
+            "zkevm-tx",
+            "--mnemonic",
+            MNEMONIC,
+            "--chain-id",
+            "1001",
+            "--nonce",
+            "3",
+            "--gas-limit",
+            "21000",
+            "--tx-type",
+            tx_type,
+            "--value",
+            "1",
+        ]
+        .into_iter()
+        .chain(args.iter().copied())
+        .chain(["transfer", to.as_str()]);
+        Options::try_parse_from(args).unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_build_sign_decode() {
+        setup_logging();
+        setup_backtrace();
+
+        let to = Address::random();
+        for tx_type in ["legacy", "eip2930", "eip1559"] {
+            tracing::info!("testing {tx_type} transaction");
+            let opt = options(tx_type, to);
+            let wallet = load_wallet(&opt).unwrap();
+            let tx = build_transaction(&opt, wallet.address(), None)
+                .await
+                .unwrap();
+            match (tx_type, &tx) {
+                ("legacy", TypedTransaction::Legacy(_))
+                | ("eip2930", TypedTransaction::Eip2930(_))
+                | ("eip1559", TypedTransaction::Eip1559(_)) => {}
+                _ => panic!("built wrong transaction type for {tx_type}: {tx:?}"),
+            }
+            let tx = sign_transaction(&wallet, tx).await.unwrap();
+
+            // The raw transaction decodes to the same signed transaction.
+            let raw = EvmTransaction::decode(&tx.rlp_signed()).unwrap();
+            assert_eq!(raw.hash(), tx.hash());
+            assert_eq!(raw.recover_sender().unwrap(), wallet.address());
+            assert_eq!(raw.transaction().to(), Some(&to.into()));
+            assert_eq!(raw.transaction().value(), Some(&parse_ether("1").unwrap()));
+            assert_eq!(raw.transaction().nonce(), Some(&3.into()));
+            assert_eq!(raw.transaction().chain_id(), Some(1001.into()));
+
+            // So does the batch encoding.
+            let batch = decode_transactions(&encode_transactions([&tx])).unwrap();
+            assert_eq!(batch.len(), 1);
+            assert_eq!(batch[0].hash(), tx.hash());
+            assert_eq!(batch[0].recover_sender().unwrap(), wallet.address());
+        }
+    }
+
+    #[async_std::test]
+    async fn test_eip1559_fees_from_rpc() {
+        setup_logging();
+        setup_backtrace();
+
+        let anvil = AnvilOptions::default().spawn().await;
+        let rpc = Provider::<Http>::try_from(anvil.url().to_string()).unwrap();
+        let (_, estimated_tip) = rpc.estimate_eip1559_fees(None).await.unwrap();
+        assert!(!estimated_tip.is_zero());
+
+        let tip = |args: &[&str]| {
+            let opt = options_with("eip1559", Address::random(), args);
+            let rpc = rpc.clone();
+            async move {
+                let from = load_wallet(&opt).unwrap().address();
+                match build_transaction(&opt, from, Some(&rpc)).await.unwrap() {
+                    TypedTransaction::Eip1559(tx) => tx.max_priority_fee_per_gas.unwrap(),
+                    tx => panic!("built wrong transaction type: {tx:?}"),
+                }
+            }
+        };
+
+        // The tip is estimated by the RPC unless it is given.
+        assert_eq!(tip(&[]).await, estimated_tip);
+        assert_eq!(tip(&["--max-priority-fee-per-gas", "7"]).await, 7.into());
+    }
+
+    #[async_std::test]
+    async fn test_keystore_password_file() {
+        setup_logging();
+        setup_backtrace();
+
+        let dir = TempDir::new().unwrap();
+        let (wallet, name) =
+            LocalWallet::new_keystore(dir.path(), &mut rand::thread_rng(), "secret", None).unwrap();
+        let password_file = dir.path().join("password");
+        // The trailing newline is not part of the password.
+        fs::write(&password_file, "secret\n").unwrap();
+
+        let keystore = dir.path().join(name);
+        let opt = Options::try_parse_from([
+            "zkevm-tx",
+            "--keystore",
+            keystore.to_str().unwrap(),
+            "--password-file",
+            password_file.to_str().unwrap(),
+            "--chain-id",
+            "1001",
+            "--nonce",
+            "0",
+            "--gas-limit",
+            "21000",
+            "transfer",
+            &format!("{:?}", Address::random()),
+        ])
+        .unwrap();
+        assert_eq!(load_wallet(&opt).unwrap().address(), wallet.address());
+
+        // A wrong password is an error.
+        fs::write(&password_file, "wrong").unwrap();
+        load_wallet(&opt).unwrap_err();
+    }
+}
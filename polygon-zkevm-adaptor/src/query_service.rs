@@ -73,7 +73,10 @@ use sequencer::SeqTypes;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use tide_disco::{error::ServerError, App, Error, StatusCode};
-use zkevm::{polygon_zkevm::encode_transactions, ZkEvm};
+use zkevm::{
+    polygon_zkevm::{encode_transactions, BatchInput},
+    ZkEvm,
+};
 
 type HotShotClient = surf_disco::Client<ServerError>;
 
@@ -345,11 +348,11 @@ impl BlockMapping {
 /// from the sequencer block and hex encoded according to the format expected by the zkEVM node. It
 /// also contains metadata fields used by the node to associate this L2 block with an L1 block.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct PolygonZkevmBlock {
-    timestamp: u64,
-    height: u64,
-    l1_block: u64,
-    transactions: String,
+pub struct PolygonZkevmBlock {
+    pub timestamp: u64,
+    pub height: u64,
+    pub l1_block: u64,
+    pub transactions: String,
 }
 
 impl PolygonZkevmBlock {
@@ -361,6 +364,18 @@ impl PolygonZkevmBlock {
             transactions: encode_transactions(zkevm.vm_transactions(l2_block.block())).to_string(),
         }
     }
+
+    /// The inputs of the L1 batch corresponding to this block.
+    ///
+    /// The global exit root is not part of the block itself. It is the global exit root which was
+    /// current as of `l1_block`, and must be looked up on L1 by the caller.
+    pub fn batch_input(&self, global_exit_root: H256) -> Result<BatchInput, ParseBytesError> {
+        Ok(BatchInput {
+            transactions: self.transactions.parse()?,
+            global_exit_root,
+            timestamp: self.timestamp,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -14,7 +14,7 @@ use futures::{
     stream::{StreamExt, TryStream, TryStreamExt},
 };
 use hotshot_query_service::availability::BlockQueryData;
-use polygon_zkevm_adaptor::{Layer1Backend, ZkEvmNode};
+use polygon_zkevm_adaptor::{query_service::PolygonZkevmBlock, Layer1Backend, ZkEvmNode};
 use sequencer::{
     api::{self, HttpOptions, QueryOptions},
     hotshot_commitment::{run_hotshot_commitment_task, CommitmentTaskOptions},
@@ -24,7 +24,7 @@ use sequencer_utils::{connect_rpc, wait_for_http};
 use std::fmt::Debug;
 use std::time::{Duration, Instant};
 use tempfile::TempDir;
-use zkevm::ZkEvm;
+use zkevm::{polygon_zkevm::acc_input_hash_chain, ZkEvm};
 use zkevm_contract_bindings::PolygonZkEVM;
 
 #[async_std::test]
@@ -142,13 +142,17 @@ async fn test_end_to_end() {
         .verify_batches_trusted_aggregator_filter()
         .from_block(l1_initial_block);
     let mut events = verified_filter.stream().await.unwrap();
-    loop {
+    let verified_batch = loop {
         let event = events.next().await.unwrap().unwrap();
         tracing::info!("batches verified up to {}/{}", event.num_batch, last_block);
         if event.num_batch > last_block {
-            break;
+            break event.num_batch;
         }
-    }
+    };
+
+    // Check that we can reproduce the accumulated input hash stored on L1 from the blocks served
+    // by the adaptor.
+    check_acc_input_hash(&node, &rollup, verified_batch).await;
 
     // Check that the malformed transaction is not present by the zkevm-node.
     assert!(l2
@@ -270,6 +274,41 @@ where
     }
 }
 
+async fn check_acc_input_hash<M: Middleware>(
+    node: &ZkEvmNode,
+    rollup: &PolygonZkEVM<M>,
+    num_batch: u64,
+) {
+    let adaptor = surf_disco::Client::<hotshot_query_service::Error>::new(
+        node.env().l2_adaptor_query().join("availability").unwrap(),
+    );
+    adaptor.connect(None).await;
+    let global_exit_root = &node.l1().global_exit_root;
+    let sequencer = rollup.trusted_sequencer().call().await.unwrap();
+
+    // Batch numbers on L1 are 1-indexed, so batch `n` corresponds to HotShot block `n - 1`.
+    let mut batches = vec![];
+    for height in 0..num_batch {
+        let block: PolygonZkevmBlock = adaptor
+            .get(&format!("block/{height}"))
+            .send()
+            .await
+            .unwrap();
+        let ger = global_exit_root
+            .get_last_global_exit_root()
+            .block(block.l1_block)
+            .call()
+            .await
+            .unwrap();
+        batches.push(block.batch_input(ger.into()).unwrap());
+    }
+
+    let (genesis_acc_input_hash, _, _) = rollup.sequenced_batches(0).call().await.unwrap();
+    let acc_input_hashes = acc_input_hash_chain(genesis_acc_input_hash.into(), &batches, sequencer);
+    let (expected, _, _) = rollup.sequenced_batches(num_batch).call().await.unwrap();
+    assert_eq!(*acc_input_hashes.last().unwrap(), H256::from(expected));
+}
+
 async fn await_transaction(rpc: &impl Middleware, hash: H256) -> Instant {
     // Note that awaiting a [PendingTransaction] will not work here -- [PendingTransaction] returns
     // [None] if the transaction is thrown out of the mempool, but since we bypassed the sequencer,
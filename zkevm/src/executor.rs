@@ -0,0 +1,582 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Local execution of zkEVM namespace transactions.
+//!
+//! This module applies the transactions sequenced for a zkEVM block-by-block to an in-memory EVM
+//! state, using [revm]. It does not need a zkevm-node, prover or state DB, so it is a fast offline
+//! reference for what the sequenced blocks mean, e.g. in tests or when checking the results of a
+//! zkevm-node.
+//!
+//! The executor only approximates Polygon zkEVM: it uses the London rules of the EVM with a base
+//! fee of zero, which is close to what the zkEVM supports, but it does not model zkEVM-specific
+//! differences such as the absence of some precompiles or the handling of the L2 bridge.
+
+use crate::{
+    polygon_zkevm::{decode_transactions, BatchInput, DecodeTransactionsError},
+    EvmTransaction,
+};
+use ethers::{
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Bytes, Log, NameOrAddress, H256, U256,
+    },
+    utils::{get_contract_address, GenesisAccount},
+};
+use revm::{
+    primitives::{
+        self, AccountInfo, Bytecode, CreateScheme, ExecutionResult, Output, SpecId, TransactTo,
+        TxEnv, B256,
+    },
+    DatabaseRef, Evm, InMemoryDB,
+};
+use snafu::Snafu;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Default gas limit of a block executed by the [Executor].
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 30_000_000;
+
+/// The receipt of a transaction executed by the [Executor].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    pub transaction_hash: H256,
+    pub from: Address,
+    pub to: Option<Address>,
+    /// The address of the contract created by this transaction, if any.
+    pub contract_address: Option<Address>,
+    /// Whether the transaction succeeded.
+    pub status: bool,
+    pub gas_used: u64,
+    pub cumulative_gas_used: u64,
+    pub logs: Vec<Log>,
+    /// Return data of a call, or revert data of a failed transaction.
+    pub output: Bytes,
+}
+
+/// A transaction which could not be included in a block.
+///
+/// Transactions are rejected if they fail validation, e.g. because of an invalid signature, an
+/// invalid nonce or insufficient balance to pay for gas. Rejected transactions have no effect on
+/// the state, like transactions which the zkevm-node discards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RejectedTransaction {
+    pub transaction_hash: H256,
+    pub reason: String,
+}
+
+/// The state of an account after executing a block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    pub balance: U256,
+    pub nonce: u64,
+}
+
+/// The result of executing a block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutedBlock {
+    pub number: u64,
+    pub timestamp: u64,
+    pub gas_used: u64,
+    pub receipts: Vec<Receipt>,
+    pub rejected: Vec<RejectedTransaction>,
+    /// The state of every account touched by the block, after executing the block.
+    pub state: BTreeMap<Address, AccountState>,
+}
+
+/// The genesis allocation of an [Executor] could not be loaded.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("invalid genesis account {address:?}: {msg}"))]
+pub struct InvalidGenesis {
+    pub address: Address,
+    pub msg: String,
+}
+
+/// An in-memory EVM executing the transactions of a zkEVM.
+#[derive(Clone, Debug)]
+pub struct Executor {
+    db: InMemoryDB,
+    chain_id: u64,
+    coinbase: Address,
+    block_gas_limit: u64,
+    next_block: u64,
+}
+
+impl Executor {
+    /// Create an executor for the chain `chain_id`, starting from a genesis allocation.
+    pub fn new(
+        chain_id: u64,
+        genesis: &BTreeMap<Address, GenesisAccount>,
+    ) -> Result<Self, InvalidGenesis> {
+        let mut db = InMemoryDB::default();
+        for (&genesis_address, account) in genesis {
+            let address = to_revm_address(genesis_address);
+            let code = account
+                .code
+                .clone()
+                .map(|code| Bytecode::new_raw(code.0.into()))
+                .unwrap_or_default();
+            db.insert_account_info(
+                address,
+                AccountInfo::new(
+                    to_revm_u256(account.balance),
+                    account.nonce.unwrap_or_default(),
+                    code.hash_slow(),
+                    code,
+                ),
+            );
+            for (slot, value) in account.storage.iter().flatten() {
+                db.insert_account_storage(
+                    address,
+                    primitives::U256::from_be_bytes(slot.0),
+                    primitives::U256::from_be_bytes(value.0),
+                )
+                .map_err(|err| InvalidGenesis {
+                    address: genesis_address,
+                    msg: err.to_string(),
+                })?;
+            }
+        }
+        Ok(Self {
+            db,
+            chain_id,
+            coinbase: Address::zero(),
+            block_gas_limit: DEFAULT_BLOCK_GAS_LIMIT,
+            next_block: 1,
+        })
+    }
+
+    /// Set the address which receives transaction fees.
+    pub fn with_coinbase(mut self, coinbase: Address) -> Self {
+        self.coinbase = coinbase;
+        self
+    }
+
+    /// Set the gas limit of each block.
+    pub fn with_block_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.block_gas_limit = gas_limit;
+        self
+    }
+
+    /// The number of the next block to be executed.
+    pub fn next_block(&self) -> u64 {
+        self.next_block
+    }
+
+    /// Execute a block of transactions on top of the current state.
+    pub fn execute_block(
+        &mut self,
+        timestamp: u64,
+        transactions: impl IntoIterator<Item = EvmTransaction>,
+    ) -> ExecutedBlock {
+        let number = self.next_block;
+        self.next_block += 1;
+
+        let chain_id = self.chain_id;
+        let coinbase = self.coinbase;
+        let block_gas_limit = self.block_gas_limit;
+        let mut evm = Evm::builder()
+            .with_db(&mut self.db)
+            .modify_cfg_env(|cfg| cfg.chain_id = chain_id)
+            .modify_block_env(|block| {
+                block.number = primitives::U256::from(number);
+                block.timestamp = primitives::U256::from(timestamp);
+                block.coinbase = to_revm_address(coinbase);
+                block.gas_limit = primitives::U256::from(block_gas_limit);
+                block.basefee = primitives::U256::ZERO;
+            })
+            .with_spec_id(SpecId::LONDON)
+            .build();
+
+        let mut touched = BTreeSet::new();
+        let mut receipts = vec![];
+        let mut rejected = vec![];
+        let mut gas_used = 0;
+        for tx in transactions {
+            let hash = tx.hash();
+            let from = match tx.recover_sender() {
+                Ok(from) => from,
+                Err(err) => {
+                    rejected.push(RejectedTransaction {
+                        transaction_hash: hash,
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let to = tx.transaction().to().and_then(|to| match to {
+                NameOrAddress::Address(address) => Some(*address),
+                NameOrAddress::Name(_) => None,
+            });
+            *evm.tx_mut() = tx_env(tx.transaction(), from);
+
+            let result = match evm.transact_commit() {
+                Ok(result) => result,
+                Err(err) => {
+                    rejected.push(RejectedTransaction {
+                        transaction_hash: hash,
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            touched.insert(from);
+            touched.extend(to);
+            let (status, output, contract_address) = match &result {
+                ExecutionResult::Success { output, .. } => match output {
+                    Output::Call(data) => (true, data.clone(), None),
+                    Output::Create(data, address) => {
+                        (true, data.clone(), address.map(from_revm_address))
+                    }
+                },
+                ExecutionResult::Revert { output, .. } => (false, output.clone(), None),
+                ExecutionResult::Halt { .. } => (false, Default::default(), None),
+            };
+            // Report the address the contract would have been deployed at even if the deployment
+            // failed, like Ethereum receipts do.
+            let nonce = tx.transaction().nonce().cloned().unwrap_or_default();
+            let contract_address = contract_address
+                .or_else(|| to.is_none().then(|| get_contract_address(from, nonce)));
+            touched.extend(contract_address);
+
+            gas_used += result.gas_used();
+            receipts.push(Receipt {
+                transaction_hash: hash,
+                from,
+                to,
+                contract_address,
+                status,
+                gas_used: result.gas_used(),
+                cumulative_gas_used: gas_used,
+                logs: result.logs().iter().map(from_revm_log).collect(),
+                output: output.to_vec().into(),
+            });
+        }
+        drop(evm);
+
+        touched.insert(coinbase);
+        let state = touched
+            .into_iter()
+            .map(|address| (address, self.account(address)))
+            .collect();
+        ExecutedBlock {
+            number,
+            timestamp,
+            gas_used,
+            receipts,
+            rejected,
+            state,
+        }
+    }
+
+    /// Execute a batch of Polygon zkEVM-encoded transactions on top of the current state.
+    pub fn execute_batch(
+        &mut self,
+        batch: &BatchInput,
+    ) -> Result<ExecutedBlock, DecodeTransactionsError> {
+        let transactions = decode_transactions(&batch.transactions)?;
+        Ok(self.execute_block(batch.timestamp, transactions))
+    }
+
+    /// The current state of an account.
+    pub fn account(&self, address: Address) -> AccountState {
+        // The in-memory database never fails.
+        let info = self
+            .db
+            .basic_ref(to_revm_address(address))
+            .unwrap()
+            .unwrap_or_default();
+        AccountState {
+            balance: from_revm_u256(info.balance),
+            nonce: info.nonce,
+        }
+    }
+
+    /// The current balance of an account.
+    pub fn balance(&self, address: Address) -> U256 {
+        self.account(address).balance
+    }
+
+    /// The current nonce of an account.
+    pub fn nonce(&self, address: Address) -> u64 {
+        self.account(address).nonce
+    }
+
+    /// The current value of a storage slot.
+    pub fn storage(&self, address: Address, slot: H256) -> H256 {
+        let value = self
+            .db
+            .storage_ref(
+                to_revm_address(address),
+                primitives::U256::from_be_bytes(slot.0),
+            )
+            .unwrap();
+        H256(value.to_be_bytes())
+    }
+
+    /// The current code of an account.
+    pub fn code(&self, address: Address) -> Bytes {
+        let info = self
+            .db
+            .basic_ref(to_revm_address(address))
+            .unwrap()
+            .unwrap_or_default();
+        match info.code {
+            Some(code) => code.original_bytes().to_vec().into(),
+            None => self
+                .db
+                .code_by_hash_ref(info.code_hash)
+                .unwrap()
+                .original_bytes()
+                .to_vec()
+                .into(),
+        }
+    }
+}
+
+fn tx_env(tx: &TypedTransaction, from: Address) -> TxEnv {
+    let transact_to = match tx.to() {
+        Some(NameOrAddress::Address(to)) => TransactTo::Call(to_revm_address(*to)),
+        // ENS names are never part of a signed transaction.
+        Some(NameOrAddress::Name(_)) | None => TransactTo::Create(CreateScheme::Create),
+    };
+    let gas_priority_fee = match tx {
+        TypedTransaction::Eip1559(tx) => tx.max_priority_fee_per_gas.map(to_revm_u256),
+        _ => None,
+    };
+    let access_list = tx
+        .access_list()
+        .map(|list| {
+            list.0
+                .iter()
+                .map(|item| {
+                    (
+                        to_revm_address(item.address),
+                        item.storage_keys
+                            .iter()
+                            .map(|key| primitives::U256::from_be_bytes(key.0))
+                            .collect(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    TxEnv {
+        caller: to_revm_address(from),
+        gas_limit: tx.gas().map(|gas| gas.low_u64()).unwrap_or_default(),
+        gas_price: to_revm_u256(tx.gas_price().unwrap_or_default()),
+        gas_priority_fee,
+        transact_to,
+        value: to_revm_u256(tx.value().cloned().unwrap_or_default()),
+        data: tx
+            .data()
+            .map(|data| data.0.clone())
+            .unwrap_or_default()
+            .into(),
+        nonce: tx.nonce().map(|nonce| nonce.low_u64()),
+        chain_id: tx.chain_id().map(|id| id.as_u64()),
+        access_list,
+        ..Default::default()
+    }
+}
+
+fn to_revm_address(address: Address) -> primitives::Address {
+    primitives::Address::from(address.0)
+}
+
+fn from_revm_address(address: primitives::Address) -> Address {
+    Address::from_slice(address.as_slice())
+}
+
+fn to_revm_u256(value: U256) -> primitives::U256 {
+    primitives::U256::from_limbs(value.0)
+}
+
+fn from_revm_u256(value: primitives::U256) -> U256 {
+    U256(value.into_limbs())
+}
+
+fn from_revm_log(log: &primitives::Log) -> Log {
+    Log {
+        address: from_revm_address(log.address),
+        topics: log
+            .topics()
+            .iter()
+            .map(|topic: &B256| H256(topic.0))
+            .collect(),
+        data: log.data.data.to_vec().into(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{
+        prelude::{LocalWallet, Signer, TransactionRequest},
+        utils::parse_ether,
+    };
+
+    #[async_std::test]
+    async fn test_executor_transfers() {
+        let chain_id = 1001;
+        let alice = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(chain_id);
+        let bob = Address::random();
+        let coinbase = Address::random();
+        let genesis = [(
+            alice.address(),
+            GenesisAccount {
+                balance: parse_ether(1).unwrap(),
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect();
+        let mut executor = Executor::new(chain_id, &genesis)
+            .unwrap()
+            .with_coinbase(coinbase);
+
+        let sign = |tx: TypedTransaction| {
+            let alice = alice.clone();
+            async move {
+                let sig = alice.sign_transaction(&tx).await.unwrap();
+                EvmTransaction::new(tx, sig)
+            }
+        };
+        let transfer = |nonce: u64| -> TypedTransaction {
+            TransactionRequest::pay(bob, 100)
+                .nonce(nonce)
+                .gas(21000)
+                .gas_price(1)
+                .chain_id(chain_id)
+                .into()
+        };
+
+        // A valid transfer, and a replay of the same nonce which must be rejected.
+        let block =
+            executor.execute_block(1, vec![sign(transfer(0)).await, sign(transfer(0)).await]);
+        assert_eq!(block.number, 1);
+        assert_eq!(block.receipts.len(), 1);
+        assert!(block.receipts[0].status);
+        assert_eq!(block.receipts[0].gas_used, 21000);
+        assert_eq!(block.rejected.len(), 1);
+        assert_eq!(block.state[&bob].balance, 100.into());
+        assert_eq!(block.state[&coinbase].balance, 21000.into());
+        assert_eq!(
+            block.state[&alice.address()],
+            AccountState {
+                balance: parse_ether(1).unwrap() - 100 - 21000,
+                nonce: 1,
+            }
+        );
+
+        // State carries over to the next block.
+        let block = executor.execute_block(2, vec![sign(transfer(1)).await]);
+        assert_eq!(block.number, 2);
+        assert_eq!(block.receipts.len(), 1);
+        assert_eq!(executor.balance(bob), 200.into());
+        assert_eq!(executor.nonce(alice.address()), 2);
+    }
+
+    /// Runtime code of a contract which stores the first word of the calldata in slot 0 and emits
+    /// it in a log with topic 0x2a, or reverts if the word is zero.
+    const STORE_RUNTIME: &str = "600035801560175780600055600052602a60206000a1005b60006000fd";
+
+    /// Init code deploying [STORE_RUNTIME].
+    const STORE_INIT: &str = "601d80600b6000396000f3";
+
+    #[async_std::test]
+    async fn test_executor_contracts() {
+        let chain_id = 1001;
+        let alice = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(chain_id);
+        let genesis = [(
+            alice.address(),
+            GenesisAccount {
+                balance: parse_ether(1).unwrap(),
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect();
+        let mut executor = Executor::new(chain_id, &genesis).unwrap();
+
+        let sign = |tx: TypedTransaction| {
+            let alice = alice.clone();
+            async move {
+                let sig = alice.sign_transaction(&tx).await.unwrap();
+                EvmTransaction::new(tx, sig)
+            }
+        };
+        let tx = |to: Option<Address>, nonce: u64, data: Bytes| -> TypedTransaction {
+            let tx = TransactionRequest::new()
+                .data(data)
+                .nonce(nonce)
+                .gas(100_000)
+                .gas_price(1)
+                .chain_id(chain_id);
+            match to {
+                Some(to) => tx.to(to).into(),
+                None => tx.into(),
+            }
+        };
+        let word = |value: u64| -> Bytes {
+            let mut word = [0; 32];
+            U256::from(value).to_big_endian(&mut word);
+            word.to_vec().into()
+        };
+
+        // Deploy the contract.
+        let code: Bytes = format!("0x{STORE_INIT}{STORE_RUNTIME}").parse().unwrap();
+        let block = executor.execute_block(1, vec![sign(tx(None, 0, code)).await]);
+        assert!(block.rejected.is_empty());
+        let receipt = &block.receipts[0];
+        assert!(receipt.status);
+        let contract = get_contract_address(alice.address(), 0);
+        assert_eq!(receipt.contract_address, Some(contract));
+        assert_eq!(
+            executor.code(contract),
+            STORE_RUNTIME.parse::<Bytes>().unwrap()
+        );
+
+        // A call changes the state of the contract and emits a log.
+        let block = executor.execute_block(2, vec![sign(tx(Some(contract), 1, word(7))).await]);
+        let receipt = &block.receipts[0];
+        assert!(receipt.status);
+        assert_eq!(receipt.to, Some(contract));
+        assert_eq!(
+            executor.storage(contract, H256::zero()),
+            H256::from_low_u64_be(7)
+        );
+        assert_eq!(receipt.logs.len(), 1);
+        assert_eq!(receipt.logs[0].address, contract);
+        assert_eq!(receipt.logs[0].topics, [H256::from_low_u64_be(0x2a)]);
+        assert_eq!(receipt.logs[0].data, word(7));
+
+        // A reverted call is included and pays for gas, but has no effect on the contract.
+        let balance = executor.balance(alice.address());
+        let block = executor.execute_block(3, vec![sign(tx(Some(contract), 2, word(0))).await]);
+        assert!(block.rejected.is_empty());
+        let receipt = &block.receipts[0];
+        assert!(!receipt.status);
+        assert!(receipt.logs.is_empty());
+        assert_eq!(
+            executor.storage(contract, H256::zero()),
+            H256::from_low_u64_be(7)
+        );
+        assert_eq!(executor.nonce(alice.address()), 3);
+        assert_eq!(
+            executor.balance(alice.address()),
+            balance - receipt.gas_used
+        );
+    }
+}
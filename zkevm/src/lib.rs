@@ -7,9 +7,13 @@
 
 use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction, utils::rlp::Rlp};
 use jf_primitives::merkle_tree::namespaced_merkle_tree::NamespaceProof;
-use sequencer::{Block, Vm, VmId, VmTransaction};
+use sequencer::{Block, NamespaceProofType, Transaction, Vm, VmId, VmTransaction};
+use snafu::Snafu;
 
+#[cfg(feature = "executor")]
+pub mod executor;
 pub mod polygon_zkevm;
+pub mod verified_batches;
 
 #[derive(Clone, Debug)]
 pub struct EvmTransaction {
@@ -37,6 +41,16 @@ impl EvmTransaction {
         self.sig
     }
 
+    /// The unsigned transaction.
+    pub fn transaction(&self) -> &TypedTransaction {
+        &self.tx
+    }
+
+    /// Recover the address of the account which signed this transaction.
+    pub fn recover_sender(&self) -> Result<Address, SignatureError> {
+        self.sig.recover(self.tx.sighash())
+    }
+
     pub fn rlp_base(&self) -> Bytes {
         self.tx.rlp()
     }
@@ -63,8 +77,37 @@ impl Vm for ZkEvm {
     }
 }
 
+/// A namespace proof for a zkEVM namespace failed to verify.
+#[derive(Clone, Debug, Snafu)]
+#[snafu(display("invalid namespace proof for VM {vm}: {msg}"))]
+pub struct InvalidNamespaceProof {
+    pub vm: u64,
+    pub msg: String,
+}
+
+/// A transaction in a zkEVM namespace could not be decoded as an EVM transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Snafu)]
+#[snafu(display("transaction {index} in namespace of VM {vm} is not a valid EVM transaction"))]
+pub struct InvalidTransaction {
+    pub vm: u64,
+    /// The position of the invalid transaction within the namespace.
+    pub index: usize,
+}
+
+/// The decoded transactions of a zkEVM namespace.
+#[derive(Clone, Debug, Default)]
+pub struct DecodedNamespace {
+    /// The transactions which could be decoded, in namespace order.
+    pub transactions: Vec<EvmTransaction>,
+    /// The transactions which could not be decoded, and were skipped.
+    pub invalid: Vec<InvalidTransaction>,
+}
+
 impl ZkEvm {
     /// Extract the VM transactions from a block.
+    ///
+    /// This trusts the namespace proof computed from `block` without verifying it. Clients which
+    /// do not trust the source of the block should use [Self::verified_vm_transactions] instead.
     pub fn vm_transactions(&self, block: &Block) -> Vec<<Self as Vm>::Transaction> {
         let proof = block.get_namespace_proof(self.id());
         let transactions = proof.get_namespace_leaves();
@@ -74,4 +117,193 @@ impl ZkEvm {
             .flat_map(|txn| txn.as_vm(self))
             .collect()
     }
+
+    /// Extract the namespace of this VM from a block, together with its namespace proof.
+    ///
+    /// The proof can be sent to a client along with the block's namespace Merkle root, so that the
+    /// client can check the namespace with [Self::verify_namespace] without downloading the whole
+    /// block.
+    pub fn namespace_with_proof(&self, block: &Block) -> (Vec<Transaction>, NamespaceProofType) {
+        let proof = block.get_namespace_proof(self.id());
+        let transactions = proof.get_namespace_leaves().into_iter().cloned().collect();
+        (transactions, proof)
+    }
+
+    /// Verify a namespace proof against a block's namespace Merkle root.
+    ///
+    /// On success, returns the raw transactions in this VM's namespace.
+    pub fn verify_namespace(
+        &self,
+        proof: &NamespaceProofType,
+        root: &<NamespaceProofType as NamespaceProof>::Node,
+    ) -> Result<Vec<Transaction>, InvalidNamespaceProof> {
+        proof
+            .verify(root, self.id())
+            .map_err(|err| InvalidNamespaceProof {
+                vm: self.chain_id,
+                msg: err.to_string(),
+            })?;
+        Ok(proof.get_namespace_leaves().into_iter().cloned().collect())
+    }
+
+    /// Decode the raw transactions in this VM's namespace.
+    ///
+    /// Anyone can submit arbitrary bytes to a namespace, so a malformed transaction must not
+    /// prevent the rest of the namespace from being executed. Transactions that cannot be decoded
+    /// are skipped, like in [Self::vm_transactions], and reported in [DecodedNamespace::invalid].
+    pub fn decode_namespace(&self, transactions: &[Transaction]) -> DecodedNamespace {
+        let mut decoded = DecodedNamespace::default();
+        for (index, txn) in transactions.iter().enumerate() {
+            match txn.as_vm(self) {
+                Some(tx) => decoded.transactions.push(tx),
+                None => decoded.invalid.push(InvalidTransaction {
+                    vm: self.chain_id,
+                    index,
+                }),
+            }
+        }
+        decoded
+    }
+
+    /// Verify a namespace proof and decode the transactions in the namespace.
+    ///
+    /// Fails only if the proof is invalid. Transactions that cannot be decoded are reported in
+    /// [DecodedNamespace::invalid].
+    pub fn verified_vm_transactions(
+        &self,
+        proof: &NamespaceProofType,
+        root: &<NamespaceProofType as NamespaceProof>::Node,
+    ) -> Result<DecodedNamespace, InvalidNamespaceProof> {
+        let transactions = self.verify_namespace(proof, root)?;
+        Ok(self.decode_namespace(&transactions))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jf_primitives::merkle_tree::{
+        namespaced_merkle_tree::NamespacedMerkleTreeScheme, MerkleCommitment, MerkleTreeScheme,
+    };
+    use sequencer::TransactionNMT;
+
+    const ZKEVM: ZkEvm = ZkEvm { chain_id: 1001 };
+    const OTHER: ZkEvm = ZkEvm { chain_id: 1002 };
+
+    async fn evm_transaction(nonce: u64) -> Transaction {
+        let signer = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap()
+            .with_chain_id(ZKEVM.chain_id);
+        let tx: TypedTransaction = TransactionRequest::pay(Address::random(), 1)
+            .nonce(nonce)
+            .gas(21000)
+            .gas_price(1)
+            .chain_id(ZKEVM.chain_id)
+            .into();
+        let sig = signer.sign_transaction(&tx).await.unwrap();
+        Transaction::new(ZKEVM.id(), EvmTransaction::new(tx, sig).encode())
+    }
+
+    /// Build a namespace Merkle tree over `transactions`, returning its root and the proof of the
+    /// zkEVM namespace.
+    fn prove(
+        transactions: &[Transaction],
+    ) -> (
+        <NamespaceProofType as NamespaceProof>::Node,
+        NamespaceProofType,
+    ) {
+        let nmt = TransactionNMT::from_elems(32, transactions).unwrap();
+        (
+            nmt.commitment().digest(),
+            nmt.get_namespace_proof(ZKEVM.id()),
+        )
+    }
+
+    #[async_std::test]
+    async fn test_decode_namespace_reports_invalid_transactions() {
+        let valid = [evm_transaction(0).await, evm_transaction(1).await];
+        let transactions = [
+            valid[0].clone(),
+            Transaction::new(ZKEVM.id(), vec![0xde, 0xad, 0xbe, 0xef]),
+            valid[1].clone(),
+        ];
+        let decoded = ZKEVM.decode_namespace(&transactions);
+        assert_eq!(
+            decoded
+                .transactions
+                .iter()
+                .map(|tx| tx.encode())
+                .collect::<Vec<_>>(),
+            valid
+                .iter()
+                .map(|tx| tx.payload().to_vec())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            decoded.invalid,
+            [InvalidTransaction {
+                vm: ZKEVM.chain_id,
+                index: 1
+            }]
+        );
+
+        // A valid proof of a namespace with an undecodable transaction still verifies, and reports
+        // the transaction instead of failing.
+        let (root, proof) = prove(&transactions);
+        let decoded = ZKEVM.verified_vm_transactions(&proof, &root).unwrap();
+        assert_eq!(decoded.transactions.len(), 2);
+        assert_eq!(decoded.invalid.len(), 1);
+        assert_eq!(decoded.invalid[0].index, 1);
+    }
+
+    #[async_std::test]
+    async fn test_namespace_proof_round_trip() {
+        let transactions = [
+            evm_transaction(0).await,
+            Transaction::new(OTHER.id(), vec![1, 2, 3]),
+            evm_transaction(1).await,
+        ];
+        let (root, proof) = prove(&transactions);
+
+        // The proof survives serialization, as it would when sent to a client.
+        let proof: NamespaceProofType =
+            serde_json::from_str(&serde_json::to_string(&proof).unwrap()).unwrap();
+        let namespace = ZKEVM.verify_namespace(&proof, &root).unwrap();
+        assert_eq!(
+            namespace,
+            [transactions[0].clone(), transactions[2].clone()]
+        );
+        let decoded = ZKEVM.verified_vm_transactions(&proof, &root).unwrap();
+        assert_eq!(decoded.transactions.len(), 2);
+        assert!(decoded.invalid.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_namespace_proof_wrong_namespace() {
+        let transactions = [
+            evm_transaction(0).await,
+            Transaction::new(OTHER.id(), vec![1, 2, 3]),
+        ];
+        let (root, proof) = prove(&transactions);
+
+        // A proof of the zkEVM namespace does not verify as a proof of another VM's namespace.
+        let err = OTHER.verify_namespace(&proof, &root).unwrap_err();
+        assert_eq!(err.vm, OTHER.chain_id);
+    }
+
+    #[async_std::test]
+    async fn test_namespace_proof_tampered() {
+        let transactions = [evm_transaction(0).await, evm_transaction(1).await];
+        let (_, proof) = prove(&transactions);
+
+        // Replacing a transaction in the namespace changes the root, so the original proof does
+        // not verify against the root of the tampered tree, and vice versa.
+        let tampered = [transactions[0].clone(), evm_transaction(2).await];
+        let (tampered_root, tampered_proof) = prove(&tampered);
+        ZKEVM.verify_namespace(&proof, &tampered_root).unwrap_err();
+
+        let (root, _) = prove(&transactions);
+        ZKEVM.verify_namespace(&tampered_proof, &root).unwrap_err();
+    }
 }
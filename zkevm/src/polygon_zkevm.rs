@@ -6,7 +6,16 @@
 // You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use crate::EvmTransaction;
-use ethers::prelude::*;
+use ethers::{
+    abi::{encode_packed, Token},
+    prelude::*,
+    types::transaction::eip2718::TypedTransaction,
+    utils::{
+        keccak256,
+        rlp::{Decodable, DecoderError, Rlp},
+    },
+};
+use snafu::{ResultExt, Snafu};
 use std::borrow::Borrow;
 
 /// Encode transactions as expected by Polygon zkEVM.
@@ -51,3 +60,271 @@ pub fn encode_transactions<T: Borrow<EvmTransaction>>(txs: impl IntoIterator<Ite
         .collect::<Vec<u8>>()
         .into()
 }
+
+/// Error decoding a batch of Polygon zkEVM-encoded transactions.
+#[derive(Debug, Snafu)]
+pub enum DecodeTransactionsError {
+    #[snafu(display("transaction at offset {offset}: invalid RLP: {source}"))]
+    Rlp { offset: usize, source: DecoderError },
+    #[snafu(display("transaction at offset {offset}: truncated signature"))]
+    TruncatedSignature { offset: usize },
+    #[snafu(display("transaction at offset {offset}: invalid signature parity {v}"))]
+    InvalidParity { offset: usize, v: u8 },
+}
+
+/// Decode transactions encoded as expected by Polygon zkEVM.
+///
+/// This is the inverse of [encode_transactions]. Each transaction is the RLP encoding of the
+/// unsigned transaction followed by 65 bytes of signature: `r` and `s` (32 bytes each) and the
+/// y-parity normalized to 27 or 28. The signature is converted back to the normalization scheme
+/// used by ethers.rs, so that the hashes of the decoded transactions match the hashes of the
+/// original ones.
+pub fn decode_transactions(bytes: &[u8]) -> Result<Vec<EvmTransaction>, DecodeTransactionsError> {
+    let mut txs = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+
+        // Typed transactions are prefixed with a single type byte, which is not part of the RLP
+        // list.
+        let type_len = if rest[0] < 0xc0 { 1 } else { 0 };
+        let rlp_len = Rlp::new(&rest[type_len..])
+            .payload_info()
+            .context(RlpSnafu { offset })?
+            .total();
+        let tx_len = type_len + rlp_len;
+        let tx =
+            TypedTransaction::decode(&Rlp::new(&rest[..tx_len])).context(RlpSnafu { offset })?;
+
+        let sig_bytes = rest
+            .get(tx_len..tx_len + 65)
+            .ok_or(DecodeTransactionsError::TruncatedSignature { offset })?;
+        let r = U256::from_big_endian(&sig_bytes[0..32]);
+        let s = U256::from_big_endian(&sig_bytes[32..64]);
+        let parity = match sig_bytes[64] {
+            v @ (27 | 28) => (v - 27) as u64,
+            v => return Err(DecodeTransactionsError::InvalidParity { offset, v }),
+        };
+        // Undo the normalization performed in [encode_transactions].
+        let v = match &tx {
+            TypedTransaction::Legacy(req) => match req.chain_id {
+                // EIP-155 replay-protected signature.
+                Some(chain_id) => parity + 35 + 2 * chain_id.as_u64(),
+                None => parity + 27,
+            },
+            _ => parity,
+        };
+
+        txs.push(EvmTransaction::new(tx, Signature { r, s, v }));
+        offset += tx_len + 65;
+    }
+    Ok(txs)
+}
+
+/// The inputs of a single batch which are committed to by the accumulated input hash.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchInput {
+    /// Transactions in the batch, encoded with [encode_transactions].
+    pub transactions: Bytes,
+    /// Global exit root used when executing the batch.
+    pub global_exit_root: H256,
+    /// Timestamp of the batch.
+    pub timestamp: u64,
+}
+
+impl BatchInput {
+    /// The hash of the L2 data in this batch.
+    pub fn l2_data_hash(&self) -> H256 {
+        batch_l2_data_hash(&self.transactions)
+    }
+}
+
+/// Compute the hash of the L2 data in a batch.
+///
+/// This is the `keccak256(transactions)` value which `PolygonZkEVM` folds into the accumulated
+/// input hash for each batch, where `transactions` is the batch encoded with
+/// [encode_transactions].
+pub fn batch_l2_data_hash(transactions: impl AsRef<[u8]>) -> H256 {
+    keccak256(transactions).into()
+}
+
+/// Compute the accumulated input hash of a batch.
+///
+/// This is the computation of upstream `PolygonZkEVM.sequenceBatches`:
+/// ```solidity
+/// keccak256(abi.encodePacked(
+///     oldAccInputHash,
+///     batchL2DataHash,
+///     globalExitRoot,
+///     timestamp,
+///     sequencer
+/// ))
+/// ```
+///
+/// The Espresso fork of `PolygonZkEVM` does not compute this hash itself. Batches are sequenced by
+/// HotShot, and `verifyBatches` takes the new accumulated input hash from the aggregator in
+/// `PackedHotShotParams`, so the hash is whatever the zkevm-node and its prover compute. They use
+/// the formula above, with the address the node sequences batches with as `sequencer`. Nothing on
+/// L1 fixes this address: in the demo deployment it is the trusted sequencer of the rollup
+/// contract, which the end-to-end test checks against the hashes stored on L1.
+pub fn acc_input_hash(
+    old_acc_input_hash: H256,
+    batch_l2_data_hash: H256,
+    global_exit_root: H256,
+    timestamp: u64,
+    sequencer: Address,
+) -> H256 {
+    // `encode_packed` only fails for nested arrays and tuples, which we never pass it.
+    let packed = encode_packed(&[
+        Token::FixedBytes(old_acc_input_hash.as_bytes().to_vec()),
+        Token::FixedBytes(batch_l2_data_hash.as_bytes().to_vec()),
+        Token::FixedBytes(global_exit_root.as_bytes().to_vec()),
+        // `encodePacked` uses the natural width of the type, which is 8 bytes for `uint64`.
+        Token::FixedBytes(timestamp.to_be_bytes().to_vec()),
+        Token::Address(sequencer),
+    ])
+    .unwrap();
+    keccak256(packed).into()
+}
+
+/// Compute the chain of accumulated input hashes for a sequence of batches.
+///
+/// Starting from `old_acc_input_hash` (the accumulated input hash of the batch preceding the first
+/// batch in `batches`), this folds each batch into the hash chain and returns the accumulated input
+/// hash after each batch. The last element of the result is the value which `PolygonZkEVM` stores
+/// in `sequencedBatches(n).accInputHash` for the last batch `n`.
+pub fn acc_input_hash_chain<B: Borrow<BatchInput>>(
+    old_acc_input_hash: H256,
+    batches: impl IntoIterator<Item = B>,
+    sequencer: Address,
+) -> Vec<H256> {
+    batches
+        .into_iter()
+        .scan(old_acc_input_hash, |acc, batch| {
+            let batch = batch.borrow();
+            *acc = acc_input_hash(
+                *acc,
+                batch.l2_data_hash(),
+                batch.global_exit_root,
+                batch.timestamp,
+                sequencer,
+            );
+            Some(*acc)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::transaction::eip2930::{AccessList, Eip2930TransactionRequest};
+
+    #[async_std::test]
+    async fn test_encode_decode_transactions() {
+        let signer = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+            .parse::<LocalWallet>()
+            .unwrap();
+        let to = Address::random();
+
+        let txs: Vec<TypedTransaction> = vec![
+            // Legacy transaction without replay protection.
+            TransactionRequest::pay(to, 1)
+                .nonce(0)
+                .gas(21000)
+                .gas_price(1)
+                .into(),
+            // Legacy transaction with EIP-155 replay protection.
+            TransactionRequest::pay(to, 2)
+                .nonce(1)
+                .gas(21000)
+                .gas_price(1)
+                .chain_id(1001)
+                .into(),
+            Eip2930TransactionRequest::new(
+                TransactionRequest::pay(to, 3)
+                    .nonce(2)
+                    .gas(21000)
+                    .gas_price(1)
+                    .chain_id(1001),
+                AccessList::default(),
+            )
+            .into(),
+            Eip1559TransactionRequest::new()
+                .to(to)
+                .value(4)
+                .nonce(3)
+                .gas(21000)
+                .max_fee_per_gas(2)
+                .max_priority_fee_per_gas(1)
+                .data(vec![1, 2, 3])
+                .chain_id(1001)
+                .into(),
+        ];
+        let mut signed = vec![];
+        for tx in txs {
+            let sig = signer.sign_transaction(&tx).await.unwrap();
+            signed.push(EvmTransaction::new(tx, sig));
+        }
+
+        let decoded = decode_transactions(&encode_transactions(&signed)).unwrap();
+        assert_eq!(
+            decoded.iter().map(EvmTransaction::hash).collect::<Vec<_>>(),
+            signed.iter().map(EvmTransaction::hash).collect::<Vec<_>>()
+        );
+
+        // Truncated input is an error, not a panic.
+        let encoded = encode_transactions(&signed[..1]);
+        decode_transactions(&encoded[..encoded.len() - 1]).unwrap_err();
+    }
+
+    #[test]
+    fn test_acc_input_hash_chain() {
+        // Expected values computed independently of ethers, from the packed encoding in the
+        // documentation of [acc_input_hash].
+        let sequencer = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap();
+        let batches = [
+            BatchInput {
+                transactions: vec![0xde, 0xad, 0xbe, 0xef].into(),
+                global_exit_root: H256::repeat_byte(0x22),
+                timestamp: 1700000000,
+            },
+            BatchInput {
+                transactions: Bytes::new(),
+                global_exit_root: H256::zero(),
+                timestamp: 1700000001,
+            },
+        ];
+        assert_eq!(
+            batches[0].l2_data_hash(),
+            "0xd4fd4e189132273036449fc9e11198c739161b4c0116a9a2dccdfa1c492006f1"
+                .parse()
+                .unwrap()
+        );
+        // The hash of no transactions is the hash of the empty string.
+        assert_eq!(
+            batches[1].l2_data_hash(),
+            "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            acc_input_hash_chain(H256::repeat_byte(0x11), &batches, sequencer),
+            [
+                "0x2bf20d7007ad283e9ee2a229e6676e0c16a22f6fb96c24361b9c9c04dac41692"
+                    .parse::<H256>()
+                    .unwrap(),
+                "0xffdd391bf467e9dbbb99c7c8eabbb8d9c2923ac94ff61f2d12e8e8b5c7c74a52"
+                    .parse()
+                    .unwrap(),
+            ]
+        );
+
+        // The sequencer is part of the hash.
+        assert_ne!(
+            acc_input_hash_chain(H256::repeat_byte(0x11), &batches, Address::zero()),
+            acc_input_hash_chain(H256::repeat_byte(0x11), &batches, sequencer)
+        );
+    }
+}
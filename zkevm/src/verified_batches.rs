@@ -0,0 +1,332 @@
+// Copyright (c) 2023 Espresso Systems (espressosys.com)
+// This file is part of the Espresso Sequencer-Polygon zkEVM integration demo.
+//
+// This program is free software: you can redistribute it and/or modify it under the terms of the GNU Affero General Public License as published by the Free Software Foundation, either version 3 of the License, or any later version.
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU Affero General Public License for more details.
+// You should have received a copy of the GNU Affero General Public License along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding of batches verified on L1.
+//!
+//! Unlike upstream Polygon zkEVM, the Espresso fork of `PolygonZkEVM` has no `sequenceBatches`
+//! function: batches are sequenced by HotShot, and their contents are never posted to L1 as
+//! calldata. They reach L1 only through the accumulated input hash. The aggregator proves a range
+//! of batches with `verifyBatches` or `verifyBatchesTrustedAggregator`, passing the accumulated
+//! input hash before and after the range in `PackedHotShotParams`, together with a proof that the
+//! range matches the block commitments in the HotShot contract.
+//!
+//! This module finds those transactions and decodes their calldata with the fork's bindings. Given
+//! the inputs of the batches in a range, for example from the blocks served by the query service
+//! adaptor, [VerifiedBatches::check] recomputes the accumulated input hash and compares it with the
+//! one verified on L1.
+
+use crate::polygon_zkevm::{acc_input_hash_chain, BatchInput};
+use ethers::{abi::AbiDecode, contract::EthEvent, prelude::*};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{borrow::Borrow, collections::HashSet, ops::RangeInclusive};
+use zkevm_contract_bindings::polygon_zk_evm::{
+    PolygonZkEVMCalls, VerifyBatchesFilter, VerifyBatchesTrustedAggregatorFilter,
+};
+
+/// A range of batches verified on L1 by a single transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedBatches {
+    /// The L1 block containing the verifying transaction.
+    pub l1_block: u64,
+    /// The hash of the verifying transaction.
+    pub l1_transaction: H256,
+    /// Whether the range was verified by `verifyBatchesTrustedAggregator`.
+    pub trusted_aggregator: bool,
+    pub pending_state_num: u64,
+    /// The last batch verified before this range.
+    pub init_num_batch: u64,
+    /// The last batch in this range.
+    pub final_new_batch: u64,
+    pub new_local_exit_root: H256,
+    pub new_state_root: H256,
+    /// The accumulated input hash of batch `init_num_batch`.
+    pub old_acc_input_hash: H256,
+    /// The accumulated input hash of batch `final_new_batch`.
+    pub new_acc_input_hash: H256,
+}
+
+impl VerifiedBatches {
+    /// The numbers of the batches in this range.
+    pub fn batch_numbers(&self) -> RangeInclusive<u64> {
+        self.init_num_batch + 1..=self.final_new_batch
+    }
+
+    /// Check the inputs of the batches in this range against the accumulated input hash verified
+    /// on L1.
+    ///
+    /// `batches` are the inputs of the batches in [Self::batch_numbers], in order. See
+    /// [acc_input_hash](crate::polygon_zkevm::acc_input_hash) for `sequencer`. On success, returns
+    /// the accumulated input hash after each batch.
+    pub fn check<B: Borrow<BatchInput>>(
+        &self,
+        batches: impl IntoIterator<Item = B>,
+        sequencer: Address,
+    ) -> Result<Vec<H256>, VerifiedBatchesError> {
+        let hashes = acc_input_hash_chain(self.old_acc_input_hash, batches, sequencer);
+        let expected = self.final_new_batch.saturating_sub(self.init_num_batch);
+        if hashes.len() as u64 != expected {
+            return Err(VerifiedBatchesError::BatchCount {
+                expected,
+                actual: hashes.len(),
+            });
+        }
+        match hashes.last() {
+            Some(actual) if *actual != self.new_acc_input_hash => {
+                Err(VerifiedBatchesError::AccInputHash {
+                    batch: self.final_new_batch,
+                    expected: self.new_acc_input_hash,
+                    actual: *actual,
+                })
+            }
+            _ => Ok(hashes),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum VerifiedBatchesError {
+    #[snafu(display("error calling L1 provider: {msg}"))]
+    Provider { msg: String },
+    #[snafu(display("L1 transaction {hash:?} not found"))]
+    TransactionNotFound { hash: H256 },
+    #[snafu(display("L1 transaction {hash:?} is not mined"))]
+    TransactionNotMined { hash: H256 },
+    #[snafu(display("L1 transaction {hash:?} does not verify any batches"))]
+    NotVerifyingTransaction { hash: H256 },
+    #[snafu(display("L1 transaction {hash:?} has invalid calldata: {source}"))]
+    Calldata {
+        hash: H256,
+        source: ethers::abi::AbiError,
+    },
+    #[snafu(display("expected inputs of {expected} batches, got {actual}"))]
+    BatchCount { expected: u64, actual: usize },
+    #[snafu(display(
+        "accumulated input hash of batch {batch} is {actual:?}, but L1 verified {expected:?}"
+    ))]
+    AccInputHash {
+        batch: u64,
+        expected: H256,
+        actual: H256,
+    },
+}
+
+impl VerifiedBatchesError {
+    fn provider(err: impl std::fmt::Display) -> Self {
+        Self::Provider {
+            msg: err.to_string(),
+        }
+    }
+}
+
+/// Decode the batches verified by a single L1 transaction.
+pub async fn verified_batches_in_transaction<M: Middleware>(
+    l1: &M,
+    hash: H256,
+) -> Result<VerifiedBatches, VerifiedBatchesError> {
+    let tx = l1
+        .get_transaction(hash)
+        .await
+        .map_err(VerifiedBatchesError::provider)?
+        .ok_or(VerifiedBatchesError::TransactionNotFound { hash })?;
+    let l1_block = tx
+        .block_number
+        .ok_or(VerifiedBatchesError::TransactionNotMined { hash })?;
+    decode_verification_calldata(&tx.input, hash, l1_block.as_u64())
+}
+
+/// Decode the batches verified by the rollup contract at `rollup` in a range of L1 blocks.
+///
+/// The result is ordered by batch number.
+pub async fn verified_batches_in_range<M: Middleware>(
+    l1: &M,
+    rollup: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<VerifiedBatches>, VerifiedBatchesError> {
+    let filter = Filter::new()
+        .address(rollup)
+        .from_block(from_block)
+        .to_block(to_block)
+        .topic0(vec![
+            VerifyBatchesFilter::signature(),
+            VerifyBatchesTrustedAggregatorFilter::signature(),
+        ]);
+    let logs = l1
+        .get_logs(&filter)
+        .await
+        .map_err(VerifiedBatchesError::provider)?;
+
+    let mut seen = HashSet::new();
+    let mut ranges = vec![];
+    for log in logs {
+        let Some(hash) = log.transaction_hash else {
+            continue;
+        };
+        if seen.insert(hash) {
+            ranges.push(verified_batches_in_transaction(l1, hash).await?);
+        }
+    }
+    ranges.sort_by_key(|range| range.final_new_batch);
+    Ok(ranges)
+}
+
+/// Decode the calldata of a `verifyBatches` or `verifyBatchesTrustedAggregator` transaction.
+pub fn decode_verification_calldata(
+    calldata: &[u8],
+    hash: H256,
+    l1_block: u64,
+) -> Result<VerifiedBatches, VerifiedBatchesError> {
+    let (
+        trusted_aggregator,
+        pending_state_num,
+        init_num_batch,
+        final_new_batch,
+        new_local_exit_root,
+        new_state_root,
+        params,
+    ) = match PolygonZkEVMCalls::decode(calldata).context(CalldataSnafu { hash })? {
+        PolygonZkEVMCalls::VerifyBatches(call) => (
+            false,
+            call.pending_state_num,
+            call.init_num_batch,
+            call.final_new_batch,
+            call.new_local_exit_root,
+            call.new_state_root,
+            call.packed_hot_shot_params,
+        ),
+        PolygonZkEVMCalls::VerifyBatchesTrustedAggregator(call) => (
+            true,
+            call.pending_state_num,
+            call.init_num_batch,
+            call.final_new_batch,
+            call.new_local_exit_root,
+            call.new_state_root,
+            call.packed_hot_shot_params,
+        ),
+        _ => return Err(VerifiedBatchesError::NotVerifyingTransaction { hash }),
+    };
+    Ok(VerifiedBatches {
+        l1_block,
+        l1_transaction: hash,
+        trusted_aggregator,
+        pending_state_num,
+        init_num_batch,
+        final_new_batch,
+        new_local_exit_root: new_local_exit_root.into(),
+        new_state_root: new_state_root.into(),
+        old_acc_input_hash: params.old_acc_input_hash.into(),
+        new_acc_input_hash: params.new_acc_input_hash.into(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Calldata of `verifyBatchesTrustedAggregator`, ABI encoded independently of the bindings.
+    ///
+    /// It verifies batches 6 and 7, whose inputs are the ones used in the test of
+    /// [acc_input_hash_chain].
+    const VERIFY_BATCHES_TRUSTED_AGGREGATOR: &str = concat!(
+        "590ae211",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000005",
+        "0000000000000000000000000000000000000000000000000000000000000007",
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "0000000000000000000000000000000000000000000000000000000000000003",
+        "0000000000000000000000000000000000000000000000000000000000000004",
+        "0000000000000000000000000000000000000000000000000000000000000005",
+        "0000000000000000000000000000000000000000000000000000000000000006",
+        "0000000000000000000000000000000000000000000000000000000000000007",
+        "0000000000000000000000000000000000000000000000000000000000000008",
+        "00000000000000000000000000000000000000000000000000000000000001c0",
+        "1111111111111111111111111111111111111111111111111111111111111111",
+        "ffdd391bf467e9dbbb99c7c8eabbb8d9c2923ac94ff61f2d12e8e8b5c7c74a52",
+        "0000000000000000000000000000000000000000000000000000000000000060",
+        "0000000000000000000000000000000000000000000000000000000000000003",
+        "c0ffee0000000000000000000000000000000000000000000000000000000000",
+    );
+
+    #[test]
+    fn test_decode_verification_calldata() {
+        let calldata = VERIFY_BATCHES_TRUSTED_AGGREGATOR.parse::<Bytes>().unwrap();
+        let hash = H256::random();
+        let verified = decode_verification_calldata(&calldata, hash, 100).unwrap();
+        assert_eq!(
+            verified,
+            VerifiedBatches {
+                l1_block: 100,
+                l1_transaction: hash,
+                trusted_aggregator: true,
+                pending_state_num: 0,
+                init_num_batch: 5,
+                final_new_batch: 7,
+                new_local_exit_root: H256::repeat_byte(0xaa),
+                new_state_root: H256::repeat_byte(0xbb),
+                old_acc_input_hash: H256::repeat_byte(0x11),
+                new_acc_input_hash:
+                    "0xffdd391bf467e9dbbb99c7c8eabbb8d9c2923ac94ff61f2d12e8e8b5c7c74a52"
+                        .parse()
+                        .unwrap(),
+            }
+        );
+        assert_eq!(verified.batch_numbers(), 6..=7);
+
+        let sequencer = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap();
+        let batches = [
+            BatchInput {
+                transactions: vec![0xde, 0xad, 0xbe, 0xef].into(),
+                global_exit_root: H256::repeat_byte(0x22),
+                timestamp: 1700000000,
+            },
+            BatchInput {
+                transactions: Bytes::new(),
+                global_exit_root: H256::zero(),
+                timestamp: 1700000001,
+            },
+        ];
+        verified.check(&batches, sequencer).unwrap();
+
+        // Different inputs do not match what L1 verified.
+        assert!(matches!(
+            verified.check(&batches[..1], sequencer),
+            Err(VerifiedBatchesError::BatchCount {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            verified.check(&batches, Address::zero()),
+            Err(VerifiedBatchesError::AccInputHash { batch: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_other_calldata() {
+        let hash = H256::random();
+        // `trustedSequencer()` is a call to the rollup contract, but does not verify batches.
+        assert!(matches!(
+            decode_verification_calldata(&[0xcf, 0xa8, 0xed, 0x47], hash, 0),
+            Err(VerifiedBatchesError::NotVerifyingTransaction { .. })
+        ));
+        assert!(matches!(
+            decode_verification_calldata(&[0, 1, 2, 3], hash, 0),
+            Err(VerifiedBatchesError::Calldata { .. })
+        ));
+        let calldata = VERIFY_BATCHES_TRUSTED_AGGREGATOR.parse::<Bytes>().unwrap();
+        assert!(matches!(
+            decode_verification_calldata(&calldata[..100], hash, 0),
+            Err(VerifiedBatchesError::Calldata { .. })
+        ));
+    }
+}